@@ -0,0 +1,79 @@
+//! Offline administration commands on the database.
+
+use anyhow::Result;
+
+use crate::{
+    graphql::export::{export_json, ExportFilter},
+    settings::{Command, Config},
+    storage::{column_family_names, import_archive, Database, DbOptions},
+};
+
+/// Runs the administration command on the database in `data_dir` of the
+/// configuration.
+///
+/// # Errors
+///
+/// Returns an error if the database cannot be opened or the command fails.
+pub fn run(command: Command, config: &Config) -> Result<()> {
+    let db_path = config.data_dir.join("db");
+    let db_options = DbOptions::new(
+        config.max_open_files,
+        config.max_mb_of_level_base,
+        config.num_of_thread,
+        config.max_sub_compactions,
+        config.cf_options.clone(),
+        config.storage_tiers.clone(),
+    );
+
+    match command {
+        Command::ListCfs => {
+            let db = Database::open_read_only(&db_path, &db_options)?;
+            println!("{:<24} {:>16} {:>20}", "NAME", "KEYS", "SIZE");
+            for cf_name in column_family_names() {
+                let props = db.properties_cf(cf_name)?;
+                println!(
+                    "{cf_name:<24} {:>16} {:>20}",
+                    props.estimate_num_keys, props.estimate_live_data_size
+                );
+            }
+        }
+        Command::ListSources => {
+            let db = Database::open_read_only(&db_path, &db_options)?;
+            let mut sources = db
+                .sources_store()?
+                .source_list()
+                .into_iter()
+                .collect::<Vec<_>>();
+            sources.sort_unstable();
+            for source in sources {
+                println!("{source}");
+            }
+        }
+        Command::Dump {
+            protocol,
+            source,
+            kind,
+            agent_name,
+            start,
+            end,
+            output,
+        } => {
+            let db = Database::open_read_only(&db_path, &db_options)?;
+            let filter = ExportFilter::new(protocol, source, kind, agent_name, start, end);
+            println!("{}", export_json(&db, &filter, &output)?);
+        }
+        Command::Import { archive } => {
+            let db = Database::open(&db_path, &db_options)?;
+            let (imported, skipped) = import_archive(&db, &archive)?;
+            db.shutdown()?;
+            println!("{imported} events imported, {skipped} skipped as already stored");
+        }
+        Command::Compact { cf } => {
+            let db = Database::open(&db_path, &db_options)?;
+            db.compact_cf(&cf)?;
+            db.shutdown()?;
+            println!("{cf} compacted");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,300 @@
+//! Authentication and authorization of the GraphQL requests.
+//!
+//! A request carries a JWT as a bearer token, whose claims name the caller,
+//! its role, and optionally the sources whose data it may read.
+
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::{anyhow, Context as _, Result};
+use async_graphql::{Context, Guard};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+
+/// What a caller is allowed to do, each role including the ones before it.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Queries the events and the status.
+    Read,
+    /// Also exports the events to files and builds pcaps.
+    Export,
+    /// Also changes the configuration and the data, and stops, reboots or
+    /// shuts down the node.
+    Admin,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    role: Role,
+    #[serde(default)]
+    sources: Option<Vec<String>>,
+}
+
+/// The authenticated caller of a GraphQL request.
+#[derive(Clone, Debug)]
+pub struct Caller {
+    pub name: String,
+    pub role: Role,
+    /// The sources whose data the caller may read, or `None` for all.
+    pub sources: Option<HashSet<String>>,
+    // The token is passed on to the peers the request is forwarded to.
+    token: String,
+}
+
+impl Caller {
+    #[cfg(test)]
+    pub(crate) fn new(name: String, role: Role, sources: Option<HashSet<String>>) -> Self {
+        Self {
+            name,
+            role,
+            sources,
+            token: String::new(),
+        }
+    }
+
+    /// Returns the bearer token of the request.
+    #[must_use]
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+/// Verifies the bearer tokens with the key in `jwt_key`.
+pub struct Authenticator {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl Authenticator {
+    /// Loads the key to verify the tokens.
+    ///
+    /// A PEM file holds the public key of RSA, ECDSA or Ed25519 signatures,
+    /// and any other file holds the secret of HMAC signatures.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or has no valid key.
+    pub fn from_key_file(path: &Path) -> Result<Self> {
+        let key = fs::read(path)
+            .with_context(|| format!("failed to read JWT key file: {}", path.display()))?;
+        let (key, algorithms) = if key.starts_with(b"-----BEGIN") {
+            if let Ok(key) = DecodingKey::from_rsa_pem(&key) {
+                let algorithms = vec![
+                    Algorithm::RS256,
+                    Algorithm::RS384,
+                    Algorithm::RS512,
+                    Algorithm::PS256,
+                    Algorithm::PS384,
+                    Algorithm::PS512,
+                ];
+                (key, algorithms)
+            } else if let Ok(key) = DecodingKey::from_ec_pem(&key) {
+                (key, vec![Algorithm::ES256, Algorithm::ES384])
+            } else {
+                let key = DecodingKey::from_ed_pem(&key).context("invalid JWT key")?;
+                (key, vec![Algorithm::EdDSA])
+            }
+        } else {
+            let secret = key.trim_ascii_end();
+            if secret.is_empty() {
+                return Err(anyhow!("empty JWT key file: {}", path.display()));
+            }
+            let algorithms = vec![Algorithm::HS256, Algorithm::HS384, Algorithm::HS512];
+            (DecodingKey::from_secret(secret), algorithms)
+        };
+        let mut validation = Validation::new(algorithms[0]);
+        validation.algorithms = algorithms;
+        Ok(Self { key, validation })
+    }
+
+    /// Returns the caller of a request with the `Authorization` header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no bearer token, or it is not valid.
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<Caller> {
+        let token = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .context("no bearer token")?
+            .trim();
+        let claims = jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation)
+            .context("invalid bearer token")?
+            .claims;
+        Ok(Caller {
+            name: claims.sub,
+            role: claims.role,
+            sources: claims.sources.map(|sources| sources.into_iter().collect()),
+            token: token.to_string(),
+        })
+    }
+}
+
+/// Allows a field only to the callers with the role or a higher one.
+///
+/// Every caller is allowed if the requests are not authenticated.
+pub struct RoleGuard(Role);
+
+impl RoleGuard {
+    #[must_use]
+    pub fn new(role: Role) -> Self {
+        Self(role)
+    }
+}
+
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        match ctx.data_opt::<Caller>() {
+            Some(caller) if caller.role < self.0 => Err(format!(
+                "{} is not allowed to do this, which needs the {:?} role",
+                caller.name, self.0
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns whether the caller may read the data of the source.
+#[must_use]
+pub fn is_source_allowed(ctx: &Context<'_>, source: &str) -> bool {
+    ctx.data_opt::<Caller>()
+        .and_then(|caller| caller.sources.as_ref())
+        .map_or(true, |sources| sources.contains(source))
+}
+
+/// Returns an error if the caller may not read the data of the source.
+///
+/// # Errors
+///
+/// Returns an error if the source is not in the sources of the caller.
+pub fn check_source(ctx: &Context<'_>, source: &str) -> async_graphql::Result<()> {
+    if is_source_allowed(ctx, source) {
+        Ok(())
+    } else {
+        Err(format!("not allowed to read the data of {source}").into())
+    }
+}
+
+/// Returns an error if the caller may read the data of some sources only, for
+/// a query without a source.
+///
+/// # Errors
+///
+/// Returns an error if the caller has a list of sources.
+pub fn check_all_sources(ctx: &Context<'_>) -> async_graphql::Result<()> {
+    match ctx.data_opt::<Caller>() {
+        Some(caller) if caller.sources.is_some() => {
+            Err("a source must be given to read the data of".into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the sources whose data the caller may read.
+#[must_use]
+pub fn allowed_sources(ctx: &Context<'_>, sources: &[String]) -> Vec<String> {
+    sources
+        .iter()
+        .filter(|source| is_source_allowed(ctx, source))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use async_graphql::Request;
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    use super::{Authenticator, Caller, Role};
+    use crate::graphql::tests::TestSchema;
+
+    const SECRET: &[u8] = b"test secret";
+
+    fn token(claims: &serde_json::Value) -> String {
+        jsonwebtoken::encode(
+            &Header::default(),
+            claims,
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap()
+    }
+
+    fn authenticator() -> Authenticator {
+        let mut key_file = tempfile::NamedTempFile::new().unwrap();
+        key_file.write_all(SECRET).unwrap();
+        key_file.write_all(b"\n").unwrap();
+        Authenticator::from_key_file(key_file.path()).unwrap()
+    }
+
+    #[test]
+    fn authenticate() {
+        let authenticator = authenticator();
+        let exp = chrono::Utc::now().timestamp() + 60;
+        let valid = token(&json!({
+            "sub": "analyst",
+            "role": "export",
+            "sources": ["src1"],
+            "exp": exp,
+        }));
+        let caller = authenticator
+            .authenticate(Some(&format!("Bearer {valid}")))
+            .unwrap();
+        assert_eq!(caller.name, "analyst");
+        assert_eq!(caller.role, Role::Export);
+        assert!(caller.sources.unwrap().contains("src1"));
+        assert_eq!(caller.token, valid);
+
+        assert!(authenticator.authenticate(None).is_err());
+        assert!(authenticator.authenticate(Some(&valid)).is_err());
+        let expired = token(&json!({ "sub": "analyst", "role": "read", "exp": exp - 3600 }));
+        assert!(authenticator
+            .authenticate(Some(&format!("Bearer {expired}")))
+            .is_err());
+        let unknown_role = token(&json!({ "sub": "analyst", "role": "root", "exp": exp }));
+        assert!(authenticator
+            .authenticate(Some(&format!("Bearer {unknown_role}")))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn authorize() {
+        let schema = TestSchema::new();
+        let caller = |role, sources: Option<&[&str]>| Caller {
+            name: "analyst".to_string(),
+            role,
+            sources: sources.map(|sources| sources.iter().map(ToString::to_string).collect()),
+            token: String::new(),
+        };
+
+        let request = Request::new("mutation { stop }").data(caller(Role::Export, None));
+        let res = schema.schema.execute(request).await;
+        assert_eq!(
+            res.errors.first().unwrap().message,
+            "analyst is not allowed to do this, which needs the Admin role"
+        );
+
+        let request = Request::new("{ sources }").data(caller(Role::Read, Some(&["src 1"])));
+        let res = schema.schema.execute(request).await;
+        assert_eq!(res.data.to_string(), "{sources: [\"src 1\"]}");
+
+        let query = r#"
+        {
+            connRawEvents(filter: { source: "ingest src 1" }, first: 1) {
+                edges { node { origAddr } }
+            }
+        }"#;
+        let request = Request::new(query).data(caller(Role::Admin, Some(&["src 1"])));
+        let res = schema.schema.execute(request).await;
+        assert_eq!(
+            res.errors.first().unwrap().message,
+            "not allowed to read the data of ingest src 1"
+        );
+        let request = Request::new(query).data(caller(Role::Read, None));
+        let res = schema.schema.execute(request).await;
+        assert!(res.errors.is_empty());
+    }
+}
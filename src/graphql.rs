@@ -1,13 +1,21 @@
+pub mod audit;
+mod backup;
 mod client;
-mod export;
+mod clock_skew;
+mod dead_letter;
+pub mod export;
+mod ingest_limit;
+mod integrity;
+mod legal_hold;
 mod log;
 mod netflow;
 pub mod network;
 mod packet;
 mod security;
-mod source;
+pub mod source;
 pub mod statistics;
 pub mod status;
+mod subscription;
 mod sysmon;
 mod timeseries;
 
@@ -24,7 +32,7 @@ use std::{
 use anyhow::anyhow;
 use async_graphql::{
     connection::{query, Connection, Edge, EmptyFields},
-    Context, EmptySubscription, Error, InputObject, MergedObject, OutputType, Result,
+    Context, Error, InputObject, MergedObject, MergedSubscription, OutputType, Result,
 };
 use base64::{engine::general_purpose::STANDARD as base64_engine, Engine};
 use chrono::{DateTime, TimeZone, Utc};
@@ -40,16 +48,22 @@ use tokio::sync::{mpsc::Sender, Notify};
 use tracing::error;
 
 use crate::{
-    ingest::implement::EventFilter,
-    peer::Peers,
+    auth::Caller,
+    ingest::{implement::EventFilter, IngestLimiter, TimestampChecker},
+    peer::{PeerIdents, Peers},
     settings::Settings,
     storage::{
-        Database, Direction, FilteredIter, KeyExtractor, KeyValue, RawEventStore, StorageKey,
+        timestamp_from_key, Database, Direction, FilteredIter, KeyExtractor, KeyValue,
+        RawEventStore, StorageKey,
     },
-    AckTransmissionCount, IngestSources, PcapSources,
+    AckTransmissionCount, IngestSources, PcapSources, RunTimeIngestSources, SourceJobs,
+    StorageIntegrity, StreamDirectChannels,
 };
 
 pub const TIMESTAMP_SIZE: usize = 8;
+// The size of the sequence number that follows the timestamp in a key, which
+// tells apart the events with the same timestamp.
+pub const SEQUENCE_SIZE: usize = 4;
 
 #[derive(Default, MergedObject)]
 pub struct Query(
@@ -64,10 +78,27 @@ pub struct Query(
     sysmon::SysmonQuery,
     security::SecurityLogQuery,
     netflow::NetflowQuery,
+    dead_letter::DeadLetterQuery,
+    backup::BackupQuery,
+    integrity::IntegrityQuery,
+    legal_hold::LegalHoldQuery,
+    ingest_limit::IngestLimitQuery,
+    clock_skew::ClockSkewQuery,
+    audit::AuditQuery,
 );
 
 #[derive(Default, MergedObject)]
-pub struct Mutation(status::ConfigMutation);
+pub struct Mutation(
+    status::ConfigMutation,
+    dead_letter::DeadLetterMutation,
+    backup::BackupMutation,
+    integrity::IntegrityMutation,
+    legal_hold::LegalHoldMutation,
+    source::SourceMutation,
+);
+
+#[derive(Default, MergedSubscription)]
+pub struct Subscription(subscription::EventSubscription);
 
 #[derive(InputObject, Serialize, Clone)]
 pub struct TimeRange {
@@ -141,7 +172,7 @@ pub trait NodeSource {
     fn source(&self) -> &str;
 }
 
-pub type Schema = async_graphql::Schema<Query, Mutation, EmptySubscription>;
+pub type Schema = async_graphql::Schema<Query, Mutation, Subscription>;
 type ConnArgs<T> = (Vec<(Box<[u8]>, T)>, bool, bool);
 
 pub struct NodeName(pub String);
@@ -149,13 +180,21 @@ pub struct RebootNotify(Arc<Notify>); // reboot
 pub struct PowerOffNotify(Arc<Notify>); // shutdown
 pub struct TerminateNotify(Arc<Notify>); // stop
 
+/// Marks a request whose client certificate matches the hostname of a peer,
+/// so that its `request_from_peer` is honored.
+#[derive(Clone, Copy)]
+pub struct PeerRequest;
+
 #[allow(clippy::too_many_arguments)]
 pub fn schema(
     node_name: NodeName,
     database: Database,
     pcap_sources: PcapSources,
     ingest_sources: IngestSources,
+    runtime_ingest_sources: RunTimeIngestSources,
+    stream_direct_channels: StreamDirectChannels,
     peers: Peers,
+    peer_idents: PeerIdents,
     request_client_pool: reqwest::Client,
     export_path: PathBuf,
     reload_tx: Sender<String>,
@@ -163,25 +202,40 @@ pub fn schema(
     notify_power_off: Arc<Notify>,
     notify_terminate: Arc<Notify>,
     ack_transmission_cnt: AckTransmissionCount,
+    storage_integrity: StorageIntegrity,
+    source_jobs: SourceJobs,
+    ingest_limiter: Arc<IngestLimiter>,
+    timestamp_checker: Arc<TimestampChecker>,
     is_local_config: bool,
     settings: Settings,
 ) -> Schema {
-    Schema::build(Query::default(), Mutation::default(), EmptySubscription)
-        .data(node_name)
-        .data(database)
-        .data(pcap_sources)
-        .data(ingest_sources)
-        .data(peers)
-        .data(request_client_pool)
-        .data(export_path)
-        .data(reload_tx)
-        .data(ack_transmission_cnt)
-        .data(TerminateNotify(notify_terminate))
-        .data(RebootNotify(notify_reboot))
-        .data(PowerOffNotify(notify_power_off))
-        .data(is_local_config)
-        .data(settings)
-        .finish()
+    Schema::build(
+        Query::default(),
+        Mutation::default(),
+        Subscription::default(),
+    )
+    .data(node_name)
+    .data(database)
+    .data(pcap_sources)
+    .data(ingest_sources)
+    .data(runtime_ingest_sources)
+    .data(stream_direct_channels)
+    .data(peers)
+    .data(peer_idents)
+    .data(request_client_pool)
+    .data(export_path)
+    .data(reload_tx)
+    .data(ack_transmission_cnt)
+    .data(storage_integrity)
+    .data(source_jobs)
+    .data(ingest_limiter)
+    .data(timestamp_checker)
+    .data(TerminateNotify(notify_terminate))
+    .data(RebootNotify(notify_reboot))
+    .data(PowerOffNotify(notify_power_off))
+    .data(is_local_config)
+    .data(settings)
+    .finish()
 }
 
 /// The default page size for connections when neither `first` nor `last` is
@@ -260,7 +314,7 @@ where
 
         // generate storage search key
         let key_builder = StorageKey::builder()
-            .start_key(filter.get_start_key())
+            .start_key(store.source_key(filter.get_start_key()))
             .mid_key(filter.get_mid_key());
         let from_key = key_builder
             .clone()
@@ -296,7 +350,7 @@ where
 
         // generate storage search key
         let key_builder = StorageKey::builder()
-            .start_key(filter.get_start_key())
+            .start_key(store.source_key(filter.get_start_key()))
             .mid_key(filter.get_mid_key());
         let from_key = key_builder
             .clone()
@@ -327,7 +381,7 @@ where
 
         // generate storage search key
         let key_builder = StorageKey::builder()
-            .start_key(filter.get_start_key())
+            .start_key(store.source_key(filter.get_start_key()))
             .mid_key(filter.get_mid_key());
         let from_key = key_builder
             .clone()
@@ -345,7 +399,7 @@ where
         let first = first.unwrap_or(MAXIMUM_PAGE_SIZE).min(MAXIMUM_PAGE_SIZE);
         // generate storage search key
         let key_builder = StorageKey::builder()
-            .start_key(filter.get_start_key())
+            .start_key(store.source_key(filter.get_start_key()))
             .mid_key(filter.get_mid_key());
         let from_key = key_builder
             .clone()
@@ -438,11 +492,7 @@ where
 }
 
 pub fn get_timestamp_from_key(key: &[u8]) -> Result<DateTime<Utc>, anyhow::Error> {
-    if key.len() > TIMESTAMP_SIZE {
-        let nanos = i64::from_be_bytes(key[(key.len() - TIMESTAMP_SIZE)..].try_into()?);
-        return Ok(Utc.timestamp_nanos(nanos));
-    }
-    Err(anyhow!("invalid database key length"))
+    Ok(Utc.timestamp_nanos(timestamp_from_key(key)?))
 }
 
 fn get_peekable_iter<'c, T>(
@@ -492,7 +542,7 @@ where
         let cursor = base64_engine.decode(before)?;
 
         // generate storage search key
-        let key_builder = StorageKey::builder().start_key(filter.get_start_key());
+        let key_builder = StorageKey::builder().start_key(store.source_key(filter.get_start_key()));
         let from_key = key_builder
             .clone()
             .upper_open_bound_end_key(filter.get_range_end_key().1)
@@ -519,7 +569,7 @@ where
         let cursor = base64_engine.decode(after)?;
 
         // generate storage search key
-        let key_builder = StorageKey::builder().start_key(filter.get_start_key());
+        let key_builder = StorageKey::builder().start_key(store.source_key(filter.get_start_key()));
         let from_key = key_builder
             .clone()
             .lower_closed_bound_end_key(filter.get_range_end_key().0)
@@ -541,7 +591,7 @@ where
         let last = last.min(MAXIMUM_PAGE_SIZE);
 
         // generate storage search key
-        let key_builder = StorageKey::builder().start_key(filter.get_start_key());
+        let key_builder = StorageKey::builder().start_key(store.source_key(filter.get_start_key()));
         let from_key = key_builder
             .clone()
             .upper_closed_bound_end_key(filter.get_range_end_key().1)
@@ -555,7 +605,7 @@ where
         let first = first.unwrap_or(MAXIMUM_PAGE_SIZE).min(MAXIMUM_PAGE_SIZE);
 
         // generate storage search key
-        let key_builder = StorageKey::builder().start_key(filter.get_start_key());
+        let key_builder = StorageKey::builder().start_key(store.source_key(filter.get_start_key()));
         let from_key = key_builder
             .clone()
             .lower_closed_bound_end_key(filter.get_range_end_key().0)
@@ -755,6 +805,7 @@ macro_rules! events_in_cluster {
      $(, with_extra_handler_args ($($handler_arg:expr ),* ))?
      $(, with_extra_query_args ($($query_arg:tt := $query_arg_from:expr),* ))? ) => {{
         type QueryVariables = $variables_type;
+        crate::auth::check_source($ctx, &$source)?;
         if crate::graphql::is_current_giganto_in_charge($ctx, &$source).await {
             $handler($ctx, &$filter, $($($handler_arg)*)*)
         } else {
@@ -808,11 +859,13 @@ macro_rules! events_in_cluster {
      $result_type:path
      $(, with_extra_handler_args ($($handler_arg:expr ),* ))?
      $(, with_extra_query_args ($($query_arg:tt := $query_arg_from:expr),* ))? ) => {{
-        if $request_from_peer.unwrap_or_default() {
-            return $handler($ctx, &$sources, $($($handler_arg,)*)*).await;
+        // Only the sources the caller may read are queried.
+        let sources = crate::auth::allowed_sources($ctx, &$sources);
+        if crate::graphql::is_request_from_peer($ctx, $request_from_peer) {
+            return $handler($ctx, &sources, $($($handler_arg,)*)*).await;
         }
 
-        let sources_set: HashSet<_> = $sources.iter().map(|s| s.as_str()).collect();
+        let sources_set: HashSet<_> = sources.iter().map(|s| s.as_str()).collect();
         let (sources_to_handle_by_current_giganto, peers_in_charge_graphql_addrs)
             = crate::graphql::find_who_are_in_charge(&$ctx, &sources_set).await;
 
@@ -825,7 +878,7 @@ macro_rules! events_in_cluster {
 
                 let peer_results_fut = crate::graphql::request_selected_peers_for_events_fut!(
                     $ctx,
-                    $sources,
+                    sources,
                     peers_in_charge_graphql_addrs,
                     $response_data_type,
                     $field_name,
@@ -858,7 +911,7 @@ macro_rules! events_in_cluster {
             (false, true) => {
                 let peer_results = crate::graphql::request_selected_peers_for_events_fut!(
                     $ctx,
-                    $sources,
+                    sources,
                     peers_in_charge_graphql_addrs,
                     $response_data_type,
                     $field_name,
@@ -956,6 +1009,7 @@ macro_rules! paged_events_in_cluster {
      $response_data_type:path,
      $field_name:ident
      $(, with_extra_query_args ($($query_arg:tt := $query_arg_from:expr),* ))? ) => {{
+        crate::auth::check_source($ctx, &$source)?;
         if crate::graphql::is_current_giganto_in_charge($ctx, &$source).await {
             $handler($ctx, $filter, $after, $before, $first, $last).await
         } else {
@@ -1030,7 +1084,11 @@ macro_rules! paged_events_in_cluster {
      $variables_type:ty,
      $response_data_type:path,
      $field_name:ident) => {{
-        if $request_from_peer.unwrap_or_default() {
+        match &$filter.source {
+            Some(source) => crate::auth::check_source($ctx, source)?,
+            None => crate::auth::check_all_sources($ctx)?,
+        }
+        if crate::graphql::is_request_from_peer($ctx, $request_from_peer) {
             return $handler($ctx, $filter, $after, $before, $first, $last).await;
         }
 
@@ -1185,6 +1243,12 @@ where
     edges
 }
 
+/// Returns whether the request is from a peer giganto, which is true only if
+/// the client certificate of the request matches a peer.
+fn is_request_from_peer(ctx: &Context<'_>, request_from_peer: Option<bool>) -> bool {
+    request_from_peer.unwrap_or_default() && ctx.data_opt::<PeerRequest>().is_some()
+}
+
 async fn is_current_giganto_in_charge<'ctx>(ctx: &Context<'ctx>, source_filter: &str) -> bool {
     let ingest_sources = ctx.data_opt::<IngestSources>();
     match ingest_sources {
@@ -1286,14 +1350,33 @@ where
     F: 'static + FnOnce(Option<ResponseDataType>) -> ResultDataType,
 {
     let client = ctx.data::<reqwest::Client>()?;
+    let url = if cfg!(test) {
+        format!("http://{peer_graphql_addr}/graphql")
+    } else {
+        // The certificate of the peer is verified against its hostname.
+        let peer_idents = ctx.data::<PeerIdents>()?;
+        let hostname = peer_idents
+            .read()
+            .await
+            .iter()
+            .find(|ident| ident.addr.ip() == peer_graphql_addr.ip())
+            .map(|ident| ident.hostname.clone())
+            .ok_or_else(|| {
+                Error::new(format!(
+                    "Peer giganto's hostname is unknown. addr: {peer_graphql_addr}"
+                ))
+            })?;
+        format!("https://{hostname}:{}/graphql", peer_graphql_addr.port())
+    };
     let req = client
-        .post(format!(
-            "{}://{}/graphql",
-            if cfg!(test) { "http" } else { "https" },
-            peer_graphql_addr
-        ))
+        .post(url)
         .header(reqwest::header::CONTENT_TYPE, "application/json")
         .json(&req_body);
+    // The peer authorizes the request with the token of the caller.
+    let req = match ctx.data_opt::<Caller>() {
+        Some(caller) => req.bearer_auth(caller.token()),
+        None => req,
+    };
 
     let resp = req
         .send()
@@ -1516,25 +1599,29 @@ macro_rules! impl_from_giganto_search_filter_for_graphql_client {
 pub(crate) use impl_from_giganto_search_filter_for_graphql_client;
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::collections::{HashMap, HashSet};
     use std::sync::Arc;
 
     use async_graphql::{
         connection::{Edge, EmptyFields},
-        EmptySubscription, SimpleObject,
+        SimpleObject,
     };
     use chrono::{DateTime, Utc};
     use tokio::sync::{Notify, RwLock};
 
     use super::{schema, sort_and_trunk_edges, NodeName};
-    use crate::graphql::{Mutation, NodeSource, Query};
+    use crate::graphql::{Mutation, NodeSource, Query, Subscription};
+    use crate::ingest::{IngestLimiter, TimestampChecker};
     use crate::peer::{PeerInfo, Peers};
-    use crate::settings::Settings;
+    use crate::settings::{IngestLimit, LimitAction, Settings, TimestampWindow};
     use crate::storage::{Database, DbOptions};
-    use crate::{new_pcap_sources, IngestSources};
+    use crate::{
+        new_pcap_sources, new_stream_direct_channels, IngestSources, RunTimeIngestSources,
+        StreamDirectChannels,
+    };
 
-    type Schema = async_graphql::Schema<Query, Mutation, EmptySubscription>;
+    type Schema = async_graphql::Schema<Query, Mutation, Subscription>;
 
     const CURRENT_GIGANTO_INGEST_SOURCES: [&str; 3] = ["src1", "src 1", "ingest src 1"];
     const PEER_GIGANTO_2_INGEST_SOURCES: [&str; 3] = ["src2", "src 2", "ingest src 2"];
@@ -1542,6 +1629,10 @@ mod tests {
     pub struct TestSchema {
         pub _dir: tempfile::TempDir, // to prevent the data directory from being deleted while the test is running
         pub db: Database,
+        pub runtime_ingest_sources: RunTimeIngestSources,
+        pub ingest_limiter: Arc<IngestLimiter>,
+        pub timestamp_checker: Arc<TimestampChecker>,
+        pub stream_direct_channels: StreamDirectChannels,
         pub schema: Schema,
     }
 
@@ -1557,12 +1648,30 @@ mod tests {
             let notify_power_off = Arc::new(Notify::new());
             let notify_terminate = Arc::new(Notify::new());
             let settings = Settings::new().unwrap();
+            let runtime_ingest_sources: RunTimeIngestSources =
+                Arc::new(RwLock::new(HashMap::new()));
+            let ingest_limiter = Arc::new(IngestLimiter::new(
+                vec![IngestLimit {
+                    source: Some("src 1".to_string()),
+                    kind: None,
+                    events_per_sec: None,
+                    bytes_per_sec: None,
+                    daily_bytes: Some(1000),
+                    action: LimitAction::Drop,
+                }],
+                db.clone(),
+            ));
+            let timestamp_checker = Arc::new(TimestampChecker::new(TimestampWindow::default()));
+            let stream_direct_channels = new_stream_direct_channels();
             let schema = schema(
                 NodeName("giganto1".to_string()),
                 db.clone(),
                 pcap_sources,
                 ingest_sources,
+                runtime_ingest_sources.clone(),
+                stream_direct_channels.clone(),
                 peers,
+                Arc::new(RwLock::new(HashSet::new())),
                 request_client_pool,
                 export_dir.path().to_path_buf(),
                 reload_tx,
@@ -1570,6 +1679,10 @@ mod tests {
                 notify_power_off,
                 notify_terminate,
                 Arc::new(RwLock::new(1024)),
+                Arc::new(RwLock::new(None)),
+                Arc::new(RwLock::new(Vec::new())),
+                ingest_limiter.clone(),
+                timestamp_checker.clone(),
                 is_local_config,
                 settings,
             );
@@ -1577,6 +1690,10 @@ mod tests {
             Self {
                 _dir: db_dir,
                 db,
+                runtime_ingest_sources,
+                ingest_limiter,
+                timestamp_checker,
+                stream_direct_channels,
                 schema,
             }
         }
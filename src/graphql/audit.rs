@@ -0,0 +1,348 @@
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use async_graphql::{
+    parser::{parse_query, types::Selection},
+    Context, Name, Object, Request, Response, Result, SimpleObject, StringNumber, Value,
+};
+use chrono::{DateTime, Utc};
+use tracing::error;
+
+use super::TimeRange;
+use crate::{
+    auth::{Caller, Role, RoleGuard},
+    storage::{AuditEntry as AuditRecordEntry, Database},
+};
+
+const DEFAULT_LIMIT: usize = 100;
+// The variables whose names contain any of these are not recorded.
+const SENSITIVE_NAMES: [&str; 4] = ["password", "secret", "token", "credential"];
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Default)]
+pub(super) struct AuditQuery;
+
+/// A GraphQL operation in the audit trail.
+#[derive(SimpleObject, Debug)]
+struct AuditEntry {
+    /// When the operation was received.
+    time: DateTime<Utc>,
+    /// The name of the caller, or `null` if the requests are not
+    /// authenticated.
+    caller: Option<String>,
+    operation: Option<String>,
+    /// The root fields of the operation, such as `export` and `reboot`.
+    fields: Vec<String>,
+    /// The variables in JSON, with the sensitive values redacted.
+    variables: String,
+    /// The sources in the arguments of the operation.
+    sources: Vec<String>,
+    /// The number of the items returned.
+    result_count: StringNumber<u64>,
+    /// How long the operation took, in nanoseconds.
+    duration: StringNumber<u64>,
+    errors: Vec<String>,
+}
+
+impl From<AuditRecordEntry> for AuditEntry {
+    fn from(entry: AuditRecordEntry) -> Self {
+        Self {
+            time: entry.time,
+            caller: entry.caller,
+            operation: entry.operation,
+            fields: entry.fields,
+            variables: entry.variables,
+            sources: entry.sources,
+            result_count: StringNumber(entry.result_count),
+            duration: StringNumber(u64::try_from(entry.duration.as_nanos()).unwrap_or(u64::MAX)),
+            errors: entry.errors,
+        }
+    }
+}
+
+#[Object]
+impl AuditQuery {
+    /// Returns up to `limit` (100 by default) operations in the audit trail
+    /// received in the time range, newest first.
+    ///
+    /// If `caller` is given, only the operations of that caller are returned.
+    #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn audit_trail<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        time: Option<TimeRange>,
+        caller: Option<String>,
+        limit: Option<usize>,
+    ) -> Result<Vec<AuditEntry>> {
+        let db = ctx.data::<Database>()?;
+        let (start, end) = time.map_or((None, None), |time| (time.start, time.end));
+        let entries = db.audit_store()?.search(
+            start,
+            end,
+            caller.as_deref(),
+            limit.unwrap_or(DEFAULT_LIMIT),
+        )?;
+        Ok(entries.into_iter().map(Into::into).collect())
+    }
+}
+
+/// What is recorded in the audit trail about a GraphQL request, taken before
+/// the request is executed.
+pub struct AuditRecord {
+    entry: AuditRecordEntry,
+    start: Instant,
+}
+
+impl AuditRecord {
+    #[must_use]
+    pub fn new(request: &Request, caller: Option<&Caller>) -> Self {
+        let (fields, sources) = root_fields(request);
+        let variables = serde_json::to_value(&request.variables).map_or_else(
+            |_| "{}".to_string(),
+            |mut variables| {
+                redact(&mut variables);
+                variables.to_string()
+            },
+        );
+        Self {
+            entry: AuditRecordEntry {
+                time: Utc::now(),
+                caller: caller.map(|caller| caller.name.clone()),
+                operation: request.operation_name.clone(),
+                fields,
+                variables,
+                sources,
+                result_count: 0,
+                duration: Duration::ZERO,
+                errors: Vec::new(),
+            },
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns the record of a subscription to the events of `source`, taken
+    /// when the subscription starts. Its arguments are recorded as the
+    /// variables.
+    #[must_use]
+    pub fn subscription(ctx: &Context<'_>, source: &str) -> Self {
+        let arguments = ctx
+            .field()
+            .arguments()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.into_json().unwrap_or_default()))
+            .collect();
+        let mut variables = serde_json::Value::Object(arguments);
+        redact(&mut variables);
+        Self {
+            entry: AuditRecordEntry {
+                time: Utc::now(),
+                caller: ctx.data_opt::<Caller>().map(|caller| caller.name.clone()),
+                operation: None,
+                fields: vec![ctx.field().name().to_string()],
+                variables: variables.to_string(),
+                sources: vec![source.to_string()],
+                result_count: 0,
+                duration: Duration::ZERO,
+                errors: Vec::new(),
+            },
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns the root fields of the operation.
+    #[must_use]
+    pub fn fields(&self) -> &[String] {
+        &self.entry.fields
+    }
+
+    /// Completes the record with the response and appends it to the audit
+    /// trail.
+    pub fn finish(mut self, db: &Database, response: &Response) {
+        self.entry.duration = self.start.elapsed();
+        self.entry.result_count = result_count(&response.data);
+        self.entry.errors = response
+            .errors
+            .iter()
+            .map(|error| error.message.clone())
+            .collect();
+        self.append(db);
+    }
+
+    /// Appends the record of a subscription to the audit trail as it starts.
+    pub fn start(&self, db: &Database) {
+        self.append(db);
+    }
+
+    /// Appends the record of a subscription to the audit trail again as it
+    /// ends, with the number of the events sent and how long it lasted.
+    pub fn end(mut self, db: &Database, sent: u64) {
+        self.entry.time = Utc::now();
+        self.entry.duration = self.start.elapsed();
+        self.entry.result_count = sent;
+        self.append(db);
+    }
+
+    fn append(&self, db: &Database) {
+        if let Err(e) = db.audit_store().and_then(|store| store.append(&self.entry)) {
+            error!("Failed to record a GraphQL operation in the audit trail: {e}");
+        }
+    }
+}
+
+/// Returns the root fields of the operations in the request, and the sources
+/// in their arguments.
+fn root_fields(request: &Request) -> (Vec<String>, Vec<String>) {
+    let Ok(document) = parse_query(&request.query) else {
+        return (Vec::new(), Vec::new());
+    };
+    let mut fields = Vec::new();
+    let mut sources = BTreeSet::new();
+    for (name, operation) in document.operations.iter() {
+        if request
+            .operation_name
+            .as_deref()
+            .is_some_and(|selected| name.map(Name::as_str) != Some(selected))
+        {
+            continue;
+        }
+        for selection in &operation.node.selection_set.node.items {
+            let Selection::Field(field) = &selection.node else {
+                continue;
+            };
+            fields.push(field.node.name.node.to_string());
+            for (name, value) in &field.node.arguments {
+                let value = value
+                    .node
+                    .clone()
+                    .into_const_with(|variable| request.variables.get(&variable).cloned().ok_or(()))
+                    .unwrap_or(Value::Null);
+                collect_sources(&name.node, &value, &mut sources);
+            }
+        }
+    }
+    (fields, sources.into_iter().collect())
+}
+
+fn collect_sources(name: &str, value: &Value, sources: &mut BTreeSet<String>) {
+    match value {
+        Value::String(source) if name == "source" || name == "sources" => {
+            sources.insert(source.clone());
+        }
+        Value::List(items) => {
+            for item in items {
+                collect_sources(name, item, sources);
+            }
+        }
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                collect_sources(name, value, sources);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (name, value) in fields {
+                let name = name.to_lowercase();
+                if SENSITIVE_NAMES.iter().any(|s| name.contains(s)) {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Returns the number of the items in the data of a response, counting the
+/// elements of a list or the edges of a connection as the items.
+fn result_count(data: &Value) -> u64 {
+    let Value::Object(fields) = data else {
+        return 0;
+    };
+    fields
+        .values()
+        .map(|value| match value {
+            Value::Null => 0,
+            Value::List(items) => items.len() as u64,
+            Value::Object(fields) => match fields.get("edges") {
+                Some(Value::List(edges)) => edges.len() as u64,
+                _ => 1,
+            },
+            _ => 1,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{Request, Variables};
+    use serde_json::json;
+
+    use super::AuditRecord;
+    use crate::{
+        auth::{Caller, Role},
+        graphql::tests::TestSchema,
+    };
+
+    #[tokio::test]
+    async fn audit_trail() {
+        let schema = TestSchema::new();
+        let query = r#"
+        query Events($source: String!) {
+            connRawEvents(filter: { source: $source }, first: 1) {
+                edges { node { origAddr } }
+            }
+            sources
+        }"#;
+        let request = Request::new(query)
+            .operation_name("Events")
+            .variables(Variables::from_json(json!({
+                "source": "src 1",
+                "token": "secret value",
+            })));
+        let record = AuditRecord::new(&request, None);
+        let res = schema.schema.execute(request).await;
+        record.finish(&schema.db, &res);
+
+        let request = Request::new("mutation { stop }");
+        let caller = Caller::new("admin".to_string(), Role::Admin, None);
+        let record = AuditRecord::new(&request, Some(&caller));
+        let res = schema.schema.execute(request.data(caller)).await;
+        record.finish(&schema.db, &res);
+
+        let query = r#"
+        {
+            auditTrail {
+                caller
+                operation
+                fields
+                variables
+                sources
+                resultCount
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{auditTrail: [\
+            {caller: \"admin\", operation: null, fields: [\"stop\"], variables: \"{}\", sources: [], resultCount: \"1\"}, \
+            {caller: null, operation: \"Events\", fields: [\"connRawEvents\", \"sources\"], \
+            variables: \"{\\\"source\\\":\\\"src 1\\\",\\\"token\\\":\\\"[REDACTED]\\\"}\", \
+            sources: [\"src 1\"], resultCount: \"3\"}]}"
+        );
+
+        let query = r#"{ auditTrail(caller: "admin") { fields } }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(res.data.to_string(), "{auditTrail: [{fields: [\"stop\"]}]}");
+    }
+}
@@ -0,0 +1,70 @@
+use async_graphql::{Context, Object, Result, SimpleObject, StringNumber};
+use chrono::{DateTime, Utc};
+
+use crate::{
+    auth::{Role, RoleGuard},
+    settings::Settings,
+    storage::{list_backups, prune_backups, BackupInfo, Database},
+};
+
+#[derive(Default)]
+pub(super) struct BackupQuery;
+
+#[derive(Default)]
+pub(super) struct BackupMutation;
+
+/// A checkpoint of the database in the backup directory.
+#[derive(SimpleObject, Debug)]
+struct Backup {
+    name: String,
+    path: String,
+    created_at: DateTime<Utc>,
+    size: StringNumber<u64>,
+}
+
+impl From<BackupInfo> for Backup {
+    fn from(backup: BackupInfo) -> Self {
+        Self {
+            name: backup.name,
+            path: backup.path.to_string_lossy().to_string(),
+            created_at: backup.created_at,
+            size: StringNumber(backup.size),
+        }
+    }
+}
+
+#[Object]
+impl BackupQuery {
+    /// Returns the backups in the backup directory, from the oldest to the
+    /// newest.
+    #[allow(clippy::unused_async)]
+    async fn backups<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<Backup>> {
+        let settings = ctx.data::<Settings>()?;
+        let backups = list_backups(&settings.config.backup_dir)?;
+        Ok(backups.into_iter().map(Into::into).collect())
+    }
+}
+
+#[Object]
+impl BackupMutation {
+    /// Creates a backup of the database in the backup directory while the
+    /// data keeps being ingested.
+    #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn backup<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Backup> {
+        let db = ctx.data::<Database>()?;
+        let settings = ctx.data::<Settings>()?;
+        let backup = db.backup(&settings.config.backup_dir)?;
+        Ok(backup.into())
+    }
+
+    /// Deletes the backups except the newest `keep` ones, and returns the
+    /// deleted backups.
+    #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn prune_backups<'ctx>(&self, ctx: &Context<'ctx>, keep: usize) -> Result<Vec<Backup>> {
+        let settings = ctx.data::<Settings>()?;
+        let pruned = prune_backups(&settings.config.backup_dir, keep)?;
+        Ok(pruned.into_iter().map(Into::into).collect())
+    }
+}
@@ -594,3 +594,19 @@ pub struct Export;
     response_derives = "Clone, Default, PartialEq"
 )]
 pub struct Statistics;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/client/schema/schema.graphql",
+    query_path = "src/graphql/client/schema/source_details.graphql",
+    response_derives = "Clone, Default, PartialEq"
+)]
+pub struct SourceDetails;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/client/schema/schema.graphql",
+    query_path = "src/graphql/client/schema/ingest_counts.graphql",
+    response_derives = "Clone, Default, PartialEq"
+)]
+pub struct IngestCounts;
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Object, Result, SimpleObject, StringNumber};
+use chrono::{DateTime, Utc};
+
+use crate::ingest::{ClockSkew as ClockSkewEntry, TimestampChecker};
+
+#[derive(Default)]
+pub(super) struct ClockSkewQuery;
+
+/// The estimated clock skew of a source.
+#[derive(SimpleObject, Debug)]
+struct ClockSkew {
+    source: String,
+    /// How far the clock of the source is ahead of giganto, in nanoseconds,
+    /// or behind it if negative. It never exceeds the actual skew, and falls
+    /// short of it by the smallest delay of the events in the last two
+    /// minutes. `null` if the source has sent no events in the last two
+    /// minutes.
+    skew: Option<StringNumber<i64>>,
+    /// When the source last sent events.
+    updated_at: DateTime<Utc>,
+    /// The events whose timestamps were outside the `timestamp_window`.
+    out_of_window_events: StringNumber<u64>,
+}
+
+impl From<ClockSkewEntry> for ClockSkew {
+    fn from(skew: ClockSkewEntry) -> Self {
+        Self {
+            source: skew.source,
+            skew: skew.skew.map(StringNumber),
+            updated_at: skew.updated_at,
+            out_of_window_events: StringNumber(skew.out_of_window),
+        }
+    }
+}
+
+#[Object]
+impl ClockSkewQuery {
+    /// Returns the estimated clock skews of the sources that have sent events
+    /// since giganto started, ordered by source.
+    ///
+    /// The skew of a source is estimated from the timestamps of its events and
+    /// the time they are received, so delayed events make a source look
+    /// behind. If `sources` is given, only the skews of those sources are
+    /// returned.
+    #[allow(clippy::unused_async)]
+    async fn clock_skews<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        sources: Option<Vec<String>>,
+    ) -> Result<Vec<ClockSkew>> {
+        let checker = ctx.data::<Arc<TimestampChecker>>()?;
+        Ok(checker
+            .skews()
+            .into_iter()
+            .filter(|skew| sources.as_ref().map_or(true, |s| s.contains(&skew.source)))
+            .filter(|skew| crate::auth::is_source_allowed(ctx, &skew.source))
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+
+    use crate::graphql::tests::TestSchema;
+
+    #[tokio::test]
+    async fn clock_skews() {
+        let schema = TestSchema::new();
+        let now = Utc::now();
+        let ahead = (now + TimeDelta::seconds(30))
+            .timestamp_nanos_opt()
+            .unwrap();
+        schema.timestamp_checker.observe("src 1", now, ahead, 3);
+        schema.timestamp_checker.observe("src 2", now, ahead, 0);
+
+        let query = r#"
+        {
+            clockSkews(sources: ["src 1"]) {
+                source
+                skew
+                outOfWindowEvents
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{clockSkews: [{source: \"src 1\", skew: \"30000000000\", outOfWindowEvents: \"3\"}]}"
+        );
+    }
+}
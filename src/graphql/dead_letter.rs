@@ -0,0 +1,269 @@
+use std::{fmt::Debug, net::IpAddr};
+
+use async_graphql::{
+    connection::{query, Connection},
+    Context, InputObject, Object, Result, SimpleObject,
+};
+use chrono::{DateTime, Utc};
+use tracing::info;
+
+use super::{base64_engine, get_timestamp_from_key, load_connection, Engine, FromKeyValue};
+use crate::{
+    auth::{Role, RoleGuard},
+    graphql::{RawEventFilter, TimeRange},
+    ingest::DeadLetter,
+    storage::{Database, KeyExtractor, StorageKey},
+};
+
+#[derive(Default)]
+pub(super) struct DeadLetterQuery;
+
+#[derive(Default)]
+pub(super) struct DeadLetterMutation;
+
+#[derive(InputObject)]
+pub struct DeadLetterFilter {
+    time: Option<TimeRange>,
+    source: String,
+}
+
+impl KeyExtractor for DeadLetterFilter {
+    fn get_start_key(&self) -> &str {
+        &self.source
+    }
+
+    // dead letters don't use mid key
+    fn get_mid_key(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn get_range_end_key(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        if let Some(time) = &self.time {
+            (time.start, time.end)
+        } else {
+            (None, None)
+        }
+    }
+}
+
+impl RawEventFilter for DeadLetterFilter {
+    fn check(
+        &self,
+        _orig_addr: Option<IpAddr>,
+        _resp_addr: Option<IpAddr>,
+        _orig_port: Option<u16>,
+        _resp_port: Option<u16>,
+        _log_level: Option<String>,
+        _log_contents: Option<String>,
+        _text: Option<String>,
+        _source: Option<String>,
+        _agent_id: Option<String>,
+    ) -> Result<bool> {
+        Ok(true)
+    }
+}
+
+/// A received record that could not be decoded.
+#[derive(SimpleObject, Debug)]
+struct DeadLetterRawEvent {
+    timestamp: DateTime<Utc>,
+    source: String,
+    kind: String,
+    error: String,
+    /// The received record, encoded in base64.
+    raw_event: String,
+}
+
+impl FromKeyValue<DeadLetter> for DeadLetterRawEvent {
+    fn from_key_value(key: &[u8], d: DeadLetter) -> Result<Self> {
+        Ok(DeadLetterRawEvent {
+            timestamp: get_timestamp_from_key(key)?,
+            source: d.source,
+            kind: d.kind,
+            error: d.error,
+            raw_event: base64_engine.encode(d.raw_event),
+        })
+    }
+}
+
+#[Object]
+impl DeadLetterQuery {
+    /// Returns the records of the source that could not be decoded at ingest,
+    /// in the order of their timestamps.
+    async fn dead_letters<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: DeadLetterFilter,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<String, DeadLetterRawEvent>> {
+        crate::auth::check_source(ctx, &filter.source)?;
+        let db = ctx.data::<Database>()?;
+        let store = db.dead_letter_store()?;
+
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                load_connection(&store, &filter, after, before, first, last)
+            },
+        )
+        .await
+    }
+}
+
+#[Object]
+impl DeadLetterMutation {
+    /// Deletes the dead letters of the source within the time range. All the
+    /// dead letters of the source are deleted if the time range is omitted.
+    #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn purge_dead_letters<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: DeadLetterFilter,
+    ) -> Result<bool> {
+        let db = ctx.data::<Database>()?;
+        let store = db.dead_letter_store()?;
+
+        let (start, end) = filter.get_range_end_key();
+        let key_builder = StorageKey::builder().start_key(store.source_key(filter.get_start_key()));
+        let from_key = key_builder
+            .clone()
+            .lower_closed_bound_end_key(start)
+            .build();
+        let to_key = key_builder.upper_open_bound_end_key(end).build();
+        store.delete_range(&from_key.key(), &to_key.key())?;
+        info!("Dead letters of {} purged", filter.source);
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{graphql::tests::TestSchema, ingest::DeadLetter, storage::RawEventStore};
+
+    #[tokio::test]
+    async fn dead_letters_empty() {
+        let schema = TestSchema::new();
+        let query = r#"
+        {
+            deadLetters (filter: {source: "src 1"}, first: 1) {
+                edges {
+                    node {
+                        kind
+                    }
+                }
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(res.data.to_string(), "{deadLetters: {edges: []}}");
+    }
+
+    #[tokio::test]
+    async fn dead_letters_with_data() {
+        let schema = TestSchema::new();
+        let store = schema.db.dead_letter_store().unwrap();
+
+        insert_dead_letter(&store, "src 1", 1, 0);
+        insert_dead_letter(&store, "src 1", 1, 1);
+        insert_dead_letter(&store, "src 2", 2, 2);
+
+        let query = r#"
+        {
+            deadLetters (filter: {source: "src 1"}, first: 10) {
+                edges {
+                    node {
+                        source
+                        kind
+                        error
+                        rawEvent
+                    }
+                }
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{deadLetters: {edges: [{node: {source: \"src 1\", kind: \"log\", error: \"io error: unexpected end of file\", rawEvent: \"/w==\"}}, {node: {source: \"src 1\", kind: \"log\", error: \"io error: unexpected end of file\", rawEvent: \"/w==\"}}]}}"
+        );
+    }
+
+    #[tokio::test]
+    async fn purge_dead_letters() {
+        let schema = TestSchema::new();
+        let store = schema.db.dead_letter_store().unwrap();
+
+        insert_dead_letter(&store, "src 1", 1, 0);
+        insert_dead_letter(&store, "src 1", 3_000_000_000, 1);
+        insert_dead_letter(&store, "src 2", 1, 2);
+
+        let mutation = r#"
+        mutation {
+            purgeDeadLetters (filter: {source: "src 1", time: {end: "1970-01-01T00:00:02Z"}})
+        }"#;
+        let res = schema.execute(mutation).await;
+        assert_eq!(res.data.to_string(), "{purgeDeadLetters: true}");
+
+        let query = r#"
+        {
+            deadLetters (filter: {source: "src 1"}, first: 10) {
+                edges {
+                    node {
+                        timestamp
+                    }
+                }
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{deadLetters: {edges: [{node: {timestamp: \"1970-01-01T00:00:03+00:00\"}}]}}"
+        );
+
+        let query = r#"
+        {
+            deadLetters (filter: {source: "src 2"}, first: 10) {
+                edges {
+                    node {
+                        source
+                    }
+                }
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{deadLetters: {edges: [{node: {source: \"src 2\"}}]}}"
+        );
+    }
+
+    fn insert_dead_letter(
+        store: &RawEventStore<DeadLetter>,
+        source: &str,
+        timestamp: i64,
+        sequence: u32,
+    ) {
+        let mut key: Vec<u8> = Vec::new();
+        key.extend_from_slice(&store.assign_source_key(source).unwrap());
+        key.push(0);
+        key.extend(timestamp.to_be_bytes());
+        key.extend(sequence.to_be_bytes());
+
+        let dead_letter = DeadLetter {
+            source: source.to_string(),
+            kind: "log".to_string(),
+            timestamp,
+            error: "io error: unexpected end of file".to_string(),
+            raw_event: vec![0xff],
+        };
+        let ser_dead_letter = bincode::serialize(&dead_letter).unwrap();
+
+        store.append(&key, &ser_dead_letter).unwrap();
+    }
+}
@@ -2,7 +2,6 @@
 mod tests;
 
 use std::{
-    borrow::Cow,
     fmt::Display,
     fs::{self, File},
     io::Write,
@@ -40,15 +39,19 @@ use super::{
     check_address, check_agent_id, check_port,
     netflow::{millis_to_secs, tcp_flags},
     statistics::MAX_CORE_SIZE,
-    IpRange, NodeName, PortRange, RawEventFilter, TimeRange, TIMESTAMP_SIZE,
+    IpRange, NodeName, PortRange, RawEventFilter, TimeRange,
 };
 use crate::{
+    auth::{Role, RoleGuard},
     graphql::{
         client::derives::{export as exports, Export as Exports},
         events_in_cluster, impl_from_giganto_range_structs_for_graphql_client,
     },
     ingest::implement::EventFilter,
-    storage::{BoundaryIter, Database, Direction, KeyExtractor, RawEventStore, StorageKey},
+    storage::{
+        timestamp_from_key, BoundaryIter, Database, Direction, KeyExtractor, RawEventStore,
+        StorageKey,
+    },
 };
 
 const ADDRESS_PROTOCOL: [&str; 18] = [
@@ -88,6 +91,46 @@ const AGENT_PROTOCOL: [&str; 14] = [
     "file delete detected",
 ];
 const KIND_PROTOCOL: [&str; 2] = ["log", "secu log"];
+const EXPORT_PROTOCOL: [&str; 38] = [
+    "conn",
+    "dns",
+    "http",
+    "log",
+    "rdp",
+    "smtp",
+    "periodic time series",
+    "ntlm",
+    "kerberos",
+    "ssh",
+    "dce rpc",
+    "op_log",
+    "ftp",
+    "mqtt",
+    "ldap",
+    "tls",
+    "smb",
+    "nfs",
+    "bootp",
+    "dhcp",
+    "statistics",
+    "process create",
+    "file create time",
+    "network_connect",
+    "process terminate",
+    "image load",
+    "file create",
+    "registry value set",
+    "registry key rename",
+    "file create stream hash",
+    "pipe event",
+    "dns query",
+    "file delete",
+    "process tamper",
+    "file delete detected",
+    "netflow5",
+    "netflow9",
+    "secu log",
+];
 
 #[derive(Default)]
 pub(super) struct ExportQuery;
@@ -1594,12 +1637,50 @@ pub struct ExportFilter {
     resp_port: Option<PortRange>,
 }
 
+impl ExportFilter {
+    pub fn new(
+        protocol: String,
+        source_id: String,
+        kind: Option<String>,
+        agent_name: Option<String>,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            protocol,
+            source_id,
+            agent_name,
+            agent_id: None,
+            kind,
+            time: Some(TimeRange { start, end }),
+            orig_addr: None,
+            resp_addr: None,
+            orig_port: None,
+            resp_port: None,
+        }
+    }
+}
+
+// The `source_id` of `op_log` is in the form of `agent_name@source`, and oplog
+// events are keyed by source and agent name.
 impl KeyExtractor for ExportFilter {
     fn get_start_key(&self) -> &str {
+        if self.protocol == "op_log" {
+            return self
+                .source_id
+                .rsplit_once('@')
+                .map_or(&self.source_id, |(_, source)| source);
+        }
         &self.source_id
     }
 
     fn get_mid_key(&self) -> Option<Vec<u8>> {
+        if self.protocol == "op_log" {
+            return self
+                .source_id
+                .rsplit_once('@')
+                .map(|(agent_name, _)| agent_name.as_bytes().to_vec());
+        }
         let mut mid_key = Vec::new();
         if let Some(kind) = &self.kind {
             mid_key.extend_from_slice(kind.as_bytes());
@@ -1686,6 +1767,7 @@ fn handle_export(ctx: &Context<'_>, filter: &ExportFilter, export_type: String)
 
 #[Object]
 impl ExportQuery {
+    #[graphql(guard = "RoleGuard::new(Role::Export)")]
     async fn export(
         &self,
         ctx: &Context<'_>,
@@ -1738,7 +1820,6 @@ impl ExportQuery {
     }
 }
 
-#[allow(clippy::too_many_lines)]
 fn export_by_protocol(
     db: Database,
     filter: &ExportFilter,
@@ -1746,775 +1827,321 @@ fn export_by_protocol(
     export_done_path: PathBuf,
     export_progress_path: PathBuf,
 ) -> Result<()> {
+    if !EXPORT_PROTOCOL.contains(&filter.protocol.as_str()) {
+        return Err(anyhow!("{}: Unknown protocol", filter.protocol).into());
+    }
     let filter = filter.clone();
-    match filter.protocol.as_str() {
-        "conn" => tokio::spawn(async move {
-            if let Ok(store) = db.conn_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "dns" => tokio::spawn(async move {
-            if let Ok(store) = db.dns_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "http" => tokio::spawn(async move {
-            if let Ok(store) = db.http_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "log" => tokio::spawn(async move {
-            if let Ok(store) = db.log_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "rdp" => tokio::spawn(async move {
-            if let Ok(store) = db.rdp_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "smtp" => tokio::spawn(async move {
-            if let Ok(store) = db.smtp_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "periodic time series" => tokio::spawn(async move {
-            if let Ok(store) = db.periodic_time_series_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "ntlm" => tokio::spawn(async move {
-            if let Ok(store) = db.ntlm_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "kerberos" => tokio::spawn(async move {
-            if let Ok(store) = db.kerberos_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "ssh" => tokio::spawn(async move {
-            if let Ok(store) = db.ssh_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "dce rpc" => tokio::spawn(async move {
-            if let Ok(store) = db.dce_rpc_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "op_log" => tokio::spawn(async move {
-            if let Ok(store) = db.op_log_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "ftp" => tokio::spawn(async move {
-            if let Ok(store) = db.ftp_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "mqtt" => tokio::spawn(async move {
-            if let Ok(store) = db.mqtt_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "ldap" => tokio::spawn(async move {
-            if let Ok(store) = db.ldap_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "tls" => tokio::spawn(async move {
-            if let Ok(store) = db.tls_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "smb" => tokio::spawn(async move {
-            if let Ok(store) = db.smb_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "nfs" => tokio::spawn(async move {
-            if let Ok(store) = db.nfs_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "bootp" => tokio::spawn(async move {
-            if let Ok(store) = db.bootp_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "dhcp" => tokio::spawn(async move {
-            if let Ok(store) = db.dhcp_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "statistics" => tokio::spawn(async move {
-            if let Ok(store) = db.statistics_store() {
-                match process_statistics_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "process create" => tokio::spawn(async move {
-            if let Ok(store) = db.process_create_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "file create time" => tokio::spawn(async move {
-            if let Ok(store) = db.file_create_time_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "network_connect" => tokio::spawn(async move {
-            if let Ok(store) = db.network_connect_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
+    tokio::spawn(async move {
+        match export_to_file(
+            &db,
+            &filter,
+            &export_type,
+            &export_done_path,
+            &export_progress_path,
+        ) {
+            Ok(result) => {
+                info!("{}", result);
             }
-        }),
-        "process terminate" => tokio::spawn(async move {
-            if let Ok(store) = db.process_terminate_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "image load" => tokio::spawn(async move {
-            if let Ok(store) = db.image_load_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "file create" => tokio::spawn(async move {
-            if let Ok(store) = db.file_create_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "registry value set" => tokio::spawn(async move {
-            if let Ok(store) = db.registry_value_set_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "registry key rename" => tokio::spawn(async move {
-            if let Ok(store) = db.registry_key_rename_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "file create stream hash" => tokio::spawn(async move {
-            if let Ok(store) = db.file_create_stream_hash_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "pipe event" => tokio::spawn(async move {
-            if let Ok(store) = db.pipe_event_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "dns query" => tokio::spawn(async move {
-            if let Ok(store) = db.dns_query_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "file delete" => tokio::spawn(async move {
-            if let Ok(store) = db.file_delete_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "process tamper" => tokio::spawn(async move {
-            if let Ok(store) = db.process_tamper_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "file delete detected" => tokio::spawn(async move {
-            if let Ok(store) = db.file_delete_detected_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "netflow5" => tokio::spawn(async move {
-            if let Ok(store) = db.netflow5_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        "netflow9" => tokio::spawn(async move {
-            if let Ok(store) = db.netflow9_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
+            Err(e) => {
+                error!("Failed to export file: {:?}", e);
             }
-        }),
-        "secu log" => tokio::spawn(async move {
-            if let Ok(store) = db.secu_log_store() {
-                match process_export(
-                    &store,
-                    &filter,
-                    &export_type,
-                    &export_done_path,
-                    &export_progress_path,
-                ) {
-                    Ok(result) => {
-                        info!("{}", result);
-                    }
-                    Err(e) => {
-                        error!("Failed to export file: {:?}", e);
-                    }
-                }
-            } else {
-                error!("Failed to open db store");
-            }
-        }),
-        none => {
-            return Err(anyhow!("{}: Unknown protocol", none).into());
         }
-    };
+    });
     Ok(())
 }
 
+/// Writes the events matching the filter to `path` as JSON, one event per
+/// line, without running the GraphQL server.
+///
+/// # Errors
+///
+/// Returns an error if the protocol is unknown, or if the events cannot be
+/// read or written.
+pub fn export_json(db: &Database, filter: &ExportFilter, path: &Path) -> anyhow::Result<String> {
+    let progress_path = PathBuf::from(format!("{}.dump", path.display()));
+    export_to_file(db, filter, "json", path, &progress_path).map_err(|e| anyhow!(e.message))
+}
+
+/// Writes the events matching the filter to `export_done_path`, through
+/// `export_progress_path` while it is being written.
+fn export_to_file(
+    db: &Database,
+    filter: &ExportFilter,
+    export_type: &str,
+    export_done_path: &Path,
+    export_progress_path: &Path,
+) -> Result<String> {
+    match filter.protocol.as_str() {
+        "conn" => process_export(
+            &db.conn_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "dns" => process_export(
+            &db.dns_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "http" => process_export(
+            &db.http_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "log" => process_export(
+            &db.log_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "rdp" => process_export(
+            &db.rdp_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "smtp" => process_export(
+            &db.smtp_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "periodic time series" => process_export(
+            &db.periodic_time_series_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "ntlm" => process_export(
+            &db.ntlm_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "kerberos" => process_export(
+            &db.kerberos_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "ssh" => process_export(
+            &db.ssh_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "dce rpc" => process_export(
+            &db.dce_rpc_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "op_log" => process_export(
+            &db.op_log_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "ftp" => process_export(
+            &db.ftp_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "mqtt" => process_export(
+            &db.mqtt_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "ldap" => process_export(
+            &db.ldap_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "tls" => process_export(
+            &db.tls_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "smb" => process_export(
+            &db.smb_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "nfs" => process_export(
+            &db.nfs_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "bootp" => process_export(
+            &db.bootp_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "dhcp" => process_export(
+            &db.dhcp_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "statistics" => process_statistics_export(
+            &db.statistics_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "process create" => process_export(
+            &db.process_create_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "file create time" => process_export(
+            &db.file_create_time_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "network_connect" => process_export(
+            &db.network_connect_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "process terminate" => process_export(
+            &db.process_terminate_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "image load" => process_export(
+            &db.image_load_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "file create" => process_export(
+            &db.file_create_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "registry value set" => process_export(
+            &db.registry_value_set_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "registry key rename" => process_export(
+            &db.registry_key_rename_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "file create stream hash" => process_export(
+            &db.file_create_stream_hash_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "pipe event" => process_export(
+            &db.pipe_event_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "dns query" => process_export(
+            &db.dns_query_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "file delete" => process_export(
+            &db.file_delete_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "process tamper" => process_export(
+            &db.process_tamper_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "file delete detected" => process_export(
+            &db.file_delete_detected_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "netflow5" => process_export(
+            &db.netflow5_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "netflow9" => process_export(
+            &db.netflow9_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        "secu log" => process_export(
+            &db.secu_log_store()?,
+            filter,
+            export_type,
+            export_done_path,
+            export_progress_path,
+        ),
+        none => Err(anyhow!("{none}: Unknown protocol").into()),
+    }
+}
+
 fn process_export<T, N>(
     store: &RawEventStore<'_, T>,
     filter: &(impl RawEventFilter + KeyExtractor),
@@ -2528,7 +2155,7 @@ where
 {
     // generate storage search key
     let key_builder = StorageKey::builder()
-        .start_key(filter.get_start_key())
+        .start_key(store.source_key(filter.get_start_key()))
         .mid_key(filter.get_mid_key());
     let from_key = key_builder
         .clone()
@@ -2558,7 +2185,7 @@ fn process_statistics_export(
     let mut iter_vec = Vec::new();
     for core in 0..MAX_CORE_SIZE {
         let key_builder = StorageKey::builder()
-            .start_key(filter.get_start_key())
+            .start_key(store.source_key(filter.get_start_key()))
             .mid_key(Some(core.to_be_bytes().to_vec()));
         let from_key = key_builder
             .clone()
@@ -2651,8 +2278,7 @@ fn export_statistic_file(
         let (min_index, (key, value)) = iter_next_values.iter().enumerate().fold(
             (0, (&iter_next_values[0].0, &iter_next_values[0].1)),
             |(min_index, (min_key, min_value)), (index, (key, value))| {
-                if key[(key.len() - TIMESTAMP_SIZE)..] < min_key[(min_key.len() - TIMESTAMP_SIZE)..]
-                {
+                if timestamp_from_key(key).ok() < timestamp_from_key(min_key).ok() {
                     (index, (key, value))
                 } else {
                     (min_index, (min_key, min_value))
@@ -2720,35 +2346,139 @@ where
         value.source(),
         value.agent_id(),
     ) {
-        let (source, timestamp) = parse_key(key)?;
-        let timestamp = DateTime::from_timestamp_nanos(timestamp)
-            .format("%s%.9f")
-            .to_string();
-
-        match export_type {
-            "csv" => {
-                writeln!(writer, "{timestamp}\t{source}\t{value}")?;
-            }
-            "json" => {
-                let json_data = value.convert_json_output(timestamp, source.to_string())?;
-                let json_data = serde_json::to_string(&json_data)?;
-                writeln!(writer, "{json_data}")?;
-            }
-            _ => {}
+        let (source, timestamp) = parse_key(filter, key)?;
+        if let Some(line) = format_event(export_type, source, timestamp, value)? {
+            writeln!(writer, "{line}")?;
         }
     }
     Ok(())
 }
 
-fn parse_key(key: &[u8]) -> anyhow::Result<(Cow<str>, i64)> {
-    if let Some(pos) = key.iter().position(|x| *x == 0) {
-        if let Some(s) = key.get(..pos) {
-            let source = String::from_utf8_lossy(s);
-            if let Some(t) = key.get(key.len() - 8..) {
-                let timestamp = i64::from_be_bytes(t.try_into()?);
-                return Ok((source, timestamp));
-            };
+/// Formats the event as a line of the export file of `export_type`, or returns
+/// `None` if the type is neither `csv` nor `json`.
+fn format_event<T, N>(
+    export_type: &str,
+    source: &str,
+    timestamp: i64,
+    value: &T,
+) -> anyhow::Result<Option<String>>
+where
+    T: Display + JsonOutput<N>,
+    N: Serialize,
+{
+    let timestamp = DateTime::from_timestamp_nanos(timestamp)
+        .format("%s%.9f")
+        .to_string();
+    match export_type {
+        "csv" => Ok(Some(format!("{timestamp}\t{source}\t{value}"))),
+        "json" => {
+            let json_data = value
+                .convert_json_output(timestamp, source.to_string())
+                .map_err(|e| anyhow!(e.message))?;
+            Ok(Some(serde_json::to_string(&json_data)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Decodes the raw event of the column family and formats it as a line of the
+/// export file of `export_type`.
+///
+/// Returns `None` if the events of the column family cannot be exported.
+///
+/// # Errors
+///
+/// Returns an error if the raw event cannot be decoded.
+pub fn format_raw_event(
+    cf_name: &str,
+    export_type: &str,
+    source: &str,
+    timestamp: i64,
+    raw_event: &[u8],
+) -> anyhow::Result<Option<String>> {
+    match cf_name {
+        "conn" => format_raw::<Conn, _>(export_type, source, timestamp, raw_event),
+        "dns" => format_raw::<Dns, _>(export_type, source, timestamp, raw_event),
+        "log" => format_raw::<Log, _>(export_type, source, timestamp, raw_event),
+        "http" => format_raw::<Http, _>(export_type, source, timestamp, raw_event),
+        "rdp" => format_raw::<Rdp, _>(export_type, source, timestamp, raw_event),
+        "periodic time series" => {
+            format_raw::<PeriodicTimeSeries, _>(export_type, source, timestamp, raw_event)
+        }
+        "smtp" => format_raw::<Smtp, _>(export_type, source, timestamp, raw_event),
+        "ntlm" => format_raw::<Ntlm, _>(export_type, source, timestamp, raw_event),
+        "kerberos" => format_raw::<Kerberos, _>(export_type, source, timestamp, raw_event),
+        "ssh" => format_raw::<Ssh, _>(export_type, source, timestamp, raw_event),
+        "dce rpc" => format_raw::<DceRpc, _>(export_type, source, timestamp, raw_event),
+        "statistics" => format_raw::<Statistics, _>(export_type, source, timestamp, raw_event),
+        "oplog" => format_raw::<OpLog, _>(export_type, source, timestamp, raw_event),
+        "ftp" => format_raw::<Ftp, _>(export_type, source, timestamp, raw_event),
+        "mqtt" => format_raw::<Mqtt, _>(export_type, source, timestamp, raw_event),
+        "ldap" => format_raw::<Ldap, _>(export_type, source, timestamp, raw_event),
+        "tls" => format_raw::<Tls, _>(export_type, source, timestamp, raw_event),
+        "smb" => format_raw::<Smb, _>(export_type, source, timestamp, raw_event),
+        "nfs" => format_raw::<Nfs, _>(export_type, source, timestamp, raw_event),
+        "bootp" => format_raw::<Bootp, _>(export_type, source, timestamp, raw_event),
+        "dhcp" => format_raw::<Dhcp, _>(export_type, source, timestamp, raw_event),
+        "process create" => {
+            format_raw::<ProcessCreate, _>(export_type, source, timestamp, raw_event)
+        }
+        "file create time" => {
+            format_raw::<FileCreationTimeChanged, _>(export_type, source, timestamp, raw_event)
         }
+        "network connect" => {
+            format_raw::<NetworkConnection, _>(export_type, source, timestamp, raw_event)
+        }
+        "process terminate" => {
+            format_raw::<ProcessTerminated, _>(export_type, source, timestamp, raw_event)
+        }
+        "image load" => format_raw::<ImageLoaded, _>(export_type, source, timestamp, raw_event),
+        "file create" => format_raw::<FileCreate, _>(export_type, source, timestamp, raw_event),
+        "registry value set" => {
+            format_raw::<RegistryValueSet, _>(export_type, source, timestamp, raw_event)
+        }
+        "registry key rename" => {
+            format_raw::<RegistryKeyValueRename, _>(export_type, source, timestamp, raw_event)
+        }
+        "file create stream hash" => {
+            format_raw::<FileCreateStreamHash, _>(export_type, source, timestamp, raw_event)
+        }
+        "pipe event" => format_raw::<PipeEvent, _>(export_type, source, timestamp, raw_event),
+        "dns query" => format_raw::<DnsEvent, _>(export_type, source, timestamp, raw_event),
+        "file delete" => format_raw::<FileDelete, _>(export_type, source, timestamp, raw_event),
+        "process tamper" => {
+            format_raw::<ProcessTampering, _>(export_type, source, timestamp, raw_event)
+        }
+        "file delete detected" => {
+            format_raw::<FileDeleteDetected, _>(export_type, source, timestamp, raw_event)
+        }
+        "netflow5" => format_raw::<Netflow5, _>(export_type, source, timestamp, raw_event),
+        "netflow9" => format_raw::<Netflow9, _>(export_type, source, timestamp, raw_event),
+        "seculog" => format_raw::<SecuLog, _>(export_type, source, timestamp, raw_event),
+        _ => Ok(None),
+    }
+}
+
+fn format_raw<T, N>(
+    export_type: &str,
+    source: &str,
+    timestamp: i64,
+    raw_event: &[u8],
+) -> anyhow::Result<Option<String>>
+where
+    T: DeserializeOwned + Display + JsonOutput<N>,
+    N: Serialize,
+{
+    let value = bincode::deserialize::<T>(raw_event)?;
+    format_event(export_type, source, timestamp, &value)
+}
+
+// The key starts with the ID of the source, not the source itself, so the
+// source is taken from the filter the key was looked up with.
+fn parse_key<'a>(filter: &'a impl KeyExtractor, key: &[u8]) -> anyhow::Result<(&'a str, i64)> {
+    if key.contains(&0) {
+        let timestamp = timestamp_from_key(key)?;
+        return Ok((filter.get_start_key(), timestamp));
     }
     Err(anyhow!("Invalid key"))
 }
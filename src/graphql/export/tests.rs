@@ -125,9 +125,10 @@ async fn export_conn() {
 
 fn insert_conn_raw_event(store: &RawEventStore<Conn>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let tmp_dur = Duration::nanoseconds(12345);
     let conn_body = Conn {
@@ -202,9 +203,10 @@ async fn export_dns() {
 
 fn insert_dns_raw_event(store: &RawEventStore<Dns>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let dns_body = Dns {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -282,9 +284,10 @@ async fn export_http() {
 
 fn insert_http_raw_event(store: &RawEventStore<Http>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let http_body = Http {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -372,9 +375,10 @@ async fn export_rdp() {
 
 fn insert_rdp_raw_event(store: &RawEventStore<Rdp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let rdp_body = Rdp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -441,9 +445,10 @@ async fn export_smtp() {
 
 fn insert_smtp_raw_event(store: &RawEventStore<Smtp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let smtp_body = Smtp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -516,9 +521,10 @@ async fn export_ntlm() {
 
 fn insert_ntlm_raw_event(store: &RawEventStore<Ntlm>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let ntlm_body = Ntlm {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -589,9 +595,10 @@ async fn export_kerberos() {
 
 fn insert_kerberos_raw_event(store: &RawEventStore<Kerberos>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let kerberos_body = Kerberos {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -665,9 +672,10 @@ async fn export_ssh() {
 }
 fn insert_ssh_raw_event(store: &RawEventStore<Ssh>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let ssh_body = Ssh {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -745,9 +753,10 @@ async fn export_dce_rpc() {
 }
 fn insert_dce_rpc_raw_event(store: &RawEventStore<DceRpc>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let dce_rpc_body = DceRpc {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -825,11 +834,12 @@ fn insert_log_raw_event(
     body: &[u8],
 ) {
     let mut key: Vec<u8> = Vec::new();
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend_from_slice(kind.as_bytes());
     key.push(0);
     key.extend_from_slice(&timestamp.to_be_bytes());
+    key.extend_from_slice(&0_u32.to_be_bytes());
     let log_body = Log {
         kind: kind.to_string(),
         log: body.to_vec(),
@@ -895,6 +905,7 @@ fn insert_time_series(
     key.extend_from_slice(id.as_bytes());
     key.push(0);
     key.extend_from_slice(&start.to_be_bytes());
+    key.extend_from_slice(&0_u32.to_be_bytes());
     let time_series_data = PeriodicTimeSeries {
         id: id.to_string(),
         data,
@@ -941,9 +952,12 @@ async fn export_op_log() {
 fn insert_op_log_raw_event(store: &RawEventStore<OpLog>, agent_name: &str, timestamp: i64) {
     let mut key: Vec<u8> = Vec::new();
     let agent_id = format!("{agent_name}@src1");
-    key.extend_from_slice(agent_id.as_bytes());
+    key.extend_from_slice(&store.assign_source_key("src1").unwrap());
+    key.push(0);
+    key.extend_from_slice(agent_name.as_bytes());
     key.push(0);
     key.extend_from_slice(&timestamp.to_be_bytes());
+    key.extend_from_slice(&0_u32.to_be_bytes());
 
     let op_log_body = OpLog {
         agent_name: agent_id.to_string(),
@@ -1007,9 +1021,10 @@ async fn export_ftp() {
 
 fn insert_ftp_raw_event(store: &RawEventStore<Ftp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let ftp_body = Ftp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1087,9 +1102,10 @@ async fn export_mqtt() {
 
 fn insert_mqtt_raw_event(store: &RawEventStore<Mqtt>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let mqtt_body = Mqtt {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1161,9 +1177,10 @@ async fn export_ldap() {
 
 fn insert_ldap_raw_event(store: &RawEventStore<Ldap>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let ldap_body = Ldap {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1236,9 +1253,10 @@ async fn export_tls() {
 
 fn insert_tls_raw_event(store: &RawEventStore<Tls>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let tls_body = Tls {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1325,9 +1343,10 @@ async fn export_smb() {
 
 fn insert_smb_raw_event(store: &RawEventStore<Smb>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let smb_body = Smb {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1404,9 +1423,10 @@ async fn export_nfs() {
 
 fn insert_nfs_raw_event(store: &RawEventStore<Nfs>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let nfs_body = Nfs {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1474,9 +1494,10 @@ async fn export_bootp() {
 
 fn insert_bootp_raw_event(store: &RawEventStore<Bootp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let bootp_body = Bootp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1553,9 +1574,10 @@ async fn export_dhcp() {
 
 fn insert_dhcp_raw_event(store: &RawEventStore<Dhcp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let dhcp_body = Dhcp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
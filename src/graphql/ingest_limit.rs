@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, Object, Result, SimpleObject, StringNumber};
+
+use super::status::limit_action_name;
+use crate::ingest::{IngestLimiter, LimitUsage as LimitUsageEntry};
+
+#[derive(Default)]
+pub(super) struct IngestLimitQuery;
+
+/// The usage of an ingest limit by a source.
+#[derive(SimpleObject, Debug)]
+struct LimitUsage {
+    source: String,
+    /// The kind of the events counted, or `null` if the limit counts the
+    /// events of all the kinds together.
+    kind: Option<String>,
+    events_per_sec_limit: Option<StringNumber<u64>>,
+    bytes_per_sec_limit: Option<StringNumber<u64>>,
+    daily_bytes_limit: Option<StringNumber<u64>>,
+    /// What the ingest server does with the events over the limit, either
+    /// `delay` or `drop`.
+    action: String,
+    /// The events received in the last complete second.
+    events_per_sec: StringNumber<u64>,
+    /// The bytes received in the last complete second.
+    bytes_per_sec: StringNumber<u64>,
+    /// The bytes received today in UTC.
+    daily_bytes: StringNumber<u64>,
+    dropped_events: StringNumber<u64>,
+    dropped_bytes: StringNumber<u64>,
+    /// How many times the events of the source were held back by the limit.
+    delays: StringNumber<u64>,
+}
+
+impl From<LimitUsageEntry> for LimitUsage {
+    fn from(usage: LimitUsageEntry) -> Self {
+        Self {
+            source: usage.source,
+            kind: usage.kind,
+            events_per_sec_limit: usage.limit.events_per_sec.map(StringNumber),
+            bytes_per_sec_limit: usage.limit.bytes_per_sec.map(StringNumber),
+            daily_bytes_limit: usage.limit.daily_bytes.map(StringNumber),
+            action: limit_action_name(usage.limit.action),
+            events_per_sec: StringNumber(usage.events_per_sec),
+            bytes_per_sec: StringNumber(usage.bytes_per_sec),
+            daily_bytes: StringNumber(usage.daily_bytes),
+            dropped_events: StringNumber(usage.dropped_events),
+            dropped_bytes: StringNumber(usage.dropped_bytes),
+            delays: StringNumber(usage.delays),
+        }
+    }
+}
+
+#[Object]
+impl IngestLimitQuery {
+    /// Returns the usage of the ingest limits by the sources that have sent
+    /// events since giganto started, in the order of the `ingest_limits` in
+    /// the configuration.
+    ///
+    /// If `source` is given, only the usage by that source is returned.
+    #[allow(clippy::unused_async)]
+    async fn ingest_usage<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        source: Option<String>,
+    ) -> Result<Vec<LimitUsage>> {
+        let limiter = ctx.data::<Arc<IngestLimiter>>()?;
+        Ok(limiter
+            .usage()
+            .into_iter()
+            .filter(|usage| source.as_ref().map_or(true, |s| *s == usage.source))
+            .filter(|usage| crate::auth::is_source_allowed(ctx, &usage.source))
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphql::tests::TestSchema;
+
+    #[tokio::test]
+    async fn ingest_usage() {
+        let schema = TestSchema::new();
+        let query = r#"
+        {
+            ingestUsage(source: "src 1") {
+                source
+                kind
+                dailyBytesLimit
+                action
+                dailyBytes
+                droppedEvents
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(res.data.to_string(), "{ingestUsage: []}");
+
+        schema.ingest_limiter.admit("src 1", "conn", 3, 300);
+        schema.ingest_limiter.admit("src 1", "conn", 10, 1000);
+        schema.ingest_limiter.admit("src 2", "conn", 1, 100);
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{ingestUsage: [{source: \"src 1\", kind: null, dailyBytesLimit: \"1000\", action: \"drop\", dailyBytes: \"300\", droppedEvents: \"10\"}]}"
+        );
+    }
+}
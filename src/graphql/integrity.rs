@@ -0,0 +1,252 @@
+use anyhow::anyhow;
+use async_graphql::{Context, Object, Result, SimpleObject, StringNumber};
+use chrono::{DateTime, Utc};
+use tokio::task;
+use tracing::error;
+
+use crate::{
+    auth::{Role, RoleGuard},
+    storage::{CfIntegrity, Database, IntegrityReport, SourceIntegrity},
+    StorageIntegrity,
+};
+
+#[derive(Default)]
+pub(super) struct IntegrityQuery;
+
+#[derive(Default)]
+pub(super) struct IntegrityMutation;
+
+/// The result of the last integrity check of the stored records.
+#[derive(SimpleObject, Debug)]
+struct StorageIntegrityReport {
+    started_at: DateTime<Utc>,
+    /// `null` while the check is running.
+    finished_at: Option<DateTime<Utc>>,
+    /// Whether the invalid records are moved to the quarantine.
+    quarantine: bool,
+    column_families: Vec<ColumnFamilyIntegrity>,
+    /// The error that stopped the check, if any.
+    error: Option<String>,
+}
+
+/// The integrity of the records in a column family.
+#[derive(SimpleObject, Debug)]
+struct ColumnFamilyIntegrity {
+    name: String,
+    records: StringNumber<u64>,
+    /// The number of records whose keys have no source or timestamp.
+    invalid_keys: StringNumber<u64>,
+    /// The number of records whose values cannot be decoded.
+    undecodable: StringNumber<u64>,
+    quarantined: StringNumber<u64>,
+    read_error: Option<String>,
+    /// The sources with invalid records.
+    sources: Vec<SourceIntegrityCount>,
+}
+
+/// The number of invalid records of a source in a column family.
+#[derive(SimpleObject, Debug)]
+struct SourceIntegrityCount {
+    source: String,
+    invalid_keys: StringNumber<u64>,
+    undecodable: StringNumber<u64>,
+}
+
+impl From<IntegrityReport> for StorageIntegrityReport {
+    fn from(report: IntegrityReport) -> Self {
+        Self {
+            started_at: report.started_at,
+            finished_at: report.finished_at,
+            quarantine: report.quarantine,
+            column_families: report.column_families.into_iter().map(Into::into).collect(),
+            error: report.error,
+        }
+    }
+}
+
+impl From<CfIntegrity> for ColumnFamilyIntegrity {
+    fn from(cf: CfIntegrity) -> Self {
+        Self {
+            name: cf.name,
+            records: StringNumber(cf.records),
+            invalid_keys: StringNumber(cf.invalid_keys),
+            undecodable: StringNumber(cf.undecodable),
+            quarantined: StringNumber(cf.quarantined),
+            read_error: cf.read_error,
+            sources: cf.sources.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<SourceIntegrity> for SourceIntegrityCount {
+    fn from(source: SourceIntegrity) -> Self {
+        Self {
+            source: source.source,
+            invalid_keys: StringNumber(source.invalid_keys),
+            undecodable: StringNumber(source.undecodable),
+        }
+    }
+}
+
+#[Object]
+impl IntegrityQuery {
+    /// Returns the result of the last integrity check, or `null` if no check
+    /// has been run since giganto started.
+    async fn storage_integrity_report<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> Result<Option<StorageIntegrityReport>> {
+        crate::auth::check_all_sources(ctx)?;
+        let storage_integrity = ctx.data::<StorageIntegrity>()?;
+        let report = storage_integrity.read().await.clone();
+        Ok(report.map(Into::into))
+    }
+}
+
+#[Object]
+impl IntegrityMutation {
+    /// Starts checking that every stored record has a valid key and can be
+    /// decoded. The result is returned by `storageIntegrityReport`.
+    ///
+    /// If `quarantine` is true, the invalid records are moved to the
+    /// quarantine column family so that they are no longer returned.
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn check_storage_integrity<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        #[graphql(default = false)] quarantine: bool,
+    ) -> Result<bool> {
+        let db = ctx.data::<Database>()?.clone();
+        let storage_integrity = ctx.data::<StorageIntegrity>()?.clone();
+        {
+            let mut report = storage_integrity.write().await;
+            if report.as_ref().is_some_and(IntegrityReport::is_running) {
+                return Err(anyhow!("integrity check is already running").into());
+            }
+            *report = Some(IntegrityReport::new(quarantine));
+        }
+
+        task::spawn(async move {
+            let result = task::spawn_blocking(move || db.check_integrity(quarantine)).await;
+            let mut report = storage_integrity.write().await;
+            let Some(report) = report.as_mut() else {
+                return;
+            };
+            match result {
+                Ok(Ok(column_families)) => report.column_families = column_families,
+                Ok(Err(e)) => {
+                    error!("Integrity check failed: {e}");
+                    report.error = Some(e.to_string());
+                }
+                Err(e) => {
+                    error!("Integrity check terminated unexpectedly: {e}");
+                    report.error = Some(e.to_string());
+                }
+            }
+            report.finished_at = Some(Utc::now());
+        });
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::Duration};
+
+    use async_graphql::Request;
+
+    use crate::{
+        auth::{Caller, Role},
+        graphql::tests::TestSchema,
+    };
+
+    #[tokio::test]
+    async fn storage_integrity_report() {
+        let schema = TestSchema::new();
+        let query = r"
+        {
+            storageIntegrityReport {
+                finishedAt
+            }
+        }";
+        let res = schema.execute(query).await;
+        assert_eq!(res.data.to_string(), "{storageIntegrityReport: null}");
+
+        let store = schema.db.conn_store().unwrap();
+        store.append(b"src 1\x00short", b"invalid").unwrap();
+
+        let mutation = r"
+        mutation {
+            checkStorageIntegrity(quarantine: true)
+        }";
+        let res = schema.execute(mutation).await;
+        assert_eq!(res.data.to_string(), "{checkStorageIntegrity: true}");
+
+        let query = r"
+        {
+            storageIntegrityReport {
+                quarantine
+                error
+                columnFamilies {
+                    name
+                    records
+                    invalidKeys
+                    undecodable
+                    quarantined
+                    sources {
+                        source
+                        invalidKeys
+                    }
+                }
+            }
+        }";
+        let mut finished = false;
+        for _ in 0..100 {
+            let res = schema
+                .execute("{ storageIntegrityReport { finishedAt } }")
+                .await;
+            if res.data.to_string() != "{storageIntegrityReport: {finishedAt: null}}" {
+                finished = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(finished);
+
+        let res = schema.execute(query).await;
+        let data = res.data.into_json().unwrap();
+        let report = &data["storageIntegrityReport"];
+        assert_eq!(report["quarantine"], true);
+        assert!(report["error"].is_null());
+        let conn = report["columnFamilies"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|cf| cf["name"] == "conn")
+            .unwrap();
+        assert_eq!(conn["records"], "1");
+        assert_eq!(conn["invalidKeys"], "1");
+        assert_eq!(conn["undecodable"], "0");
+        assert_eq!(conn["quarantined"], "1");
+        assert_eq!(conn["sources"][0]["source"], "src 1");
+        assert_eq!(conn["sources"][0]["invalidKeys"], "1");
+        assert!(store.iter_forward().next().is_none());
+    }
+
+    #[tokio::test]
+    async fn storage_integrity_report_sources_not_allowed() {
+        let schema = TestSchema::new();
+        let caller = Caller::new(
+            "analyst".to_string(),
+            Role::Read,
+            Some(HashSet::from(["src 1".to_string()])),
+        );
+        let request = Request::new("{ storageIntegrityReport { finishedAt } }").data(caller);
+        let res = schema.schema.execute(request).await;
+        assert_eq!(
+            res.errors.first().unwrap().message,
+            "a source must be given to read the data of"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use async_graphql::{Context, Object, Result, SimpleObject, ID};
+use chrono::{DateTime, Utc};
+
+use super::TimeRange;
+use crate::{
+    auth::{Role, RoleGuard},
+    storage::{Database, LegalHold},
+};
+
+#[derive(Default)]
+pub(super) struct LegalHoldQuery;
+
+#[derive(Default)]
+pub(super) struct LegalHoldMutation;
+
+/// A hold that keeps the data of a source in a time range from being deleted
+/// by retention.
+#[derive(SimpleObject, Debug)]
+#[graphql(name = "LegalHold")]
+struct LegalHoldInfo {
+    id: ID,
+    source: String,
+    /// The held record types, such as `conn` and `log`. All record types are
+    /// held if empty.
+    kinds: Vec<String>,
+    /// The start of the held time range, inclusive. `null` for no limit.
+    start: Option<DateTime<Utc>>,
+    /// The end of the held time range, exclusive. `null` for no limit.
+    end: Option<DateTime<Utc>>,
+    reason: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<LegalHold> for LegalHoldInfo {
+    fn from(hold: LegalHold) -> Self {
+        Self {
+            id: ID(hold.id.to_string()),
+            source: hold.source,
+            kinds: hold.kinds,
+            start: hold.start,
+            end: hold.end,
+            reason: hold.reason,
+            created_at: hold.created_at,
+        }
+    }
+}
+
+#[Object]
+impl LegalHoldQuery {
+    /// Returns the legal holds in the order of their creation.
+    #[allow(clippy::unused_async)]
+    async fn legal_holds<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<LegalHoldInfo>> {
+        let db = ctx.data::<Database>()?;
+        let holds = db.legal_hold_store()?.list()?;
+        Ok(holds
+            .into_iter()
+            .filter(|hold| crate::auth::is_source_allowed(ctx, &hold.source))
+            .map(Into::into)
+            .collect())
+    }
+}
+
+#[Object]
+impl LegalHoldMutation {
+    /// Creates a legal hold on the data of the source in the time range, which
+    /// retention skips until the hold is released.
+    ///
+    /// If `kinds` is given, only the data of those record types are held.
+    #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn create_legal_hold<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        source: String,
+        kinds: Option<Vec<String>>,
+        time: Option<TimeRange>,
+        reason: String,
+    ) -> Result<LegalHoldInfo> {
+        let db = ctx.data::<Database>()?;
+        let (start, end) = time.map_or((None, None), |time| (time.start, time.end));
+        let hold = db.legal_hold_store()?.create(
+            &source,
+            kinds.unwrap_or_default(),
+            start,
+            end,
+            &reason,
+        )?;
+        Ok(hold.into())
+    }
+
+    /// Releases the legal hold, and returns it if it existed.
+    #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn release_legal_hold<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        id: ID,
+    ) -> Result<Option<LegalHoldInfo>> {
+        let db = ctx.data::<Database>()?;
+        let id = id.parse::<u32>()?;
+        let hold = db.legal_hold_store()?.release(id)?;
+        Ok(hold.map(Into::into))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphql::tests::TestSchema;
+
+    #[tokio::test]
+    async fn legal_holds() {
+        let schema = TestSchema::new();
+        let mutation = r#"
+        mutation {
+            createLegalHold(
+                source: "src 1",
+                kinds: ["conn"],
+                time: { start: "2024-01-01T00:00:00Z", end: "2024-02-01T00:00:00Z" },
+                reason: "incident 1"
+            ) {
+                id
+                kinds
+                start
+            }
+        }"#;
+        let res = schema.execute(mutation).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{createLegalHold: {id: \"0\", kinds: [\"conn\"], start: \"2024-01-01T00:00:00+00:00\"}}"
+        );
+
+        let mutation = r#"
+        mutation {
+            createLegalHold(source: "src 1", kinds: ["unknown"], reason: "incident 2") {
+                id
+            }
+        }"#;
+        let res = schema.execute(mutation).await;
+        assert!(res.is_err());
+
+        let query = r"
+        {
+            legalHolds {
+                id
+                source
+                end
+                reason
+            }
+        }";
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{legalHolds: [{id: \"0\", source: \"src 1\", end: \"2024-02-01T00:00:00+00:00\", reason: \"incident 1\"}]}"
+        );
+
+        let mutation = r#"
+        mutation {
+            releaseLegalHold(id: "0") {
+                id
+            }
+        }"#;
+        let res = schema.execute(mutation).await;
+        assert_eq!(res.data.to_string(), "{releaseLegalHold: {id: \"0\"}}");
+
+        let res = schema.execute(query).await;
+        assert_eq!(res.data.to_string(), "{legalHolds: []}");
+    }
+}
@@ -79,14 +79,19 @@ pub struct OpLogFilter {
     contents: Option<String>,
 }
 
+// `agent_id` is in the form of `agent_name@source`, and oplog events are keyed
+// by source and agent name.
 impl KeyExtractor for OpLogFilter {
     fn get_start_key(&self) -> &str {
-        &self.agent_id
+        self.agent_id
+            .rsplit_once('@')
+            .map_or(&self.agent_id, |(_, source)| source)
     }
 
-    // oplog event don't use mid key
     fn get_mid_key(&self) -> Option<Vec<u8>> {
-        None
+        self.agent_id
+            .rsplit_once('@')
+            .map(|(agent_name, _)| agent_name.as_bytes().to_vec())
     }
 
     fn get_range_end_key(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
@@ -224,6 +229,7 @@ impl LogQuery {
         first: Option<i32>,
         last: Option<i32>,
     ) -> Result<Connection<String, OpLogRawEvent>> {
+        crate::auth::check_source(ctx, filter.get_start_key())?;
         let db = ctx.data::<Database>()?;
         let store = db.op_log_store()?;
 
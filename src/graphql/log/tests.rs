@@ -1,8 +1,12 @@
+use std::collections::HashSet;
+
+use async_graphql::Request;
 use chrono::DateTime;
 use giganto_client::ingest::log::{Log, OpLog, OpLogLevel};
 
 use super::{base64_engine, Engine, LogFilter, LogRawEvent, OpLogFilter, OpLogRawEvent};
 use crate::{
+    auth::{Caller, Role},
     graphql::{tests::TestSchema, TimeRange},
     storage::RawEventStore,
 };
@@ -474,6 +478,32 @@ async fn oplog_with_data() {
     );
 }
 
+#[tokio::test]
+async fn oplog_source_not_allowed() {
+    let schema = TestSchema::new();
+    let query = r#"
+        {
+            opLogRawEvents (filter: {agentId: "giganto@src 1", logLevel: "Info"}, first: 1) {
+                edges {
+                    node {
+                        level
+                    }
+                }
+            }
+        }"#;
+    let caller = Caller::new(
+        "analyst".to_string(),
+        Role::Read,
+        Some(HashSet::from(["src 2".to_string()])),
+    );
+    let request = Request::new(query).data(caller);
+    let res = schema.schema.execute(request).await;
+    assert_eq!(
+        res.errors.first().unwrap().message,
+        "not allowed to read the data of src 1"
+    );
+}
+
 #[tokio::test]
 async fn load_oplog() {
     let schema = TestSchema::new();
@@ -515,11 +545,12 @@ fn insert_log_raw_event(
     body: &[u8],
 ) {
     let mut key: Vec<u8> = Vec::new();
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend_from_slice(kind.as_bytes());
     key.push(0);
     key.extend_from_slice(&timestamp.to_be_bytes());
+    key.extend_from_slice(&0_u32.to_be_bytes());
     let log_body = Log {
         kind: kind.to_string(),
         log: body.to_vec(),
@@ -531,9 +562,12 @@ fn insert_log_raw_event(
 fn insert_oplog_raw_event(store: &RawEventStore<OpLog>, agent_name: &str, timestamp: i64) {
     let mut key: Vec<u8> = Vec::new();
     let agent_id = format!("{agent_name}@src 1");
-    key.extend_from_slice(agent_id.as_bytes());
+    key.extend_from_slice(&store.assign_source_key("src 1").unwrap());
+    key.push(0);
+    key.extend_from_slice(agent_name.as_bytes());
     key.push(0);
     key.extend_from_slice(&timestamp.to_be_bytes());
+    key.extend_from_slice(&0_u32.to_be_bytes());
 
     let oplog_body = OpLog {
         agent_name: agent_id.to_string(),
@@ -127,7 +127,7 @@ impl RawEventFilter for SearchFilter {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [conn_raw_events::ConnRawEventsConnRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnConnRawEvent])]
-struct ConnRawEvent {
+pub(super) struct ConnRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -148,7 +148,7 @@ struct ConnRawEvent {
 #[allow(clippy::struct_excessive_bools)]
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [dns_raw_events::DnsRawEventsDnsRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnDnsRawEvent])]
-struct DnsRawEvent {
+pub(super) struct DnsRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -172,7 +172,7 @@ struct DnsRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [http_raw_events::HttpRawEventsHttpRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnHttpRawEvent])]
-struct HttpRawEvent {
+pub(super) struct HttpRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -206,7 +206,7 @@ struct HttpRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [rdp_raw_events::RdpRawEventsRdpRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnRdpRawEvent])]
-struct RdpRawEvent {
+pub(super) struct RdpRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -219,7 +219,7 @@ struct RdpRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [smtp_raw_events::SmtpRawEventsSmtpRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnSmtpRawEvent])]
-struct SmtpRawEvent {
+pub(super) struct SmtpRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -238,7 +238,7 @@ struct SmtpRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [ntlm_raw_events::NtlmRawEventsNtlmRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnNtlmRawEvent])]
-struct NtlmRawEvent {
+pub(super) struct NtlmRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -255,7 +255,7 @@ struct NtlmRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [kerberos_raw_events::KerberosRawEventsKerberosRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnKerberosRawEvent])]
-struct KerberosRawEvent {
+pub(super) struct KerberosRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -276,7 +276,7 @@ struct KerberosRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [ssh_raw_events::SshRawEventsSshRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnSshRawEvent])]
-struct SshRawEvent {
+pub(super) struct SshRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -301,7 +301,7 @@ struct SshRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [dce_rpc_raw_events::DceRpcRawEventsDceRpcRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnDceRpcRawEvent])]
-struct DceRpcRawEvent {
+pub(super) struct DceRpcRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -317,7 +317,7 @@ struct DceRpcRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [ftp_raw_events::FtpRawEventsFtpRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnFtpRawEvent])]
-struct FtpRawEvent {
+pub(super) struct FtpRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -341,7 +341,7 @@ struct FtpRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [mqtt_raw_events::MqttRawEventsMqttRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnMqttRawEvent])]
-struct MqttRawEvent {
+pub(super) struct MqttRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -359,7 +359,7 @@ struct MqttRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [ldap_raw_events::LdapRawEventsLdapRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnLdapRawEvent])]
-struct LdapRawEvent {
+pub(super) struct LdapRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -378,7 +378,7 @@ struct LdapRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [tls_raw_events::TlsRawEventsTlsRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnTlsRawEvent])]
-struct TlsRawEvent {
+pub(super) struct TlsRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -412,7 +412,7 @@ struct TlsRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [smb_raw_events::SmbRawEventsSmbRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnSmbRawEvent])]
-struct SmbRawEvent {
+pub(super) struct SmbRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -435,7 +435,7 @@ struct SmbRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [nfs_raw_events::NfsRawEventsNfsRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnNfsRawEvent])]
-struct NfsRawEvent {
+pub(super) struct NfsRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -449,7 +449,7 @@ struct NfsRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [bootp_raw_events::BootpRawEventsBootpRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnBootpRawEvent])]
-struct BootpRawEvent {
+pub(super) struct BootpRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
@@ -472,7 +472,7 @@ struct BootpRawEvent {
 
 #[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
 #[graphql_client_type(names = [dhcp_raw_events::DhcpRawEventsDhcpRawEventsEdgesNode, network_raw_events::NetworkRawEventsNetworkRawEventsEdgesNodeOnDhcpRawEvent])]
-struct DhcpRawEvent {
+pub(super) struct DhcpRawEvent {
     timestamp: DateTime<Utc>,
     orig_addr: String,
     orig_port: u16,
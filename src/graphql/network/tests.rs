@@ -151,9 +151,10 @@ async fn conn_with_data() {
 
 fn insert_conn_raw_event(store: &RawEventStore<Conn>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let tmp_dur = Duration::nanoseconds(12345);
     let conn_body = Conn {
@@ -415,9 +416,10 @@ async fn dns_with_data() {
 
 fn insert_dns_raw_event(store: &RawEventStore<Dns>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let dns_body = Dns {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -672,9 +674,10 @@ async fn http_with_data() {
 
 fn insert_http_raw_event(store: &RawEventStore<Http>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let http_body = Http {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -958,9 +961,10 @@ async fn rdp_with_data() {
 
 fn insert_rdp_raw_event(store: &RawEventStore<Rdp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let rdp_body = Rdp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1087,9 +1091,10 @@ async fn smtp_with_data() {
 
 fn insert_smtp_raw_event(store: &RawEventStore<Smtp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let smtp_body = Smtp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1222,9 +1227,10 @@ async fn ntlm_with_data() {
 
 fn insert_ntlm_raw_event(store: &RawEventStore<Ntlm>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let ntlm_body = Ntlm {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1353,9 +1359,10 @@ async fn kerberos_with_data() {
 
 fn insert_kerberos_raw_event(store: &RawEventStore<Kerberos>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let kerberos_body = Kerberos {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1501,9 +1508,10 @@ async fn ssh_with_data() {
 
 fn insert_ssh_raw_event(store: &RawEventStore<Ssh>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let ssh_body = Ssh {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1648,9 +1656,10 @@ async fn dce_rpc_with_data() {
 
 fn insert_dce_rpc_raw_event(store: &RawEventStore<DceRpc>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let dce_rpc_body = DceRpc {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1777,9 +1786,10 @@ async fn ftp_with_data() {
 
 fn insert_ftp_raw_event(store: &RawEventStore<Ftp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let ftp_body = Ftp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -1923,9 +1933,10 @@ async fn mqtt_with_data() {
 
 fn insert_mqtt_raw_event(store: &RawEventStore<Mqtt>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let mqtt_body = Mqtt {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -2061,9 +2072,10 @@ async fn ldap_with_data() {
 
 fn insert_ldap_raw_event(store: &RawEventStore<Ldap>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let ldap_body = Ldap {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -2212,9 +2224,10 @@ async fn tls_with_data() {
 
 fn insert_tls_raw_event(store: &RawEventStore<Tls>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let tls_body = Tls {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -2396,9 +2409,10 @@ async fn smb_with_data() {
 
 fn insert_smb_raw_event(store: &RawEventStore<Smb>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let smb_body = Smb {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -2545,9 +2559,10 @@ async fn nfs_with_data() {
 
 fn insert_nfs_raw_event(store: &RawEventStore<Nfs>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let nfs_body = Nfs {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -2675,9 +2690,10 @@ async fn bootp_with_data() {
 
 fn insert_bootp_raw_event(store: &RawEventStore<Bootp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let bootp_body = Bootp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -2821,9 +2837,10 @@ async fn dhcp_with_data() {
 
 fn insert_dhcp_raw_event(store: &RawEventStore<Dhcp>, source: &str, timestamp: i64) {
     let mut key = Vec::with_capacity(source.len() + 1 + mem::size_of::<i64>());
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(&store.assign_source_key(source).unwrap());
     key.push(0);
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
 
     let dhcp_body = Dhcp {
         orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
@@ -9,9 +9,10 @@ use graphql_client::GraphQLQuery;
 
 use super::{
     collect_records, get_timestamp_from_key, handle_paged_events, write_run_tcpdump, Direction,
-    FromKeyValue, RawEventFilter, TimeRange, TIMESTAMP_SIZE,
+    FromKeyValue, RawEventFilter, TimeRange, SEQUENCE_SIZE, TIMESTAMP_SIZE,
 };
 use crate::{
+    auth::{Role, RoleGuard},
     graphql::{
         client::derives::{packets, pcap as pcaps, Packets, Pcap as Pcaps},
         events_in_cluster, impl_from_giganto_time_range_struct_for_graphql_client,
@@ -96,8 +97,16 @@ impl Pcap {
 
 impl FromKeyValue<pk> for Packet {
     fn from_key_value(key: &[u8], pk: pk) -> Result<Self> {
+        // The request time is followed by `\0`, the packet time, and the sequence number.
+        let request_time = key
+            .len()
+            .checked_sub(TIMESTAMP_SIZE + SEQUENCE_SIZE + 1)
+            .and_then(|end| key.get(end.checked_sub(TIMESTAMP_SIZE)?..end))
+            .ok_or("invalid packet key")?;
         Ok(Packet {
-            request_time: get_timestamp_from_key(&key[..key.len() - (TIMESTAMP_SIZE + 1)])?,
+            request_time: DateTime::from_timestamp_nanos(i64::from_be_bytes(
+                request_time.try_into()?,
+            )),
             packet_time: get_timestamp_from_key(key)?,
             packet: BASE64.encode(&pk.packet),
         })
@@ -124,7 +133,7 @@ fn handle_pcap(ctx: &Context<'_>, filter: &PacketFilter) -> Result<Pcap> {
 
     // generate storage search key
     let key_builder = StorageKey::builder()
-        .start_key(filter.get_start_key())
+        .start_key(store.source_key(filter.get_start_key()))
         .mid_key(filter.get_mid_key());
     let from_key = key_builder
         .clone()
@@ -176,6 +185,7 @@ impl PacketQuery {
         )
     }
 
+    #[graphql(guard = "RoleGuard::new(Role::Export)")]
     async fn pcap<'ctx>(&self, ctx: &Context<'ctx>, filter: PacketFilter) -> Result<Pcap> {
         let handler = handle_pcap;
 
@@ -219,7 +229,11 @@ mod tests {
     use chrono::{NaiveDateTime, TimeZone, Utc};
     use giganto_client::ingest::Packet as pk;
 
-    use crate::{graphql::tests::TestSchema, storage::RawEventStore};
+    use super::Packet;
+    use crate::{
+        graphql::{tests::TestSchema, FromKeyValue},
+        storage::RawEventStore,
+    };
 
     #[tokio::test]
     async fn packets_empty() {
@@ -450,11 +464,12 @@ mod tests {
         let mut key = Vec::with_capacity(
             source.len() + 1 + mem::size_of::<i64>() + 1 + mem::size_of::<i64>(),
         );
-        key.extend_from_slice(source.as_bytes());
+        key.extend_from_slice(&store.assign_source_key(source).unwrap());
         key.push(0);
         key.extend(req_timestamp.to_be_bytes());
         key.push(0);
         key.extend(pk_timestamp.to_be_bytes());
+        key.extend(0_u32.to_be_bytes());
 
         let packet_body = pk {
             packet_timestamp: pk_timestamp,
@@ -470,4 +485,23 @@ mod tests {
         let utc_time = local_datetime.with_timezone(&chrono::Utc);
         utc_time.to_string()
     }
+
+    #[test]
+    fn packet_from_short_key() {
+        let packet = || pk {
+            packet_timestamp: 1,
+            packet: vec![0, 1, 2, 3],
+        };
+        assert!(Packet::from_key_value(&[0; 12], packet()).is_err());
+        assert!(Packet::from_key_value(&[0; 20], packet()).is_err());
+
+        let mut key = b"src1\0".to_vec();
+        key.extend(2_i64.to_be_bytes());
+        key.push(0);
+        key.extend(1_i64.to_be_bytes());
+        key.extend(0_u32.to_be_bytes());
+        let packet = Packet::from_key_value(&key, packet()).unwrap();
+        assert_eq!(packet.request_time.timestamp_nanos_opt(), Some(2));
+        assert_eq!(packet.packet_time.timestamp_nanos_opt(), Some(1));
+    }
 }
@@ -293,11 +293,12 @@ mod tests {
         timestamp: i64,
     ) {
         let mut key: Vec<u8> = Vec::new();
-        key.extend_from_slice(source.as_bytes());
+        key.extend_from_slice(&store.assign_source_key(source).unwrap());
         key.push(0);
         key.extend_from_slice(kind.as_bytes());
         key.push(0);
         key.extend_from_slice(&timestamp.to_be_bytes());
+        key.extend_from_slice(&0_u32.to_be_bytes());
 
         let secu_log_body = SecuLog {
             source: source.to_string(),
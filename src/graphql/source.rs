@@ -1,37 +1,460 @@
 use std::collections::HashSet;
 
-use async_graphql::{Context, Object, Result};
+use anyhow::anyhow;
+use async_graphql::{Context, Enum, Error, Object, Result, SimpleObject, StringNumber, ID};
+use chrono::{DateTime, Utc};
+use giganto_proc_macro::ConvertGraphQLEdgesNode;
+use graphql_client::GraphQLQuery;
+use tokio::task;
+use tracing::{error, info};
 
-use crate::{peer::Peers, IngestSources};
+use crate::{
+    auth::{Role, RoleGuard},
+    graphql::{
+        client::derives::{
+            ingest_counts, source_details, IngestCounts as IngestCountsQuery,
+            SourceDetails as SourceDetailsQuery,
+        },
+        events_in_cluster, impl_from_giganto_time_range_struct_for_graphql_client, TimeRange,
+    },
+    peer::Peers,
+    storage::{self, Database},
+    IngestSources, RunTimeIngestSources, SourceJobs,
+};
 
 #[derive(Default)]
 pub(super) struct SourceQuery;
 
+#[derive(Default)]
+pub(super) struct SourceMutation;
+
+/// The numbers of the events a source has sent, per kind and per hour.
+#[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
+#[graphql_client_type(names = [ingest_counts::IngestCountsIngestCounts, ])]
+struct SourceIngestCounts {
+    source: String,
+    #[graphql_client_type(recursive_into = true)]
+    counts: Vec<IngestCount>,
+}
+
+/// The number of the events of a kind received from a source in an hour.
+#[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
+#[graphql_client_type(names = [ingest_counts::IngestCountsIngestCountsCounts, ])]
+struct IngestCount {
+    kind: String,
+    /// The start of the hour in which the events were received.
+    hour: DateTime<Utc>,
+    events: StringNumber<u64>,
+    /// The total size of the events in bytes.
+    bytes: StringNumber<u64>,
+}
+
+impl From<storage::IngestCount> for IngestCount {
+    fn from(count: storage::IngestCount) -> Self {
+        Self {
+            kind: count.kind,
+            hour: count.hour,
+            events: StringNumber(count.events),
+            bytes: StringNumber(count.bytes),
+        }
+    }
+}
+
+/// What is known about a source.
+#[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
+#[graphql_client_type(names = [source_details::SourceDetailsSourceDetails, ])]
+struct SourceDetails {
+    name: String,
+    /// Whether an agent of the source is connected now.
+    connected: bool,
+    first_seen: DateTime<Utc>,
+    last_active: DateTime<Utc>,
+    last_connected: Option<DateTime<Utc>>,
+    last_disconnected: Option<DateTime<Utc>>,
+    /// The names of the agents that have sent the data of the source, taken
+    /// from the common names of their certificates.
+    agents: Vec<String>,
+    /// The protocol version of the client that connected last.
+    protocol_version: Option<String>,
+    /// The kinds of the events the source has sent.
+    #[graphql_client_type(recursive_into = true)]
+    kinds: Vec<SourceKind>,
+}
+
+/// A kind of the events a source has sent.
+#[derive(SimpleObject, Debug, ConvertGraphQLEdgesNode)]
+#[graphql_client_type(names = [source_details::SourceDetailsSourceDetailsKinds, ])]
+struct SourceKind {
+    kind: String,
+    /// The timestamp of the last event of the kind.
+    last_event_time: DateTime<Utc>,
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
+enum SourceJobKind {
+    Delete,
+    Rename,
+}
+
+/// A job that deletes or renames all the data of a source.
+#[derive(SimpleObject, Debug)]
+struct SourceJob {
+    id: ID,
+    source: String,
+    kind: SourceJobKind,
+    /// The new name of the source, if the job renames it.
+    new_name: Option<String>,
+    started_at: DateTime<Utc>,
+    /// `null` while the job is running.
+    finished_at: Option<DateTime<Utc>>,
+    /// The error that stopped the job, if any.
+    error: Option<String>,
+}
+
+impl From<storage::SourceJob> for SourceJob {
+    fn from(job: storage::SourceJob) -> Self {
+        let (kind, new_name) = match job.kind {
+            storage::SourceJobKind::Delete => (SourceJobKind::Delete, None),
+            storage::SourceJobKind::Rename { new_name } => (SourceJobKind::Rename, Some(new_name)),
+        };
+        Self {
+            id: ID(job.id.to_string()),
+            source: job.source,
+            kind,
+            new_name,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            error: job.error,
+        }
+    }
+}
+
+async fn all_sources(ctx: &Context<'_>) -> Vec<String> {
+    let mut total_source_list = HashSet::new();
+    // Add current giganto's sources
+    let ingest_sources = ctx.data_opt::<IngestSources>();
+    if let Some(ingest_sources) = ingest_sources {
+        total_source_list.extend(ingest_sources.read().await.clone());
+    }
+    // Add peer giganto's sources
+    let peers = ctx.data_opt::<Peers>();
+    if let Some(peers) = peers {
+        for peer in peers.read().await.values() {
+            total_source_list.extend(peer.ingest_sources.clone());
+        }
+    }
+
+    let mut sources: Vec<String> = total_source_list
+        .into_iter()
+        .filter(|source| crate::auth::is_source_allowed(ctx, source))
+        .collect();
+    sources.sort();
+    sources
+}
+
+async fn handle_source_details(
+    ctx: &Context<'_>,
+    sources: &[String],
+) -> Result<Vec<SourceDetails>> {
+    let connected: HashSet<String> = match ctx.data_opt::<RunTimeIngestSources>() {
+        Some(runtime_ingest_sources) => runtime_ingest_sources
+            .read()
+            .await
+            .keys()
+            .cloned()
+            .collect(),
+        None => HashSet::new(),
+    };
+    let store = ctx.data::<Database>()?.sources_store()?;
+    let mut details = Vec::new();
+    for source in sources {
+        let Some(metadata) = store.metadata(source)? else {
+            continue;
+        };
+        let kinds = store
+            .last_events(source)?
+            .into_iter()
+            .map(|(kind, timestamp)| SourceKind {
+                kind,
+                last_event_time: DateTime::from_timestamp_nanos(timestamp),
+            })
+            .collect();
+        details.push(SourceDetails {
+            name: source.clone(),
+            connected: connected.contains(source),
+            first_seen: metadata.first_seen,
+            last_active: metadata.last_active,
+            last_connected: metadata.last_connected,
+            last_disconnected: metadata.last_disconnected,
+            agents: metadata.agents.into_iter().collect(),
+            protocol_version: metadata.protocol_version,
+            kinds,
+        });
+    }
+    Ok(details)
+}
+
+#[allow(clippy::unused_async)]
+async fn handle_ingest_counts(
+    ctx: &Context<'_>,
+    sources: &[String],
+    kinds: &Option<Vec<String>>,
+    time: &Option<TimeRange>,
+) -> Result<Vec<SourceIngestCounts>> {
+    let db = ctx.data::<Database>()?;
+    let store = db.ingest_counter_store()?;
+    let (from, to) = time
+        .as_ref()
+        .map_or((None, None), |time| (time.start, time.end));
+    let from = from.map_or(i64::MIN, |from| {
+        from.timestamp_nanos_opt().unwrap_or(i64::MAX)
+    });
+    let to = to.map_or(i64::MAX, |to| to.timestamp_nanos_opt().unwrap_or(i64::MAX));
+
+    let mut ingest_counts = Vec::new();
+    for source in sources {
+        let counts = store
+            .counts(source, from, to)?
+            .into_iter()
+            .filter(|count| {
+                kinds
+                    .as_ref()
+                    .map_or(true, |kinds| kinds.contains(&count.kind))
+            })
+            .map(Into::into)
+            .collect::<Vec<_>>();
+        if counts.is_empty() {
+            continue;
+        }
+        ingest_counts.push(SourceIngestCounts {
+            source: source.clone(),
+            counts,
+        });
+    }
+    Ok(ingest_counts)
+}
+
 #[Object]
 impl SourceQuery {
     async fn sources<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<String>> {
-        let mut total_source_list = HashSet::new();
-        // Add current giganto's sources
-        let ingest_sources = ctx.data_opt::<IngestSources>();
-        if let Some(ingest_sources) = ingest_sources {
-            total_source_list.extend(ingest_sources.read().await.clone());
+        Ok(all_sources(ctx).await)
+    }
+
+    /// Returns what is known about the sources, or about all the sources in
+    /// the cluster if `sources` is not given.
+    async fn source_details<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        sources: Option<Vec<String>>,
+        request_from_peer: Option<bool>,
+    ) -> Result<Vec<SourceDetails>> {
+        let sources = match sources {
+            Some(sources) => sources,
+            None => all_sources(ctx).await,
+        };
+        let handler = handle_source_details;
+
+        let mut details: Vec<SourceDetails> = events_in_cluster!(
+            multiple_sources
+            ctx,
+            sources,
+            request_from_peer,
+            handler,
+            SourceDetailsQuery,
+            source_details::Variables,
+            source_details::ResponseData,
+            source_details,
+            Vec<SourceDetails>
+        )?;
+        details.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(details)
+    }
+
+    /// Returns the numbers of the events received from the sources, or from
+    /// all the sources in the cluster if `sources` is not given, per kind and
+    /// per hour.
+    ///
+    /// Only the hours that overlap `time` and the kinds in `kinds` are
+    /// returned if they are given.
+    async fn ingest_counts<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        sources: Option<Vec<String>>,
+        kinds: Option<Vec<String>>,
+        time: Option<TimeRange>,
+        request_from_peer: Option<bool>,
+    ) -> Result<Vec<SourceIngestCounts>> {
+        let sources = match sources {
+            Some(sources) => sources,
+            None => all_sources(ctx).await,
+        };
+        let handler = handle_ingest_counts;
+
+        let mut ingest_counts: Vec<SourceIngestCounts> = events_in_cluster!(
+            multiple_sources
+            ctx,
+            sources,
+            request_from_peer,
+            handler,
+            IngestCountsQuery,
+            ingest_counts::Variables,
+            ingest_counts::ResponseData,
+            ingest_counts,
+            Vec<SourceIngestCounts>,
+            with_extra_handler_args (&kinds, &time),
+            with_extra_query_args (kinds := kinds.clone(), time := time.clone().map(Into::into))
+        )?;
+        ingest_counts.sort_by(|a, b| a.source.cmp(&b.source));
+        Ok(ingest_counts)
+    }
+
+    /// Returns the jobs started by `deleteSource` and `renameSource`, in the
+    /// order they were started.
+    async fn source_jobs<'ctx>(&self, ctx: &Context<'ctx>) -> Result<Vec<SourceJob>> {
+        let source_jobs = ctx.data::<SourceJobs>()?;
+        let jobs = source_jobs.read().await.clone();
+        Ok(jobs.into_iter().map(Into::into).collect())
+    }
+}
+
+impl_from_giganto_time_range_struct_for_graphql_client!(ingest_counts);
+
+#[Object]
+impl SourceMutation {
+    /// Starts deleting all the data of the source, and returns the ID of the
+    /// job, whose progress is returned by `sourceJobs`.
+    ///
+    /// The source must not be connected, nor be under a legal hold.
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn delete_source<'ctx>(&self, ctx: &Context<'ctx>, source: String) -> Result<ID> {
+        start_source_job(ctx, source, storage::SourceJobKind::Delete).await
+    }
+
+    /// Starts renaming the source, so that all its data belong to `newName`,
+    /// and returns the ID of the job, whose progress is returned by
+    /// `sourceJobs`.
+    ///
+    /// The source must not be connected, and `newName` must not be in use.
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn rename_source<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        source: String,
+        new_name: String,
+    ) -> Result<ID> {
+        start_source_job(ctx, source, storage::SourceJobKind::Rename { new_name }).await
+    }
+}
+
+async fn start_source_job<'ctx>(
+    ctx: &Context<'ctx>,
+    source: String,
+    kind: storage::SourceJobKind,
+) -> Result<ID> {
+    let db = ctx.data::<Database>()?.clone();
+    let ingest_sources = ctx.data::<IngestSources>()?.clone();
+    let source_jobs = ctx.data::<SourceJobs>()?.clone();
+
+    let mut names = vec![source.clone()];
+    if let storage::SourceJobKind::Rename { new_name } = &kind {
+        names.push(new_name.clone());
+    }
+    let job = {
+        // The jobs are locked while the connected sources are checked, and
+        // the ingest server rejects the sources of the running jobs.
+        let mut jobs = source_jobs.write().await;
+        let runtime_ingest_sources = ctx.data::<RunTimeIngestSources>()?.read().await;
+        if let Some(name) = names
+            .iter()
+            .find(|name| runtime_ingest_sources.contains_key(*name))
+        {
+            return Err(anyhow!("{name} is connected").into());
         }
-        // Add peer giganto's sources
-        let peers = ctx.data_opt::<Peers>();
-        if let Some(peers) = peers {
-            for peer in peers.read().await.values() {
-                total_source_list.extend(peer.ingest_sources.clone());
-            }
+        if let Some(job) = jobs
+            .iter()
+            .find(|job| job.is_running() && names.iter().any(|name| job.involves(name)))
+        {
+            return Err(anyhow!("{} is being changed by job {}", job.source, job.id).into());
         }
+        let job = db.source_job_store()?.create(&source, kind)?;
+        jobs.push(job.clone());
+        job
+    };
+    let id = job.id;
+    task::spawn(run_source_job(db, ingest_sources, source_jobs, job, false));
+
+    Ok(ID(id.to_string()))
+}
+
+/// Runs again the job that was running when giganto stopped, unless all its
+/// changes were written.
+pub async fn resume_source_job(
+    db: Database,
+    ingest_sources: IngestSources,
+    source_jobs: SourceJobs,
+    job: storage::SourceJob,
+) {
+    info!("Resuming source job {} on {}", job.id, job.source);
+    run_source_job(db, ingest_sources, source_jobs, job, true).await;
+}
 
-        let mut sources: Vec<String> = total_source_list.into_iter().collect();
-        sources.sort();
-        Ok(sources)
+async fn run_source_job(
+    db: Database,
+    ingest_sources: IngestSources,
+    source_jobs: SourceJobs,
+    job: storage::SourceJob,
+    resumed: bool,
+) {
+    let storage::SourceJob {
+        id, source, kind, ..
+    } = job.clone();
+    let job_db = db.clone();
+    let result = task::spawn_blocking(move || {
+        if resumed && job_db.is_source_job_done(&job)? {
+            return Ok(());
+        }
+        match &job.kind {
+            storage::SourceJobKind::Delete => job_db.delete_source(&job.source),
+            storage::SourceJobKind::Rename { new_name } => {
+                job_db.rename_source(&job.source, new_name)
+            }
+        }
+    })
+    .await;
+    let error = match result {
+        Ok(Ok(())) => {
+            let mut ingest_sources = ingest_sources.write().await;
+            ingest_sources.remove(&source);
+            if let storage::SourceJobKind::Rename { new_name } = kind {
+                ingest_sources.insert(new_name);
+            }
+            None
+        }
+        Ok(Err(e)) => {
+            error!("Source job {id} on {source} failed: {e}");
+            Some(e.to_string())
+        }
+        Err(e) => {
+            error!("Source job {id} on {source} terminated unexpectedly: {e}");
+            Some(e.to_string())
+        }
+    };
+    let mut jobs = source_jobs.write().await;
+    if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+        job.error = error;
+        job.finished_at = Some(Utc::now());
+        if let Err(e) = db.source_job_store().and_then(|store| store.update(job)) {
+            error!("Failed to store source job {id}: {e}");
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
     use crate::graphql::tests::TestSchema;
     #[tokio::test]
     async fn sources_test() {
@@ -61,4 +484,189 @@ mod tests {
             "{sources: [\"ingest src 1\", \"ingest src 2\", \"src 1\", \"src 2\", \"src1\", \"src2\"]}"
         );
     }
+
+    #[tokio::test]
+    async fn source_details() {
+        let schema = TestSchema::new();
+        let sources = schema.db.sources_store().unwrap();
+        let first_seen = "2024-01-01T00:00:00Z".parse().unwrap();
+        let connected = "2024-01-02T00:00:00Z".parse().unwrap();
+        sources.insert("src 1", first_seen).unwrap();
+        sources
+            .connect("src 1", "piglet", "0.22.1", connected)
+            .unwrap();
+        let id = sources.assign_id("src 1").unwrap();
+        sources.set_last_event(id, "conn", 1_000_000_000).unwrap();
+        schema
+            .runtime_ingest_sources
+            .write()
+            .await
+            .insert("src 1".to_string(), connected);
+
+        let query = r#"
+        {
+            sourceDetails(sources: ["src 1", "src1"]) {
+                name
+                connected
+                firstSeen
+                lastConnected
+                lastDisconnected
+                agents
+                protocolVersion
+                kinds {
+                    kind
+                    lastEventTime
+                }
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{sourceDetails: [{name: \"src 1\", connected: true, \
+            firstSeen: \"2024-01-01T00:00:00+00:00\", \
+            lastConnected: \"2024-01-02T00:00:00+00:00\", lastDisconnected: null, \
+            agents: [\"piglet\"], protocolVersion: \"0.22.1\", \
+            kinds: [{kind: \"conn\", lastEventTime: \"1970-01-01T00:00:01+00:00\"}]}]}"
+        );
+
+        let res = schema.execute("{ sourceDetails { name } }").await;
+        assert_eq!(res.data.to_string(), "{sourceDetails: [{name: \"src 1\"}]}");
+    }
+
+    #[tokio::test]
+    async fn ingest_counts() {
+        let schema = TestSchema::new();
+        let sources = schema.db.sources_store().unwrap();
+        let src1 = sources.assign_id("src 1").unwrap();
+        let src2 = sources.assign_id("src 2").unwrap();
+        let counters = schema.db.ingest_counter_store().unwrap();
+        let time = |s: &str| s.parse().unwrap();
+        counters
+            .add(src1, "conn", time("2024-01-01T00:10:00Z"), 2, 200)
+            .unwrap();
+        counters
+            .add(src1, "conn", time("2024-01-01T00:20:00Z"), 3, 300)
+            .unwrap();
+        counters
+            .add(src1, "dns", time("2024-01-01T00:10:00Z"), 1, 50)
+            .unwrap();
+        counters
+            .add(src2, "conn", time("2024-01-01T02:10:00Z"), 4, 400)
+            .unwrap();
+
+        let query = r#"
+        {
+            ingestCounts(sources: ["src 2", "src 1", "src 3"], kinds: ["conn"]) {
+                source
+                counts {
+                    kind
+                    hour
+                    events
+                    bytes
+                }
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{ingestCounts: [{source: \"src 1\", counts: [{kind: \"conn\", \
+            hour: \"2024-01-01T00:00:00+00:00\", events: \"5\", bytes: \"500\"}]}, \
+            {source: \"src 2\", counts: [{kind: \"conn\", \
+            hour: \"2024-01-01T02:00:00+00:00\", events: \"4\", bytes: \"400\"}]}]}"
+        );
+
+        let query = r#"
+        {
+            ingestCounts(
+                sources: ["src 1", "src 2"],
+                time: { start: "2024-01-01T00:30:00Z", end: "2024-01-01T02:00:00Z" }
+            ) {
+                source
+                counts {
+                    kind
+                    events
+                }
+            }
+        }"#;
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{ingestCounts: [{source: \"src 1\", counts: [{kind: \"conn\", events: \"5\"}, \
+            {kind: \"dns\", events: \"1\"}]}]}"
+        );
+    }
+
+    async fn wait_source_jobs(schema: &TestSchema) {
+        for _ in 0..100 {
+            let res = schema.execute("{ sourceJobs { finishedAt } }").await;
+            if !res.data.to_string().contains("finishedAt: null") {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("source jobs are not finished");
+    }
+
+    #[tokio::test]
+    async fn source_jobs() {
+        let schema = TestSchema::new();
+        let sources = schema.db.sources_store().unwrap();
+        sources.insert("src 1", Utc::now()).unwrap();
+        sources.insert("src1", Utc::now()).unwrap();
+        schema
+            .runtime_ingest_sources
+            .write()
+            .await
+            .insert("src1".to_string(), Utc::now());
+
+        let res = schema
+            .execute(r#"mutation { deleteSource(source: "src1") }"#)
+            .await;
+        assert_eq!(res.errors[0].message, "src1 is connected");
+
+        let res = schema
+            .execute(r#"mutation { renameSource(source: "src 1", newName: "src 2") }"#)
+            .await;
+        assert_eq!(res.data.to_string(), "{renameSource: \"0\"}");
+        wait_source_jobs(&schema).await;
+
+        let res = schema
+            .execute(r#"mutation { deleteSource(source: "src 2") }"#)
+            .await;
+        assert_eq!(res.data.to_string(), "{deleteSource: \"1\"}");
+        wait_source_jobs(&schema).await;
+
+        let res = schema
+            .execute(r#"mutation { deleteSource(source: "src 3") }"#)
+            .await;
+        assert_eq!(res.data.to_string(), "{deleteSource: \"2\"}");
+        wait_source_jobs(&schema).await;
+
+        let query = r"
+        {
+            sourceJobs {
+                id
+                source
+                kind
+                newName
+                error
+            }
+        }";
+        let res = schema.execute(query).await;
+        assert_eq!(
+            res.data.to_string(),
+            "{sourceJobs: [\
+            {id: \"0\", source: \"src 1\", kind: RENAME, newName: \"src 2\", error: null}, \
+            {id: \"1\", source: \"src 2\", kind: DELETE, newName: null, error: null}, \
+            {id: \"2\", source: \"src 3\", kind: DELETE, newName: null, error: \"unknown source: src 3\"}]}"
+        );
+        assert!(!sources.contains("src 1").unwrap());
+        assert!(!sources.contains("src 2").unwrap());
+
+        let res = schema.execute("{ sources }").await;
+        assert_eq!(
+            res.data.to_string(),
+            "{sources: [\"ingest src 1\", \"src1\"]}"
+        );
+    }
 }
@@ -16,13 +16,12 @@ use rocksdb::Direction;
 use serde::de::DeserializeOwned;
 use tracing::error;
 
-use super::TIMESTAMP_SIZE;
 use crate::{
     graphql::{
         client::derives::{statistics as stats, Statistics as Stats},
         events_in_cluster, impl_from_giganto_time_range_struct_for_graphql_client, TimeRange,
     },
-    storage::{Database, RawEventStore, StatisticsIter, StorageKey},
+    storage::{timestamp_from_key, Database, RawEventStore, StatisticsIter, StorageKey},
 };
 
 pub const MAX_CORE_SIZE: u32 = 16; // Number of queues on the collect device's NIC
@@ -159,7 +158,7 @@ where
     };
 
     let key_builder = StorageKey::builder()
-        .start_key(source)
+        .start_key(store.source_key(source))
         .mid_key(Some(core_id.to_be_bytes().to_vec()));
     let from_key = key_builder.clone().upper_closed_bound_end_key(end).build();
     let to_key = key_builder.lower_closed_bound_end_key(start).build();
@@ -190,19 +189,17 @@ fn gen_statistics(
         let check_latest_values = iter_next_values.clone();
         let Some((latest_key, latest_stats)) = check_latest_values
             .iter()
-            .max_by_key(|(key, _)| &key[(key.len() - TIMESTAMP_SIZE)..])
+            .max_by_key(|(key, _)| timestamp_from_key(key).ok())
         else {
             break;
         };
 
-        let latest_key_timestamp =
-            i64::from_be_bytes(latest_key[(latest_key.len() - TIMESTAMP_SIZE)..].try_into()?);
+        let latest_key_timestamp = timestamp_from_key(latest_key)?;
         let mut total_stats: HashMap<RawEventKind, (u64, u64)> = HashMap::new();
 
         // Collect statistics formed at the same timestamp as the most recent statistics into a HashMap.
         for (idx, (key, value)) in iter_next_values.clone().iter().enumerate() {
-            let compare_key_timestamp =
-                i64::from_be_bytes(key[(key.len() - TIMESTAMP_SIZE)..].try_into()?);
+            let compare_key_timestamp = timestamp_from_key(key)?;
             if latest_key_timestamp == compare_key_timestamp {
                 for (record, count, size) in &value.stats {
                     if allowed_raw_event_kinds.contains(record) {
@@ -293,10 +290,14 @@ fn calculate_ps(period: u16, len: u64) -> f64 {
 mod tests {
     use std::net::SocketAddr;
 
+    use async_graphql::Request;
     use chrono::Utc;
     use giganto_client::{ingest::statistics::Statistics, RawEventKind};
 
-    use crate::{graphql::tests::TestSchema, storage::RawEventStore};
+    use crate::{
+        graphql::{tests::TestSchema, PeerRequest},
+        storage::RawEventStore,
+    };
 
     #[tokio::test]
     async fn test_statistics() {
@@ -339,11 +340,12 @@ mod tests {
         size: u64,
     ) {
         let mut key = Vec::with_capacity(source.len() + 1 + std::mem::size_of::<i64>());
-        key.extend_from_slice(source.as_bytes());
+        key.extend_from_slice(&store.assign_source_key(source).unwrap());
         key.push(0);
         key.extend_from_slice(&core.to_be_bytes());
         key.push(0);
         key.extend_from_slice(&timestamp.to_be_bytes());
+        key.extend_from_slice(&0_u32.to_be_bytes());
 
         let msg = Statistics {
             core,
@@ -424,6 +426,53 @@ mod tests {
         mock.assert_async().await;
     }
 
+    #[tokio::test]
+    async fn test_statistics_request_from_peer() {
+        let query = r#"
+        {
+            statistics(sources: ["src 2"], requestFromPeer: true) {
+                source
+            }
+        }"#;
+
+        let mut peer_server = mockito::Server::new_async().await;
+        let peer_response_mock_data = r#"
+        {
+            "data": {
+                "statistics": [
+                    {
+                        "source": "src 2",
+                        "stats": []
+                    }
+                ]
+            }
+        }
+        "#;
+        let mock = peer_server
+            .mock("POST", "/graphql")
+            .with_status(200)
+            .with_body(peer_response_mock_data)
+            .expect(1)
+            .create();
+
+        let peer_port = peer_server
+            .host_with_port()
+            .parse::<SocketAddr>()
+            .expect("Port must exist")
+            .port();
+        let schema = TestSchema::new_with_graphql_peer(peer_port);
+
+        // A request not from a peer is forwarded, even if it says it is.
+        let res = schema.execute(query).await;
+        assert_eq!(res.data.to_string(), "{statistics: [{source: \"src 2\"}]}");
+
+        // A request from a peer is handled locally.
+        let request = Request::new(query).data(PeerRequest);
+        schema.schema.execute(request).await;
+
+        mock.assert_async().await;
+    }
+
     #[tokio::test]
     async fn test_statistics_giganto_cluster_combined() {
         // given
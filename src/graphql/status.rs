@@ -7,10 +7,17 @@ use toml_edit::{value, DocumentMut, InlineTable};
 use tracing::{error, info, warn};
 
 use super::{PowerOffNotify, RebootNotify, TerminateNotify};
-use crate::settings::Config;
+use crate::settings::{
+    CfOptions, Compression, Config, IngestLimit, LimitAction, RetentionPolicy, StorageTier,
+    SyncPolicy, TimestampWindow, WindowAction,
+};
 #[cfg(debug_assertions)]
 use crate::storage::Database;
-use crate::{peer::PeerIdentity, settings::Settings};
+use crate::{
+    auth::{Role, RoleGuard},
+    peer::PeerIdentity,
+    settings::Settings,
+};
 
 const GRAPHQL_REBOOT_DELAY: u64 = 100;
 pub const CONFIG_PUBLISH_SRV_ADDR: &str = "publish_srv_addr";
@@ -61,6 +68,10 @@ impl Config {
         humantime::format_duration(self.retention).to_string()
     }
 
+    async fn retention_policies(&self) -> Vec<RetentionPolicy> {
+        self.retention_policies.clone()
+    }
+
     async fn data_dir(&self) -> String {
         self.data_dir.to_string_lossy().to_string()
     }
@@ -73,6 +84,20 @@ impl Config {
         self.export_dir.to_string_lossy().to_string()
     }
 
+    async fn backup_dir(&self) -> String {
+        self.backup_dir.to_string_lossy().to_string()
+    }
+
+    async fn archive_dir(&self) -> Option<String> {
+        self.archive_dir
+            .as_ref()
+            .map(|dir| dir.to_string_lossy().to_string())
+    }
+
+    async fn archive_format(&self) -> String {
+        self.archive_format.export_type().to_string()
+    }
+
     async fn max_open_files(&self) -> i32 {
         self.max_open_files
     }
@@ -89,6 +114,14 @@ impl Config {
         StringNumber(self.max_sub_compactions)
     }
 
+    async fn cf_options(&self) -> Vec<CfOptions> {
+        self.cf_options.clone()
+    }
+
+    async fn storage_tiers(&self) -> Vec<StorageTier> {
+        self.storage_tiers.clone()
+    }
+
     async fn addr_to_peers(&self) -> Option<String> {
         self.addr_to_peers.map(|addr| addr.to_string())
     }
@@ -100,6 +133,159 @@ impl Config {
     async fn ack_transmission(&self) -> u16 {
         self.ack_transmission
     }
+
+    async fn sync_policy(&self) -> String {
+        match self.sync_policy {
+            SyncPolicy::Ack => "ack",
+            SyncPolicy::Periodic => "periodic",
+            SyncPolicy::None => "none",
+        }
+        .to_string()
+    }
+
+    async fn sync_interval(&self) -> String {
+        humantime::format_duration(self.sync_interval).to_string()
+    }
+
+    async fn ingest_limits(&self) -> Vec<IngestLimit> {
+        self.ingest_limits.clone()
+    }
+
+    async fn timestamp_window(&self) -> TimestampWindow {
+        self.timestamp_window
+    }
+
+    async fn jwt_key(&self) -> Option<String> {
+        self.jwt_key
+            .as_ref()
+            .map(|key| key.to_string_lossy().to_string())
+    }
+}
+
+#[Object]
+impl RetentionPolicy {
+    async fn record_type(&self) -> String {
+        self.record_type.clone()
+    }
+
+    async fn source(&self) -> Option<String> {
+        self.source.clone()
+    }
+
+    async fn retention(&self) -> String {
+        humantime::format_duration(self.retention).to_string()
+    }
+}
+
+#[Object]
+impl IngestLimit {
+    async fn source(&self) -> Option<String> {
+        self.source.clone()
+    }
+
+    async fn kind(&self) -> Option<String> {
+        self.kind.clone()
+    }
+
+    async fn events_per_sec(&self) -> Option<StringNumber<u64>> {
+        self.events_per_sec.map(StringNumber)
+    }
+
+    async fn bytes_per_sec(&self) -> Option<StringNumber<u64>> {
+        self.bytes_per_sec.map(StringNumber)
+    }
+
+    async fn daily_bytes(&self) -> Option<StringNumber<u64>> {
+        self.daily_bytes.map(StringNumber)
+    }
+
+    async fn action(&self) -> String {
+        limit_action_name(self.action)
+    }
+}
+
+#[Object]
+impl TimestampWindow {
+    async fn max_past(&self) -> Option<String> {
+        self.max_past
+            .map(|max_past| humantime::format_duration(max_past).to_string())
+    }
+
+    async fn max_future(&self) -> Option<String> {
+        self.max_future
+            .map(|max_future| humantime::format_duration(max_future).to_string())
+    }
+
+    async fn action(&self) -> String {
+        match self.action {
+            WindowAction::Reject => "reject",
+            WindowAction::Clamp => "clamp",
+            WindowAction::Quarantine => "quarantine",
+        }
+        .to_string()
+    }
+}
+
+#[Object]
+impl CfOptions {
+    async fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn compression(&self) -> Option<String> {
+        self.compression.map(compression_name)
+    }
+
+    async fn bottommost_compression(&self) -> Option<String> {
+        self.bottommost_compression.map(compression_name)
+    }
+
+    async fn block_size(&self) -> Option<StringNumber<u64>> {
+        self.block_size.map(StringNumber)
+    }
+
+    async fn write_buffer_size(&self) -> Option<StringNumber<u64>> {
+        self.write_buffer_size.map(StringNumber)
+    }
+
+    async fn source_bloom_filter(&self) -> bool {
+        self.source_bloom_filter
+    }
+
+    async fn storage_tiers(&self) -> Vec<StorageTier> {
+        self.storage_tiers.clone()
+    }
+}
+
+#[Object]
+impl StorageTier {
+    async fn path(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+
+    async fn target_size(&self) -> StringNumber<u64> {
+        StringNumber(self.target_size)
+    }
+}
+
+fn compression_name(compression: Compression) -> String {
+    match compression {
+        Compression::None => "none",
+        Compression::Snappy => "snappy",
+        Compression::Zlib => "zlib",
+        Compression::Lz4 => "lz4",
+        Compression::Lz4hc => "lz4hc",
+        Compression::Zstd => "zstd",
+    }
+    .to_string()
+}
+
+pub(super) fn limit_action_name(action: LimitAction) -> String {
+    match action {
+        LimitAction::Delay => "delay",
+        LimitAction::Drop => "drop",
+    }
+    .to_string()
 }
 
 #[Object]
@@ -176,6 +362,7 @@ impl StatusQuery {
 #[Object]
 impl ConfigMutation {
     #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
     async fn set_config<'ctx>(&self, ctx: &Context<'ctx>, draft: String) -> Result<bool> {
         let is_local = ctx.data::<bool>()?;
 
@@ -213,6 +400,7 @@ impl ConfigMutation {
     }
 
     #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
     async fn stop<'ctx>(&self, ctx: &Context<'ctx>) -> Result<bool> {
         let terminate_notify = ctx.data::<TerminateNotify>()?;
         let notify_terminate = terminate_notify.0.clone();
@@ -222,6 +410,7 @@ impl ConfigMutation {
     }
 
     #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
     async fn reboot<'ctx>(&self, ctx: &Context<'ctx>) -> Result<bool> {
         let reboot_notify = ctx.data::<RebootNotify>()?;
         let notify_reboot = reboot_notify.0.clone();
@@ -231,6 +420,7 @@ impl ConfigMutation {
     }
 
     #[allow(clippy::unused_async)]
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
     async fn shutdown<'ctx>(&self, ctx: &Context<'ctx>) -> Result<bool> {
         let power_off_notify = ctx.data::<PowerOffNotify>()?;
         let notify_power_off = power_off_notify.0.clone();
@@ -342,8 +532,14 @@ mod tests {
                     graphqlSrvAddr
                     dataDir
                     retention
+                    retentionPolicies {
+                        recordType
+                        source
+                        retention
+                    }
                     logDir
                     exportDir
+                    backupDir
                     ackTransmission
                     maxOpenFiles
                     maxMbOfLevelBase
@@ -354,6 +550,8 @@ mod tests {
                         addr
                         hostname
                     }
+                    syncPolicy
+                    syncInterval
                 }
             }
         "#;
@@ -392,18 +590,49 @@ mod tests {
                     graphqlSrvAddr
                     dataDir
                     retention
+                    retentionPolicies {
+                        recordType
+                        source
+                        retention
+                    }
                     logDir
                     exportDir
+                    backupDir
+                    archiveDir
+                    archiveFormat
                     ackTransmission
                     maxOpenFiles
                     maxMbOfLevelBase
                     numOfThread
                     maxSubCompactions
+                    cfOptions {
+                        name
+                        compression
+                        blockSize
+                        sourceBloomFilter
+                    }
+                    storageTiers {
+                        path
+                        targetSize
+                    }
                     addrToPeers
                     peers {
                         addr
                         hostname
                     }
+                    syncPolicy
+                    syncInterval
+                    ingestLimits {
+                        source
+                        dailyBytes
+                        action
+                    }
+                    timestampWindow {
+                        maxPast
+                        maxFuture
+                        action
+                    }
+                    jwtKey
                 }
             }
         "#;
@@ -414,6 +643,15 @@ mod tests {
         assert!(
             data.contains("ackTransmission: 1024, maxOpenFiles: 8000, maxMbOfLevelBase: \"512\", numOfThread: 8, maxSubCompactions: \"2\"")
         );
+        assert!(data.contains("archiveDir: null, archiveFormat: \"json\""));
+        assert!(data.contains("cfOptions: []"));
+        assert!(data.contains("storageTiers: []"));
+        assert!(data.contains("syncPolicy: \"ack\", syncInterval: \"1s\""));
+        assert!(data.contains("ingestLimits: []"));
+        assert!(
+            data.contains("timestampWindow: {maxPast: null, maxFuture: null, action: \"reject\"}")
+        );
+        assert!(data.contains("jwtKey: null"));
 
         let toml_content = test_toml_content();
 
@@ -438,15 +676,35 @@ mod tests {
             graphql_srv_addr = "127.0.0.1:8442"
             data_dir = "tests/data"
             retention = "100d"
+            retention_policies = [
+                { record_type = "packet", retention = "7d" },
+                { record_type = "seculog", source = "src1", retention = "365d" },
+            ]
             log_dir = "/data/logs/apps"
             export_dir = "tests/export"
+            backup_dir = "tests/backup"
+            archive_dir = "tests/archive"
+            archive_format = "csv"
             ack_transmission = 1024
             max_open_files = 8000
             max_mb_of_level_base = 512
             num_of_thread = 8
             max_sub_compactions = 2
+            cf_options = [
+                { name = "conn", compression = "zstd", block_size = 65536, source_bloom_filter = true },
+            ]
+            storage_tiers = [
+                { path = "tests/data/db", target_size = 1099511627776 },
+                { path = "tests/cold", target_size = 10995116277760 },
+            ]
             addr_to_peers = "127.0.0.1:48383"
             peers = [{ addr = "127.0.0.1:60192", hostname = "node2" }]
+            sync_policy = "periodic"
+            sync_interval = "1s"
+            ingest_limits = [
+                { source = "src1", events_per_sec = 1000, daily_bytes = 1073741824, action = "drop" },
+            ]
+            timestamp_window = { max_past = "30d", max_future = "1h", action = "quarantine" }
             "#
         .to_string()
     }
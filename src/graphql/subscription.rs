@@ -0,0 +1,561 @@
+//! Subscriptions to the events as they are ingested.
+//!
+//! A subscriber is registered in the stream channels that the ingest server
+//! fans the events out to, like a stream requested from the publish server.
+//! The matching events are kept in a bounded buffer until they are sent, and
+//! the events that arrive while the buffer is full are dropped.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_graphql::{Context, Result, Subscription};
+use chrono::{TimeZone, Utc};
+use futures_util::{stream, Stream};
+use giganto_client::ingest::network::{
+    Bootp, Conn, DceRpc, Dhcp, Dns, Ftp, Http, Kerberos, Ldap, Mqtt, Nfs, Ntlm, Rdp, Smb, Smtp,
+    Ssh, Tls,
+};
+use serde::de::DeserializeOwned;
+use tokio::{
+    sync::mpsc::{self, error::TrySendError, unbounded_channel},
+    task,
+};
+
+use super::{
+    audit::AuditRecord,
+    is_current_giganto_in_charge,
+    network::{
+        BootpRawEvent, ConnRawEvent, DceRpcRawEvent, DhcpRawEvent, DnsRawEvent, FtpRawEvent,
+        HttpRawEvent, KerberosRawEvent, LdapRawEvent, MqttRawEvent, NfsRawEvent, NtlmRawEvent,
+        RdpRawEvent, SmbRawEvent, SmtpRawEvent, SshRawEvent, TlsRawEvent,
+    },
+    peer_in_charge_graphql_addr, FromKeyValue, NetworkFilter, RawEventFilter,
+};
+use crate::{
+    auth::check_source,
+    ingest::implement::EventFilter,
+    metrics,
+    publish::SUBSCRIPTION_CHANNEL_PREFIX,
+    storage::{Database, StorageKey},
+    StreamDirectChannels,
+};
+
+/// The events buffered for a subscriber that has not received them yet.
+const SUBSCRIPTION_BUFFER_SIZE: usize = 1024;
+
+// Tells apart the channels of the subscribers.
+static SUBSCRIPTION_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Default)]
+pub(super) struct EventSubscription;
+
+#[Subscription]
+impl EventSubscription {
+    /// Tails the conn events of the source, as they are ingested.
+    async fn conn_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = ConnRawEvent>> {
+        subscribe::<Conn, ConnRawEvent>(ctx, filter, "conn").await
+    }
+
+    /// Tails the DNS events of the source, as they are ingested.
+    async fn dns_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = DnsRawEvent>> {
+        subscribe::<Dns, DnsRawEvent>(ctx, filter, "dns").await
+    }
+
+    /// Tails the HTTP events of the source, as they are ingested.
+    async fn http_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = HttpRawEvent>> {
+        subscribe::<Http, HttpRawEvent>(ctx, filter, "http").await
+    }
+
+    /// Tails the RDP events of the source, as they are ingested.
+    async fn rdp_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = RdpRawEvent>> {
+        subscribe::<Rdp, RdpRawEvent>(ctx, filter, "rdp").await
+    }
+
+    /// Tails the SMTP events of the source, as they are ingested.
+    async fn smtp_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = SmtpRawEvent>> {
+        subscribe::<Smtp, SmtpRawEvent>(ctx, filter, "smtp").await
+    }
+
+    /// Tails the NTLM events of the source, as they are ingested.
+    async fn ntlm_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = NtlmRawEvent>> {
+        subscribe::<Ntlm, NtlmRawEvent>(ctx, filter, "ntlm").await
+    }
+
+    /// Tails the Kerberos events of the source, as they are ingested.
+    async fn kerberos_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = KerberosRawEvent>> {
+        subscribe::<Kerberos, KerberosRawEvent>(ctx, filter, "kerberos").await
+    }
+
+    /// Tails the SSH events of the source, as they are ingested.
+    async fn ssh_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = SshRawEvent>> {
+        subscribe::<Ssh, SshRawEvent>(ctx, filter, "ssh").await
+    }
+
+    /// Tails the DCE/RPC events of the source, as they are ingested.
+    async fn dce_rpc_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = DceRpcRawEvent>> {
+        subscribe::<DceRpc, DceRpcRawEvent>(ctx, filter, "dce rpc").await
+    }
+
+    /// Tails the FTP events of the source, as they are ingested.
+    async fn ftp_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = FtpRawEvent>> {
+        subscribe::<Ftp, FtpRawEvent>(ctx, filter, "ftp").await
+    }
+
+    /// Tails the MQTT events of the source, as they are ingested.
+    async fn mqtt_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = MqttRawEvent>> {
+        subscribe::<Mqtt, MqttRawEvent>(ctx, filter, "mqtt").await
+    }
+
+    /// Tails the LDAP events of the source, as they are ingested.
+    async fn ldap_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = LdapRawEvent>> {
+        subscribe::<Ldap, LdapRawEvent>(ctx, filter, "ldap").await
+    }
+
+    /// Tails the TLS events of the source, as they are ingested.
+    async fn tls_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = TlsRawEvent>> {
+        subscribe::<Tls, TlsRawEvent>(ctx, filter, "tls").await
+    }
+
+    /// Tails the SMB events of the source, as they are ingested.
+    async fn smb_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = SmbRawEvent>> {
+        subscribe::<Smb, SmbRawEvent>(ctx, filter, "smb").await
+    }
+
+    /// Tails the NFS events of the source, as they are ingested.
+    async fn nfs_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = NfsRawEvent>> {
+        subscribe::<Nfs, NfsRawEvent>(ctx, filter, "nfs").await
+    }
+
+    /// Tails the BOOTP events of the source, as they are ingested.
+    async fn bootp_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = BootpRawEvent>> {
+        subscribe::<Bootp, BootpRawEvent>(ctx, filter, "bootp").await
+    }
+
+    /// Tails the DHCP events of the source, as they are ingested.
+    async fn dhcp_raw_events<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        filter: NetworkFilter,
+    ) -> Result<impl Stream<Item = DhcpRawEvent>> {
+        subscribe::<Dhcp, DhcpRawEvent>(ctx, filter, "dhcp").await
+    }
+}
+
+/// Registers a subscriber to the events of the protocol, and returns the
+/// stream of the events that pass the filter.
+///
+/// The subscriber is removed from the stream channels once the stream is
+/// dropped. The start and the end of the subscription are recorded in the
+/// audit trail.
+///
+/// # Errors
+///
+/// Returns an error if the caller may not read the data of the source, or a
+/// peer is in charge of the source, since its events are not ingested here.
+async fn subscribe<T, N>(
+    ctx: &Context<'_>,
+    filter: NetworkFilter,
+    protocol: &'static str,
+) -> Result<impl Stream<Item = N>>
+where
+    T: DeserializeOwned + EventFilter + 'static,
+    N: FromKeyValue<T> + Send + 'static,
+{
+    check_source(ctx, &filter.source)?;
+    if !is_current_giganto_in_charge(ctx, &filter.source).await {
+        if let Some(peer_addr) = peer_in_charge_graphql_addr(ctx, &filter.source).await {
+            return Err(format!(
+                "the peer at {peer_addr} is in charge of {}, so subscribe to it instead",
+                filter.source
+            )
+            .into());
+        }
+    }
+    let channels = ctx.data::<StreamDirectChannels>()?.clone();
+    let db = ctx.data::<Database>()?.clone();
+    let audit = AuditRecord::subscription(ctx, &filter.source);
+    audit.start(&db);
+    let (tx, mut rx) = mpsc::channel(SUBSCRIPTION_BUFFER_SIZE);
+
+    task::spawn(async move {
+        // `send_direct_stream` sends the events of the source to the channels
+        // whose keys end with `\0{source}\0{protocol}`.
+        let key = format!(
+            "{SUBSCRIPTION_CHANNEL_PREFIX}{}\0{}\0{protocol}",
+            SUBSCRIPTION_ID.fetch_add(1, Ordering::Relaxed),
+            filter.source
+        );
+        let (event_tx, mut event_rx) = unbounded_channel::<Vec<u8>>();
+        channels.write().await.insert(key.clone(), event_tx);
+
+        // The events are moved out of the unbounded channel as soon as they
+        // arrive, so that only the bounded buffer fills up with a slow
+        // subscriber.
+        let mut sent = 0;
+        loop {
+            let message = tokio::select! {
+                () = tx.closed() => break,
+                message = event_rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+            };
+            let Some(event) = decode::<T, N>(&message, &filter) else {
+                continue;
+            };
+            match tx.try_send(event) {
+                Ok(()) => sent += 1,
+                Err(TrySendError::Full(_)) => metrics::record_subscription_drop(protocol),
+                Err(TrySendError::Closed(_)) => break,
+            }
+        }
+        // The channel is removed before `event_rx` is dropped, so that the
+        // ingest server never sends to a closed channel.
+        channels.write().await.remove(&key);
+        audit.end(&db, sent);
+    });
+
+    Ok(stream::poll_fn(move |cx| rx.poll_recv(cx)))
+}
+
+/// Returns the event in a message of `send_direct_stream` if it is of the
+/// source of the filter and passes the filter.
+///
+/// The key of a subscription to `all` also receives the events of the other
+/// sources, which are dropped here.
+fn decode<T, N>(message: &[u8], filter: &NetworkFilter) -> Option<N>
+where
+    T: DeserializeOwned + EventFilter,
+    N: FromKeyValue<T>,
+{
+    let (timestamp, source, raw_event) = split_message(message)?;
+    if source != filter.source {
+        return None;
+    }
+    if let Some(time) = &filter.time {
+        let time_of_event = Utc.timestamp_nanos(timestamp);
+        if time.start.is_some_and(|start| time_of_event < start)
+            || time.end.is_some_and(|end| time_of_event >= end)
+        {
+            return None;
+        }
+    }
+    let event: T = bincode::deserialize(raw_event).ok()?;
+    let passed = filter
+        .check(
+            event.orig_addr(),
+            event.resp_addr(),
+            event.orig_port(),
+            event.resp_port(),
+            event.log_level(),
+            event.log_contents(),
+            event.text(),
+            event.source(),
+            event.agent_id(),
+        )
+        .ok()?;
+    if !passed {
+        return None;
+    }
+    let key = StorageKey::builder()
+        .start_key(&filter.source)
+        .end_key(timestamp)
+        .sequence(0)
+        .build()
+        .key();
+    N::from_key_value(&key, event).ok()
+}
+
+/// Splits a message of `send_direct_stream` into the timestamp, the source,
+/// and the raw event.
+fn split_message(message: &[u8]) -> Option<(i64, String, &[u8])> {
+    let (timestamp, rest) = message.split_first_chunk::<8>()?;
+    let (len, rest) = rest.split_first_chunk::<4>()?;
+    let len = usize::try_from(u32::from_le_bytes(*len)).ok()?;
+    let source: String = bincode::deserialize(rest.get(..len)?).ok()?;
+    let rest = rest.get(len..)?;
+    let (len, raw_event) = rest.split_first_chunk::<4>()?;
+    let raw_event = raw_event.get(..usize::try_from(u32::from_le_bytes(*len)).ok()?)?;
+    Some((i64::from_le_bytes(*timestamp), source, raw_event))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::Duration};
+
+    use futures_util::StreamExt;
+    use giganto_client::ingest::network::Conn;
+
+    use super::SUBSCRIPTION_BUFFER_SIZE;
+    use crate::{graphql::tests::TestSchema, ingest::NetworkKey, publish::send_direct_stream};
+
+    const PEER_PORT: u16 = 60_192;
+
+    fn conn(resp_port: u16) -> Vec<u8> {
+        let conn = Conn {
+            orig_addr: "192.168.4.76".parse::<IpAddr>().unwrap(),
+            orig_port: 46378,
+            resp_addr: "192.168.4.77".parse::<IpAddr>().unwrap(),
+            resp_port,
+            proto: 6,
+            conn_state: "sf".to_string(),
+            duration: 12345,
+            service: "-".to_string(),
+            orig_bytes: 77,
+            resp_bytes: 295,
+            orig_pkts: 397,
+            resp_pkts: 511,
+            orig_l2_bytes: 21515,
+            resp_l2_bytes: 27889,
+        };
+        bincode::serialize(&conn).unwrap()
+    }
+
+    #[tokio::test]
+    async fn conn_raw_events() {
+        let schema = TestSchema::new();
+        let query = r#"
+        subscription {
+            connRawEvents(filter: { source: "src 1", respPort: { start: 80, end: 81 } }) {
+                timestamp
+                respAddr
+                respPort
+            }
+        }"#;
+        let mut stream = schema.schema.execute_stream(query);
+        let events = tokio::spawn(async move {
+            let first = stream.next().await.unwrap();
+            let second = stream.next().await.unwrap();
+            (first, second)
+        });
+        while schema.stream_direct_channels.read().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let network_key = NetworkKey::new("src 1", "conn");
+        let other_key = NetworkKey::new("src 2", "conn");
+        for (key, resp_port, timestamp) in [
+            (&network_key, 80, 1_000_000_000),
+            (&network_key, 443, 2_000_000_000),
+            (&other_key, 80, 3_000_000_000),
+            (&network_key, 80, 4_000_000_000),
+        ] {
+            let channels = schema.stream_direct_channels.clone();
+            send_direct_stream(key, &conn(resp_port), timestamp, "src 1", channels)
+                .await
+                .unwrap();
+        }
+
+        let (first, second) = events.await.unwrap();
+        assert_eq!(
+            first.data.to_string(),
+            "{connRawEvents: {timestamp: \"1970-01-01T00:00:01+00:00\", \
+            respAddr: \"192.168.4.77\", respPort: 80}}"
+        );
+        assert_eq!(
+            second.data.to_string(),
+            "{connRawEvents: {timestamp: \"1970-01-01T00:00:04+00:00\", \
+            respAddr: \"192.168.4.77\", respPort: 80}}"
+        );
+
+        // The subscriber is removed once the stream is dropped.
+        while !schema.stream_direct_channels.read().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn similar_sources() {
+        let schema = TestSchema::new();
+        let subscribe = |source: &str| {
+            let query = format!(
+                r#"subscription {{ connRawEvents(filter: {{ source: "{source}" }}) {{ timestamp }} }}"#
+            );
+            let mut stream = schema.schema.execute_stream(query);
+            tokio::spawn(async move { stream.next().await.unwrap() })
+        };
+        let ax = subscribe("ax");
+        let mall = subscribe("mall");
+        let all = subscribe("all");
+        while schema.stream_direct_channels.read().await.len() < 3 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // The keys of `ax` and `mall` contain the keys of the events of `x`,
+        // `x\0conn` and `all\0conn`, as substrings.
+        for (source, timestamp) in [
+            ("x", 1_000_000_000),
+            ("ax", 2_000_000_000),
+            ("mall", 3_000_000_000),
+            ("all", 4_000_000_000),
+        ] {
+            let network_key = NetworkKey::new(source, "conn");
+            let channels = schema.stream_direct_channels.clone();
+            send_direct_stream(&network_key, &conn(80), timestamp, source, channels)
+                .await
+                .unwrap();
+        }
+
+        for (subscription, timestamp) in [
+            (ax, "1970-01-01T00:00:02+00:00"),
+            (mall, "1970-01-01T00:00:03+00:00"),
+            (all, "1970-01-01T00:00:04+00:00"),
+        ] {
+            assert_eq!(
+                subscription.await.unwrap().data.to_string(),
+                format!("{{connRawEvents: {{timestamp: \"{timestamp}\"}}}}")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn audit_subscription() {
+        let schema = TestSchema::new();
+        let query = r#"subscription { connRawEvents(filter: { source: "src 1" }) { timestamp } }"#;
+        let mut stream = schema.schema.execute_stream(query);
+        let event = tokio::spawn(async move { stream.next().await.unwrap() });
+        while schema.stream_direct_channels.read().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let store = schema.db.audit_store().unwrap();
+        let entries = store.search(None, None, None, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].fields, ["connRawEvents"]);
+        assert_eq!(entries[0].sources, ["src 1"]);
+        assert_eq!(entries[0].variables, r#"{"filter":{"source":"src 1"}}"#);
+
+        let network_key = NetworkKey::new("src 1", "conn");
+        let channels = schema.stream_direct_channels.clone();
+        send_direct_stream(&network_key, &conn(80), 1, "src 1", channels)
+            .await
+            .unwrap();
+        event.await.unwrap();
+
+        // The end is recorded once the stream is dropped.
+        let mut entries = Vec::new();
+        for _ in 0..100 {
+            entries = store.search(None, None, None, 10).unwrap();
+            if entries.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].fields, ["connRawEvents"]);
+        assert_eq!(entries[0].result_count, 1);
+    }
+
+    #[tokio::test]
+    async fn source_of_peer() {
+        let schema = TestSchema::new_with_graphql_peer(PEER_PORT);
+        let query = r#"subscription { connRawEvents(filter: { source: "src 2" }) { timestamp } }"#;
+        let mut stream = schema.schema.execute_stream(query);
+        let res = stream.next().await.unwrap();
+        assert_eq!(
+            res.errors.first().unwrap().message,
+            format!(
+                "the peer at 127.0.0.1:{PEER_PORT} is in charge of src 2, so subscribe to it \
+                instead"
+            )
+        );
+        assert!(schema.stream_direct_channels.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn slow_subscriber() {
+        let schema = TestSchema::new();
+        let query = r#"subscription { connRawEvents(filter: { source: "src 1" }) { timestamp } }"#;
+        let mut stream = schema.schema.execute_stream(query);
+        // Starts the subscription without receiving any event.
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), stream.next())
+                .await
+                .is_err()
+        );
+        while schema.stream_direct_channels.read().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let network_key = NetworkKey::new("src 1", "conn");
+        let sent = i64::try_from(SUBSCRIPTION_BUFFER_SIZE).unwrap() + 10;
+        for timestamp in 0..sent {
+            let channels = schema.stream_direct_channels.clone();
+            send_direct_stream(&network_key, &conn(80), timestamp, "src 1", channels)
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut received = 0;
+        while tokio::time::timeout(Duration::from_millis(100), stream.next())
+            .await
+            .is_ok()
+        {
+            received += 1;
+        }
+        assert_eq!(received, SUBSCRIPTION_BUFFER_SIZE);
+    }
+}
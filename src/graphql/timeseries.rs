@@ -87,6 +87,7 @@ impl TimeSeriesQuery {
         first: Option<i32>,
         last: Option<i32>,
     ) -> Result<Connection<String, TimeSeries>> {
+        crate::auth::check_all_sources(ctx)?;
         let db = ctx.data::<Database>()?;
         let store = db.periodic_time_series_store()?;
 
@@ -105,9 +106,16 @@ impl TimeSeriesQuery {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
+    use async_graphql::Request;
     use giganto_client::ingest::timeseries::PeriodicTimeSeries;
 
-    use crate::{graphql::tests::TestSchema, storage::RawEventStore};
+    use crate::{
+        auth::{Caller, Role},
+        graphql::tests::TestSchema,
+        storage::RawEventStore,
+    };
 
     #[tokio::test]
     async fn time_series_empty() {
@@ -126,6 +134,32 @@ mod tests {
         assert_eq!(res.data.to_string(), "{periodicTimeSeries: {edges: []}}");
     }
 
+    #[tokio::test]
+    async fn time_series_sources_not_allowed() {
+        let schema = TestSchema::new();
+        let query = r#"
+        {
+            periodicTimeSeries (filter: {id: "src 1"}, first: 1) {
+                edges {
+                    node {
+                        id
+                    }
+                }
+            }
+        }"#;
+        let caller = Caller::new(
+            "analyst".to_string(),
+            Role::Read,
+            Some(HashSet::from(["src 1".to_string()])),
+        );
+        let request = Request::new(query).data(caller);
+        let res = schema.schema.execute(request).await;
+        assert_eq!(
+            res.errors.first().unwrap().message,
+            "a source must be given to read the data of"
+        );
+    }
+
     #[tokio::test]
     async fn time_series_with_data() {
         let schema = TestSchema::new();
@@ -165,6 +199,7 @@ mod tests {
         key.extend_from_slice(id.as_bytes());
         key.push(0);
         key.extend_from_slice(&start.to_be_bytes());
+        key.extend_from_slice(&0_u32.to_be_bytes());
         let time_series_data = PeriodicTimeSeries {
             id: id.to_string(),
             data,
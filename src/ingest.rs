@@ -1,6 +1,8 @@
 pub mod implement;
+mod limit;
 #[cfg(test)]
 mod tests;
+mod timestamp;
 
 use std::{
     net::SocketAddr,
@@ -15,7 +17,6 @@ use anyhow::{anyhow, bail, Context, Result};
 use chrono::{DateTime, Utc};
 use giganto_client::frame::recv_raw;
 use giganto_client::{
-    connection::server_handshake,
     frame::{self, RecvError, SendError},
     ingest::{
         log::{Log, OpLog, SecuLog},
@@ -26,7 +27,13 @@ use giganto_client::{
     },
     RawEventKind,
 };
+use limit::Admission;
+pub use limit::{IngestLimiter, LimitUsage};
 use quinn::{Endpoint, RecvStream, SendStream, ServerConfig};
+use semver::{Version, VersionReq};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use timestamp::TimestampCheck;
+pub use timestamp::{ClockSkew, TimestampChecker};
 use tokio::{
     select,
     sync::{
@@ -36,17 +43,20 @@ use tokio::{
     task, time,
     time::sleep,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use x509_parser::nom::AsBytes;
 
+use crate::metrics;
 use crate::publish::send_direct_stream;
 use crate::server::{
     config_server, extract_cert_from_conn, subject_from_cert_verbose, Certs,
     SERVER_CONNNECTION_DELAY, SERVER_ENDPOINT_DELAY,
 };
-use crate::storage::{Database, RawEventStore, StorageKey};
+use crate::settings::SyncPolicy;
+use crate::storage::{encode_source_id, Database, RawEventStore, StorageKey};
 use crate::{
-    AckTransmissionCount, IngestSources, PcapSources, RunTimeIngestSources, StreamDirectChannels,
+    AckTransmissionCount, IngestSources, PcapSources, RunTimeIngestSources, SourceJobs,
+    StreamDirectChannels,
 };
 
 const ACK_INTERVAL_TIME: u64 = 60;
@@ -54,18 +64,56 @@ const CHANNEL_CLOSE_MESSAGE: &[u8; 12] = b"channel done";
 const CHANNEL_CLOSE_TIMESTAMP: i64 = -1;
 const NO_TIMESTAMP: i64 = 0;
 const SOURCE_INTERVAL: u64 = 60 * 60 * 24;
+// The wire protocol is the same as in 0.22.1, so the clients of that version
+// are still accepted. It is bumped together with `PUBLISH_VERSION_REQ`
+// when the protocol changes.
 const INGEST_VERSION_REQ: &str = ">=0.21.0,<0.23.0";
 
 type SourceInfo = (String, DateTime<Utc>, ConnState, bool);
 
+/// A received record that could not be decoded.
+///
+/// It is kept in the dead letter store instead of the store of its kind, so
+/// that a bad record doesn't stop the ingestion of the following ones.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub source: String,
+    pub kind: String,
+    pub timestamp: i64,
+    pub error: String,
+    pub raw_event: Vec<u8>,
+}
+
+impl DeadLetter {
+    fn new(
+        source: &str,
+        kind: RawEventKind,
+        timestamp: i64,
+        error: &bincode::Error,
+        raw_event: Vec<u8>,
+    ) -> Self {
+        Self {
+            source: source.to_string(),
+            kind: kind.to_string(),
+            timestamp,
+            error: error.to_string(),
+            raw_event,
+        }
+    }
+}
+
 enum ConnState {
-    Connected,
+    Connected {
+        agent: String,
+        protocol_version: String,
+    },
     Disconnected,
 }
 
 pub struct Server {
     server_config: ServerConfig,
     server_address: SocketAddr,
+    bound: Arc<AtomicBool>,
 }
 
 impl Server {
@@ -75,9 +123,16 @@ impl Server {
         Server {
             server_config,
             server_address: addr,
+            bound: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Returns the flag that tells whether the server is accepting
+    /// connections.
+    pub fn bound(&self) -> Arc<AtomicBool> {
+        self.bound.clone()
+    }
+
     #[allow(clippy::too_many_lines, clippy::too_many_arguments)]
     pub async fn run(
         self,
@@ -89,8 +144,14 @@ impl Server {
         notify_shutdown: Arc<Notify>,
         notify_source: Option<Arc<Notify>>,
         ack_transmission_cnt: AckTransmissionCount,
+        sync_policy: SyncPolicy,
+        sync_interval: Duration,
+        limiter: Arc<IngestLimiter>,
+        timestamp_checker: Arc<TimestampChecker>,
+        source_jobs: SourceJobs,
     ) {
         let endpoint = Endpoint::server(self.server_config, self.server_address).expect("endpoint");
+        self.bound.store(true, Ordering::SeqCst);
         info!(
             "listening on {}",
             endpoint.local_addr().expect("for local addr display")
@@ -109,6 +170,18 @@ impl Server {
 
         let shutdown_signal = Arc::new(AtomicBool::new(false));
 
+        if sync_policy == SyncPolicy::Periodic && sync_interval.is_zero() {
+            error!(
+                "The sync interval must be greater than zero. The data is not synced periodically"
+            );
+        } else if sync_policy == SyncPolicy::Periodic {
+            task::spawn(sync_periodically(
+                db.clone(),
+                sync_interval,
+                shutdown_signal.clone(),
+            ));
+        }
+
         loop {
             select! {
                 Some(conn) = endpoint.accept()  => {
@@ -119,10 +192,13 @@ impl Server {
                     let notify_shutdown = notify_shutdown.clone();
                     let shutdown_sig = shutdown_signal.clone();
                     let ack_trans_cnt= ack_transmission_cnt.clone();
+                    let limiter = limiter.clone();
+                    let timestamp_checker = timestamp_checker.clone();
+                    let source_jobs = source_jobs.clone();
                     tokio::spawn(async move {
                         let remote = conn.remote_address();
                         if let Err(e) =
-                            handle_connection(conn, db, pcap_sources, sender, stream_direct_channels,notify_shutdown,shutdown_sig,ack_trans_cnt).await
+                            handle_connection(conn, db, pcap_sources, sender, stream_direct_channels,notify_shutdown,shutdown_sig,ack_trans_cnt,sync_policy,limiter,timestamp_checker,source_jobs).await
                         {
                             error!("connection failed: {e}. {remote}");
                         }
@@ -132,6 +208,7 @@ impl Server {
                     shutdown_signal.store(true,Ordering::SeqCst); // Setting signal to handle termination on each channel.
                     sleep(Duration::from_millis(SERVER_ENDPOINT_DELAY)).await;      // Wait time for channels,connection to be ready for shutdown.
                     endpoint.close(0_u32.into(), &[]);
+                    self.bound.store(false, Ordering::SeqCst);
                     info!("Shutting down ingest");
                     notify_shutdown.notify_one();
                     break;
@@ -151,12 +228,17 @@ async fn handle_connection(
     notify_shutdown: Arc<Notify>,
     shutdown_signal: Arc<AtomicBool>,
     ack_trans_cnt: AckTransmissionCount,
+    sync_policy: SyncPolicy,
+    limiter: Arc<IngestLimiter>,
+    timestamp_checker: Arc<TimestampChecker>,
+    source_jobs: SourceJobs,
 ) -> Result<()> {
     let connection = conn.await?;
-    match server_handshake(&connection, INGEST_VERSION_REQ).await {
-        Ok((mut send, _)) => {
+    let protocol_version = match handshake(&connection).await {
+        Ok((mut send, version)) => {
             info!("Compatible version");
             send.finish()?;
+            version
         }
         Err(e) => {
             info!("Incompatible version");
@@ -168,6 +250,19 @@ async fn handle_connection(
     let (agent, source) = subject_from_cert_verbose(&extract_cert_from_conn(&connection)?)?;
     let rep = agent.contains("reproduce");
 
+    // The data ingested while a job deletes or renames the source would be
+    // left behind by the job.
+    if let Some(job) = source_jobs
+        .read()
+        .await
+        .iter()
+        .find(|job| job.is_running() && job.involves(&source))
+    {
+        let reason = format!("{source} is being changed by job {}", job.id);
+        connection.close(quinn::VarInt::from_u32(0), reason.as_bytes());
+        bail!("{reason}");
+    }
+
     if !rep {
         pcap_sources
             .write()
@@ -178,7 +273,15 @@ async fn handle_connection(
     }
 
     if let Err(error) = sender
-        .send((source.clone(), Utc::now(), ConnState::Connected, rep))
+        .send((
+            source.clone(),
+            Utc::now(),
+            ConnState::Connected {
+                agent,
+                protocol_version,
+            },
+            rep,
+        ))
         .await
     {
         error!("Failed to send channel data : {error}");
@@ -209,8 +312,10 @@ async fn handle_connection(
                 let stream_direct_channels = stream_direct_channels.clone();
                 let shutdown_signal = shutdown_signal.clone();
                 let ack_trans_cnt = ack_trans_cnt.clone();
+                let limiter = limiter.clone();
+                let timestamp_checker = timestamp_checker.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = handle_request(source, stream, db, stream_direct_channels,shutdown_signal,ack_trans_cnt).await {
+                    if let Err(e) = handle_request(source, stream, db, stream_direct_channels,shutdown_signal,ack_trans_cnt,sync_policy,limiter,timestamp_checker).await {
                         error!("failed: {e}");
                     }
                 });
@@ -225,7 +330,54 @@ async fn handle_connection(
     }
 }
 
-#[allow(clippy::too_many_lines)]
+/// Decodes the record, or keeps it in `dead_letters` if it cannot be decoded.
+fn deserialize_or_dead_letter<T: DeserializeOwned>(
+    source: &str,
+    kind: RawEventKind,
+    timestamp: i64,
+    raw_event: &[u8],
+    dead_letters: &mut Vec<DeadLetter>,
+) -> Option<T> {
+    match bincode::deserialize(raw_event) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            dead_letters.push(DeadLetter::new(
+                source,
+                kind,
+                timestamp,
+                &e,
+                raw_event.to_vec(),
+            ));
+            None
+        }
+    }
+}
+
+/// Processes the handshake of a connection as `server_handshake` does, and
+/// returns the protocol version of the client along with the stream.
+///
+/// The client sends its version as a bincode-encoded string, and is answered
+/// with the version requirement if the version meets it, or with nothing.
+async fn handshake(connection: &quinn::Connection) -> Result<(SendStream, String)> {
+    let (mut send, mut recv) = connection.accept_bi().await?;
+    let mut buf = Vec::new();
+    recv_raw(&mut recv, &mut buf).await?;
+    let version: String = bincode::deserialize(&buf).context("invalid handshake message")?;
+    let compatible = Version::parse(&version).is_ok_and(|version| {
+        VersionReq::parse(INGEST_VERSION_REQ)
+            .expect("valid version requirement")
+            .matches(&version)
+    });
+    let response = compatible.then_some(INGEST_VERSION_REQ);
+    frame::send_raw(&mut send, &bincode::serialize(&response)?).await?;
+    if !compatible {
+        send.finish().ok();
+        bail!("incompatible protocol version {version}, required {INGEST_VERSION_REQ}");
+    }
+    Ok((send, version))
+}
+
+#[allow(clippy::too_many_lines, clippy::too_many_arguments)]
 async fn handle_request(
     source: String,
     (send, mut recv): (SendStream, RecvStream),
@@ -233,6 +385,9 @@ async fn handle_request(
     stream_direct_channels: StreamDirectChannels,
     shutdown_signal: Arc<AtomicBool>,
     ack_trans_cnt: AckTransmissionCount,
+    sync_policy: SyncPolicy,
+    limiter: Arc<IngestLimiter>,
+    timestamp_checker: Arc<TimestampChecker>,
 ) -> Result<()> {
     let mut buf = [0; 4];
     receive_record_header(&mut recv, &mut buf)
@@ -250,6 +405,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -264,6 +423,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -278,6 +441,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -292,6 +459,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -306,6 +477,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -320,6 +495,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -334,6 +513,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -348,6 +531,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -362,6 +549,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -376,6 +567,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -390,6 +585,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -404,6 +603,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -418,6 +621,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -432,6 +639,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -446,6 +657,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -460,6 +675,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -474,6 +693,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -488,6 +711,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -502,6 +729,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -516,6 +747,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -530,6 +765,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -544,6 +783,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -558,6 +801,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -572,6 +819,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -586,6 +837,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -600,6 +855,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -614,6 +873,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -628,6 +891,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -642,6 +909,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -656,6 +927,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -670,6 +945,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -684,6 +963,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -698,6 +981,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -712,6 +999,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -726,6 +1017,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -740,6 +1035,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -754,6 +1053,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -768,6 +1071,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -782,6 +1089,10 @@ async fn handle_request(
                 stream_direct_channels,
                 shutdown_signal,
                 ack_trans_cnt,
+                &db,
+                sync_policy,
+                limiter,
+                timestamp_checker,
             )
             .await?;
         }
@@ -803,6 +1114,10 @@ async fn handle_data<T>(
     stream_direct_channels: StreamDirectChannels,
     shutdown_signal: Arc<AtomicBool>,
     ack_trans_cnt: AckTransmissionCount,
+    db: &Database,
+    sync_policy: SyncPolicy,
+    limiter: Arc<IngestLimiter>,
+    timestamp_checker: Arc<TimestampChecker>,
 ) -> Result<()> {
     let sender_rotation = Arc::new(Mutex::new(send));
     let sender_interval = Arc::clone(&sender_rotation);
@@ -820,6 +1135,7 @@ async fn handle_data<T>(
 
     let mut err_msg = None;
     let stream_id = recv.id();
+    let db_interval = db.clone();
 
     #[cfg(feature = "benchmark")]
     let mut count = 0_usize;
@@ -834,6 +1150,10 @@ async fn handle_data<T>(
                 _ = itv.tick() => {
                     let last_timestamp = ack_time_interval.load(Ordering::SeqCst);
                     if last_timestamp !=  NO_TIMESTAMP {
+                        if let Err(e) = db_interval.flush_wal(sync_policy) {
+                            error!("Failed to flush the ingested data: {e}");
+                            break;
+                        }
                         if send_ack_timestamp(&mut (*sender_interval.lock().await),last_timestamp).await.is_err()
                         {
                             break;
@@ -851,6 +1171,12 @@ async fn handle_data<T>(
     });
     let mut buf: Vec<u8> = Vec::new();
     let mut last_timestamp = 0;
+    // The keys start with the ID of the source instead of its name.
+    let sources = db.sources_store()?;
+    let source_id = sources.assign_id(&source)?;
+    let source_key = encode_source_id(source_id);
+    let kind = raw_event_kind.to_string();
+    let counters = db.ingest_counter_store()?;
     loop {
         buf.clear();
         match recv_raw(&mut recv, &mut buf).await {
@@ -865,67 +1191,110 @@ async fn handle_data<T>(
                 let mut packet_size = 0_u64;
                 #[cfg(feature = "benchmark")]
                 let mut packet_count = 0_u64;
+                // The events in a frame are written at once, after all of them are decoded.
+                let mut raw_events = Vec::with_capacity(recv_buf.len());
+                let mut dead_letters = Vec::new();
+                let mut channel_closed = false;
+                let dropped = !admit(&limiter, &source, &kind, &recv_buf, &shutdown_signal).await;
+                let now = Utc::now();
+                let now_nanos = now.timestamp_nanos_opt().unwrap_or(i64::MAX);
+                let mut latest_timestamp = None;
+                let mut out_of_window = 0_u64;
+                let mut quarantined = Vec::new();
                 for (timestamp, raw_event) in recv_buf {
                     last_timestamp = timestamp;
                     if (timestamp == CHANNEL_CLOSE_TIMESTAMP)
                         && (raw_event.as_bytes() == CHANNEL_CLOSE_MESSAGE)
                     {
-                        if let Err(e) =
-                            send_ack_timestamp(&mut (*sender_rotation.lock().await), timestamp)
-                                .await
-                        {
-                            err_msg = Some(format!("Failed to send ack timestamp: {e}"));
-                            break;
-                        }
+                        channel_closed = true;
+                        continue;
+                    }
+                    // Undecodable records are acknowledged as well, once they
+                    // are stored as dead letters.
+                    recv_events_cnt += 1;
+                    latest_timestamp = latest_timestamp.max(Some(timestamp));
+                    // So are the records dropped by a limit.
+                    if dropped {
                         continue;
                     }
-                    let key_builder = StorageKey::builder().start_key(&source);
+                    // The acknowledged timestamp is still the one the sender
+                    // gave, even if the event is stored with another.
+                    let check = timestamp_checker.check(now_nanos, timestamp);
+                    if check != TimestampCheck::Accept {
+                        out_of_window += 1;
+                    }
+                    let timestamp = match check {
+                        TimestampCheck::Clamp(clamped) => clamped,
+                        TimestampCheck::Reject => continue,
+                        TimestampCheck::Accept | TimestampCheck::Quarantine => timestamp,
+                    };
+                    let key_builder = StorageKey::builder().start_key(&source_key);
                     let key_builder = match raw_event_kind {
                         RawEventKind::Log => {
-                            let Ok(log) = bincode::deserialize::<Log>(&raw_event) else {
-                                err_msg = Some("Failed to deserialize Log".to_string());
-                                break;
+                            let Some(log) = deserialize_or_dead_letter::<Log>(
+                                &source,
+                                raw_event_kind,
+                                timestamp,
+                                &raw_event,
+                                &mut dead_letters,
+                            ) else {
+                                continue;
                             };
                             key_builder
                                 .mid_key(Some(log.kind.as_bytes().to_vec()))
                                 .end_key(timestamp)
                         }
                         RawEventKind::PeriodicTimeSeries => {
-                            let Ok(time_series) =
-                                bincode::deserialize::<PeriodicTimeSeries>(&raw_event)
-                            else {
-                                err_msg =
-                                    Some("Failed to deserialize PeriodicTimeSeries".to_string());
-                                break;
+                            let Some(time_series) = deserialize_or_dead_letter::<PeriodicTimeSeries>(
+                                &source,
+                                raw_event_kind,
+                                timestamp,
+                                &raw_event,
+                                &mut dead_letters,
+                            ) else {
+                                continue;
                             };
                             StorageKey::builder()
                                 .start_key(&time_series.id)
                                 .end_key(timestamp)
                         }
                         RawEventKind::OpLog => {
-                            let Ok(op_log) = bincode::deserialize::<OpLog>(&raw_event) else {
-                                err_msg = Some("Failed to deserialize OpLog".to_string());
-                                break;
+                            let Some(op_log) = deserialize_or_dead_letter::<OpLog>(
+                                &source,
+                                raw_event_kind,
+                                timestamp,
+                                &raw_event,
+                                &mut dead_letters,
+                            ) else {
+                                continue;
                             };
-                            let agent_id = format!("{}@{source}", op_log.agent_name);
-                            StorageKey::builder()
-                                .start_key(&agent_id)
+                            key_builder
+                                .mid_key(Some(op_log.agent_name.as_bytes().to_vec()))
                                 .end_key(timestamp)
                         }
                         RawEventKind::Packet => {
-                            let Ok(packet) = bincode::deserialize::<Packet>(&raw_event) else {
-                                err_msg = Some("Failed to deserialize Packet".to_string());
-                                break;
+                            let Some(packet) = deserialize_or_dead_letter::<Packet>(
+                                &source,
+                                raw_event_kind,
+                                timestamp,
+                                &raw_event,
+                                &mut dead_letters,
+                            ) else {
+                                continue;
                             };
                             key_builder
                                 .mid_key(Some(timestamp.to_be_bytes().to_vec()))
                                 .end_key(packet.packet_timestamp)
                         }
                         RawEventKind::Statistics => {
-                            let Ok(statistics) = bincode::deserialize::<Statistics>(&raw_event)
-                            else {
-                                err_msg = Some("Failed to deserialize Statistics".to_string());
-                                break;
+                            let Some(statistics) = deserialize_or_dead_letter::<Statistics>(
+                                &source,
+                                raw_event_kind,
+                                timestamp,
+                                &raw_event,
+                                &mut dead_letters,
+                            ) else {
+                                continue;
                             };
                             #[cfg(feature = "benchmark")]
                             {
@@ -941,9 +1310,14 @@ async fn handle_data<T>(
                                 .end_key(timestamp)
                         }
                         RawEventKind::SecuLog => {
-                            let Ok(secu_log) = bincode::deserialize::<SecuLog>(&raw_event) else {
-                                err_msg = Some("Failed to deserialize SecuLog".to_string());
-                                break;
+                            let Some(secu_log) = deserialize_or_dead_letter::<SecuLog>(
+                                &source,
+                                raw_event_kind,
+                                timestamp,
+                                &raw_event,
+                                &mut dead_letters,
+                            ) else {
+                                continue;
                             };
                             key_builder
                                 .mid_key(Some(secu_log.kind.as_bytes().to_vec()))
@@ -952,15 +1326,71 @@ async fn handle_data<T>(
                         _ => key_builder.end_key(timestamp),
                     };
 
-                    recv_events_cnt += 1;
+                    // The events with the same key are told apart by the
+                    // sequence numbers `append_unique` appends to it.
+                    let key_prefix = key_builder.build().key();
+                    if check == TimestampCheck::Quarantine {
+                        quarantined.push((key_prefix, raw_event));
+                        continue;
+                    }
                     recv_events_len += raw_event.len();
-                    let storage_key = key_builder.build();
-                    store.append(&storage_key.key(), &raw_event)?;
-                    if let Some(network_key) = network_key.as_ref() {
+                    raw_events.push((timestamp, key_prefix, raw_event));
+                }
+
+                // The dead letters are written with the events, so that they
+                // are not lost once the frame is acknowledged.
+                let dead_letters = dead_letters
+                    .iter()
+                    .map(|dead_letter| {
+                        let key = StorageKey::builder()
+                            .start_key(&source_key)
+                            .end_key(dead_letter.timestamp)
+                            .build()
+                            .key();
+                        Ok((key, bincode::serialize(dead_letter)?))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                store.append_unique(
+                    raw_events
+                        .iter()
+                        .map(|(_, key, raw_event)| (key.as_slice(), raw_event.as_slice())),
+                    quarantined
+                        .iter()
+                        .map(|(key, raw_event)| (key.as_slice(), raw_event.as_slice())),
+                    dead_letters
+                        .iter()
+                        .map(|(key, dead_letter)| (key.as_slice(), dead_letter.as_slice())),
+                )?;
+                if !dead_letters.is_empty() {
+                    warn!(
+                        "{} undecodable record(s) moved to the dead letter store",
+                        dead_letters.len()
+                    );
+                }
+                let last_event = raw_events.iter().map(|(timestamp, _, _)| *timestamp).max();
+                if let Some(timestamp) = last_event {
+                    sources.set_last_event(source_id, &kind, timestamp)?;
+                }
+                if !raw_events.is_empty() {
+                    let events = raw_events.len().try_into().unwrap_or(u64::MAX);
+                    let bytes = recv_events_len.try_into().unwrap_or(u64::MAX);
+                    counters.add(source_id, &kind, Utc::now(), events, bytes)?;
+                    metrics::record_ingest(&kind, events, bytes);
+                }
+                if let Some(latest_timestamp) = latest_timestamp {
+                    timestamp_checker.observe(&source, now, latest_timestamp, out_of_window);
+                }
+                if out_of_window > 0 {
+                    warn!(
+                        "{out_of_window} {kind} event(s) from {source} outside the timestamp window"
+                    );
+                }
+                if let Some(network_key) = network_key.as_ref() {
+                    for (timestamp, _, raw_event) in &raw_events {
                         if let Err(e) = send_direct_stream(
                             network_key,
-                            &raw_event,
-                            timestamp,
+                            raw_event,
+                            *timestamp,
                             &source,
                             stream_direct_channels.clone(),
                         )
@@ -972,6 +1402,18 @@ async fn handle_data<T>(
                     }
                 }
 
+                if channel_closed && err_msg.is_none() {
+                    db.flush_wal(sync_policy)?;
+                    if let Err(e) = send_ack_timestamp(
+                        &mut (*sender_rotation.lock().await),
+                        CHANNEL_CLOSE_TIMESTAMP,
+                    )
+                    .await
+                    {
+                        err_msg = Some(format!("Failed to send ack timestamp: {e}"));
+                    }
+                }
+
                 if err_msg.is_some() {
                     break;
                 }
@@ -979,11 +1421,12 @@ async fn handle_data<T>(
                 ack_cnt_rotation.fetch_add(recv_events_cnt, Ordering::SeqCst);
                 ack_time_rotation.store(last_timestamp, Ordering::SeqCst);
                 if *ack_trans_cnt.read().await <= ack_cnt_rotation.load(Ordering::SeqCst) {
+                    // The acknowledged data must survive a crash.
+                    db.flush_wal(sync_policy)?;
                     send_ack_timestamp(&mut (*sender_rotation.lock().await), last_timestamp)
                         .await?;
                     ack_cnt_rotation.store(0, Ordering::SeqCst);
                     ack_time_notify.notify_one();
-                    store.flush()?;
                 }
 
                 #[cfg(feature = "benchmark")]
@@ -1031,6 +1474,58 @@ async fn handle_data<T>(
     Ok(())
 }
 
+/// Waits until the limits let the events in, and returns whether they are
+/// accepted, or `false` if they are to be dropped.
+///
+/// The events are accepted without waiting further once the server is
+/// shutting down.
+async fn admit(
+    limiter: &IngestLimiter,
+    source: &str,
+    kind: &str,
+    events: &[(i64, Vec<u8>)],
+    shutdown_signal: &AtomicBool,
+) -> bool {
+    let (count, bytes) = events
+        .iter()
+        .filter(|(timestamp, raw_event)| {
+            *timestamp != CHANNEL_CLOSE_TIMESTAMP || raw_event.as_bytes() != CHANNEL_CLOSE_MESSAGE
+        })
+        .fold((0_u64, 0_u64), |(count, bytes), (_, raw_event)| {
+            let len = u64::try_from(raw_event.len()).unwrap_or(u64::MAX);
+            (count + 1, bytes.saturating_add(len))
+        });
+    if count == 0 {
+        return true;
+    }
+    loop {
+        match limiter.admit(source, kind, count, bytes) {
+            Admission::Accept => return true,
+            Admission::Drop => return false,
+            Admission::Delay(wait) => {
+                if shutdown_signal.load(Ordering::SeqCst) {
+                    return true;
+                }
+                sleep(wait).await;
+            }
+        }
+    }
+}
+
+/// Fsyncs the write-ahead log every `interval` until the server shuts down.
+async fn sync_periodically(db: Database, interval: Duration, shutdown_signal: Arc<AtomicBool>) {
+    let mut itv = time::interval(interval);
+    loop {
+        itv.tick().await;
+        if shutdown_signal.load(Ordering::SeqCst) {
+            break;
+        }
+        if let Err(e) = db.sync_wal() {
+            error!("Failed to sync the ingested data: {e}");
+        }
+    }
+}
+
 /// Sends a cumulative acknowledgement message up to the given timestamp over the given send
 /// stream.
 ///
@@ -1073,8 +1568,8 @@ async fn check_sources_conn(
 
             Some((source_key, timestamp_val, conn_state, rep)) = rx.recv() => {
                 match conn_state {
-                    ConnState::Connected => {
-                        if source_store.insert(&source_key, timestamp_val).is_err() {
+                    ConnState::Connected { agent, protocol_version } => {
+                        if source_store.connect(&source_key, &agent, &protocol_version, timestamp_val).is_err() {
                             error!("Failed to append source store");
                         }
                         runtime_ingest_sources.write().await.insert(source_key.clone(), timestamp_val);
@@ -1084,7 +1579,7 @@ async fn check_sources_conn(
                         }
                     }
                     ConnState::Disconnected => {
-                        if source_store.insert(&source_key, timestamp_val).is_err() {
+                        if source_store.disconnect(&source_key, timestamp_val).is_err() {
                             error!("Failed to append source store");
                         }
                         if !rep {
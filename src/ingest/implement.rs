@@ -17,6 +17,8 @@ use giganto_client::ingest::{
     Packet,
 };
 
+use super::DeadLetter;
+
 pub trait EventFilter {
     fn data_type(&self) -> String;
     fn orig_addr(&self) -> Option<IpAddr>;
@@ -1029,3 +1031,30 @@ impl EventFilter for SecuLog {
         Some(self.source.clone())
     }
 }
+
+impl EventFilter for DeadLetter {
+    fn data_type(&self) -> String {
+        "dead letter".to_string()
+    }
+    fn orig_addr(&self) -> Option<IpAddr> {
+        None
+    }
+    fn resp_addr(&self) -> Option<IpAddr> {
+        None
+    }
+    fn orig_port(&self) -> Option<u16> {
+        None
+    }
+    fn resp_port(&self) -> Option<u16> {
+        None
+    }
+    fn log_level(&self) -> Option<String> {
+        None
+    }
+    fn log_contents(&self) -> Option<String> {
+        None
+    }
+    fn source(&self) -> Option<String> {
+        Some(self.source.clone())
+    }
+}
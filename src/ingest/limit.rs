@@ -0,0 +1,390 @@
+//! Limits on the rate and the daily volume of the ingested events.
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tracing::warn;
+
+use crate::{
+    settings::{IngestLimit, LimitAction},
+    storage::Database,
+};
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+const SECS_PER_DAY: i64 = 86_400;
+
+/// What the ingest server does with a frame of events.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    Accept,
+    /// Checks the limits again after the duration.
+    Delay(Duration),
+    Drop,
+}
+
+/// The usage of a limit by a source, or by a kind of a source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LimitUsage {
+    pub limit: IngestLimit,
+    pub source: String,
+    /// `None` if the limit counts the events of all the kinds together.
+    pub kind: Option<String>,
+    /// The events in the last complete second.
+    pub events_per_sec: u64,
+    /// The bytes in the last complete second.
+    pub bytes_per_sec: u64,
+    /// The bytes ingested today in UTC.
+    pub daily_bytes: u64,
+    pub dropped_events: u64,
+    pub dropped_bytes: u64,
+    /// How many times the events were held back, about once a second while
+    /// they wait.
+    pub delays: u64,
+}
+
+// The key of the usage: the index of the limit, the source, and the kind if
+// the limit has one.
+type UsageKey = (usize, String, Option<String>);
+
+#[derive(Debug, Default)]
+struct Usage {
+    // The current window, in seconds since the epoch.
+    second: i64,
+    events: u64,
+    bytes: u64,
+    last_events: u64,
+    last_bytes: u64,
+    // The current day, in days since the epoch.
+    day: i64,
+    daily_bytes: u64,
+    dropped_events: u64,
+    dropped_bytes: u64,
+    delays: u64,
+}
+
+impl Usage {
+    /// Moves the windows forward to `second`.
+    fn roll(&mut self, second: i64) {
+        if second != self.second {
+            (self.last_events, self.last_bytes) = if second == self.second + 1 {
+                (self.events, self.bytes)
+            } else {
+                (0, 0)
+            };
+            self.second = second;
+            self.events = 0;
+            self.bytes = 0;
+        }
+        let day = second.div_euclid(SECS_PER_DAY);
+        if day != self.day {
+            self.day = day;
+            self.daily_bytes = 0;
+        }
+    }
+
+    /// Returns whether adding the events would exceed the limit.
+    ///
+    /// The events are let into an empty window however many they are, so that
+    /// a frame larger than the limit is not held back forever.
+    fn is_over(&self, limit: &IngestLimit, events: u64, bytes: u64) -> bool {
+        let over_rate = self.events > 0
+            && (limit
+                .events_per_sec
+                .is_some_and(|max| self.events.saturating_add(events) > max)
+                || limit
+                    .bytes_per_sec
+                    .is_some_and(|max| self.bytes.saturating_add(bytes) > max));
+        let over_quota = self.daily_bytes > 0
+            && limit
+                .daily_bytes
+                .is_some_and(|max| self.daily_bytes.saturating_add(bytes) > max);
+        over_rate || over_quota
+    }
+}
+
+/// The limits on the events ingested from the sources, and their usage.
+pub struct IngestLimiter {
+    limits: Vec<IngestLimit>,
+    db: Database,
+    usage: Mutex<HashMap<UsageKey, Usage>>,
+}
+
+impl IngestLimiter {
+    #[must_use]
+    pub fn new(limits: Vec<IngestLimit>, db: Database) -> Self {
+        Self {
+            limits,
+            db,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Decides what to do with a frame of `events` events of the kind from
+    /// the source, which are `bytes` bytes in total, and counts them unless
+    /// they are delayed.
+    ///
+    /// The frame is dropped if it is over a limit whose action is `drop`, and
+    /// delayed if it is over a limit whose action is `delay`.
+    pub fn admit(&self, source: &str, kind: &str, events: u64, bytes: u64) -> Admission {
+        self.admit_at(Utc::now(), source, kind, events, bytes)
+    }
+
+    fn admit_at(
+        &self,
+        now: DateTime<Utc>,
+        source: &str,
+        kind: &str,
+        events: u64,
+        bytes: u64,
+    ) -> Admission {
+        if self.limits.is_empty() {
+            return Admission::Accept;
+        }
+        let mut usage = self.usage.lock().expect("not poisoned");
+        let mut keys = Vec::new();
+        let (mut dropped, mut delayed) = (false, false);
+        for (index, limit) in self.limits.iter().enumerate() {
+            if limit.source.as_deref().is_some_and(|s| s != source)
+                || limit.kind.as_deref().is_some_and(|k| k != kind)
+            {
+                continue;
+            }
+            let key = (
+                index,
+                source.to_string(),
+                limit.kind.as_ref().map(|_| kind.to_string()),
+            );
+            let entry = usage
+                .entry(key.clone())
+                .or_insert_with(|| self.new_usage(limit, source, kind, now));
+            entry.roll(now.timestamp());
+            if entry.is_over(limit, events, bytes) {
+                match limit.action {
+                    LimitAction::Drop => dropped = true,
+                    LimitAction::Delay => delayed = true,
+                }
+            }
+            keys.push(key);
+        }
+
+        let admission = if dropped {
+            Admission::Drop
+        } else if delayed {
+            // Both the rate windows and the quota are checked again when the
+            // next second starts.
+            let subsec = Duration::from_nanos(now.timestamp_subsec_nanos().into());
+            Admission::Delay(Duration::from_secs(1).saturating_sub(subsec))
+        } else {
+            Admission::Accept
+        };
+        for key in &keys {
+            let Some(entry) = usage.get_mut(key) else {
+                continue;
+            };
+            match admission {
+                Admission::Accept => {
+                    entry.events = entry.events.saturating_add(events);
+                    entry.bytes = entry.bytes.saturating_add(bytes);
+                    entry.daily_bytes = entry.daily_bytes.saturating_add(bytes);
+                }
+                Admission::Drop => {
+                    entry.dropped_events = entry.dropped_events.saturating_add(events);
+                    entry.dropped_bytes = entry.dropped_bytes.saturating_add(bytes);
+                }
+                Admission::Delay(_) => entry.delays += 1,
+            }
+        }
+        admission
+    }
+
+    /// Starts counting the usage of the limit, with the bytes already
+    /// ingested today taken from the ingest counters.
+    fn new_usage(
+        &self,
+        limit: &IngestLimit,
+        source: &str,
+        kind: &str,
+        now: DateTime<Utc>,
+    ) -> Usage {
+        let second = now.timestamp();
+        let day = second.div_euclid(SECS_PER_DAY);
+        let start_of_day = day.saturating_mul(SECS_PER_DAY * NANOS_PER_SEC);
+        let counts = self
+            .db
+            .ingest_counter_store()
+            .and_then(|store| store.counts(source, start_of_day, i64::MAX));
+        let daily_bytes = match counts {
+            Ok(counts) => counts
+                .iter()
+                .filter(|count| limit.kind.is_none() || count.kind == kind)
+                .map(|count| count.bytes)
+                .sum(),
+            Err(e) => {
+                warn!("Failed to read the ingest counters of {source}: {e}");
+                0
+            }
+        };
+        Usage {
+            second,
+            day,
+            daily_bytes,
+            ..Usage::default()
+        }
+    }
+
+    /// Returns the usage of the limits by the sources that have sent events
+    /// since giganto started, in the order of the limits.
+    #[must_use]
+    pub fn usage(&self) -> Vec<LimitUsage> {
+        self.usage_at(Utc::now())
+    }
+
+    fn usage_at(&self, now: DateTime<Utc>) -> Vec<LimitUsage> {
+        let mut usage = self.usage.lock().expect("not poisoned");
+        let mut entries: Vec<_> = usage.iter_mut().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
+            .into_iter()
+            .map(|((index, source, kind), usage)| {
+                usage.roll(now.timestamp());
+                LimitUsage {
+                    limit: self.limits[*index].clone(),
+                    source: source.clone(),
+                    kind: kind.clone(),
+                    events_per_sec: usage.last_events,
+                    bytes_per_sec: usage.last_bytes,
+                    daily_bytes: usage.daily_bytes,
+                    dropped_events: usage.dropped_events,
+                    dropped_bytes: usage.dropped_bytes,
+                    delays: usage.delays,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, TimeDelta, Utc};
+
+    use super::{Admission, IngestLimiter};
+    use crate::{
+        settings::{IngestLimit, LimitAction},
+        storage::{Database, DbOptions},
+    };
+
+    fn limit(source: Option<&str>, kind: Option<&str>, action: LimitAction) -> IngestLimit {
+        IngestLimit {
+            source: source.map(ToString::to_string),
+            kind: kind.map(ToString::to_string),
+            events_per_sec: None,
+            bytes_per_sec: None,
+            daily_bytes: None,
+            action,
+        }
+    }
+
+    #[test]
+    fn rate_limits() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let limiter = IngestLimiter::new(
+            vec![
+                IngestLimit {
+                    events_per_sec: Some(10),
+                    ..limit(Some("src1"), None, LimitAction::Delay)
+                },
+                IngestLimit {
+                    bytes_per_sec: Some(100),
+                    ..limit(None, Some("dns"), LimitAction::Drop)
+                },
+            ],
+            db,
+        );
+        let now: DateTime<Utc> = "2024-01-01T00:00:00.250Z".parse().unwrap();
+
+        assert_eq!(
+            limiter.admit_at(now, "src1", "conn", 6, 60),
+            Admission::Accept
+        );
+        assert_eq!(
+            limiter.admit_at(now, "src1", "conn", 6, 60),
+            Admission::Delay(Duration::from_millis(750))
+        );
+        // A frame is let into an empty window.
+        assert_eq!(
+            limiter.admit_at(now, "src2", "conn", 20, 200),
+            Admission::Accept
+        );
+        assert_eq!(
+            limiter.admit_at(now, "src2", "dns", 1, 80),
+            Admission::Accept
+        );
+        assert_eq!(limiter.admit_at(now, "src2", "dns", 1, 80), Admission::Drop);
+        // The kinds of `src1` count together.
+        assert_eq!(
+            limiter.admit_at(now, "src1", "dns", 6, 10),
+            Admission::Delay(Duration::from_millis(750))
+        );
+
+        let now = now + TimeDelta::seconds(1);
+        assert_eq!(
+            limiter.admit_at(now, "src1", "conn", 6, 60),
+            Admission::Accept
+        );
+
+        let usage = limiter.usage_at(now + TimeDelta::seconds(1));
+        assert_eq!(usage.len(), 3);
+        assert_eq!(usage[0].source, "src1");
+        assert_eq!(usage[0].kind, None);
+        assert_eq!(usage[0].events_per_sec, 6);
+        assert_eq!(usage[0].delays, 2);
+        assert_eq!(
+            (usage[1].source.as_str(), usage[1].kind.as_deref()),
+            ("src1", Some("dns"))
+        );
+        assert_eq!(usage[2].source, "src2");
+        assert_eq!(usage[2].daily_bytes, 80);
+        assert_eq!(usage[2].dropped_events, 1);
+        assert_eq!(usage[2].dropped_bytes, 80);
+    }
+
+    #[test]
+    fn daily_quota() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let id = db.sources_store().unwrap().assign_id("src1").unwrap();
+        let now: DateTime<Utc> = "2024-01-01T12:00:00Z".parse().unwrap();
+        let counters = db.ingest_counter_store().unwrap();
+        counters
+            .add(id, "conn", now - TimeDelta::days(1), 1, 1000)
+            .unwrap();
+        counters
+            .add(id, "conn", now - TimeDelta::hours(1), 1, 900)
+            .unwrap();
+        let limiter = IngestLimiter::new(
+            vec![IngestLimit {
+                daily_bytes: Some(1000),
+                ..limit(Some("src1"), Some("conn"), LimitAction::Drop)
+            }],
+            db,
+        );
+
+        assert_eq!(
+            limiter.admit_at(now, "src1", "conn", 1, 100),
+            Admission::Accept
+        );
+        assert_eq!(limiter.admit_at(now, "src1", "conn", 1, 1), Admission::Drop);
+        assert_eq!(
+            limiter.admit_at(now, "src1", "dns", 1, 100),
+            Admission::Accept
+        );
+        let tomorrow = now + TimeDelta::days(1);
+        assert_eq!(
+            limiter.admit_at(tomorrow, "src1", "conn", 1, 100),
+            Admission::Accept
+        );
+    }
+}
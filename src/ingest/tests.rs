@@ -23,7 +23,7 @@ use giganto_client::{
     },
     RawEventKind,
 };
-use quinn::{Connection, Endpoint};
+use quinn::{Connection, ConnectionError, Endpoint};
 use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use serde::Serialize;
 use tempfile::TempDir;
@@ -32,11 +32,13 @@ use tokio::{
     task::JoinHandle,
 };
 
-use super::Server;
+use super::{IngestLimiter, Server, TimestampChecker};
 use crate::{
-    new_ingest_sources, new_pcap_sources, new_runtime_ingest_sources, new_stream_direct_channels,
-    storage::{Database, DbOptions},
-    to_cert_chain, to_private_key, to_root_cert, Certs,
+    new_ingest_sources, new_pcap_sources, new_runtime_ingest_sources, new_source_jobs,
+    new_stream_direct_channels,
+    settings::{SyncPolicy, TimestampWindow},
+    storage::{Database, DbOptions, SourceJob, SourceJobKind},
+    to_cert_chain, to_private_key, to_root_cert, Certs, SourceJobs,
 };
 
 fn get_token() -> &'static Mutex<u32> {
@@ -87,6 +89,7 @@ fn server() -> Server {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     Server::new(
@@ -1190,12 +1193,81 @@ async fn one_short_reproduce_channel_close() {
     assert_eq!(CHANNEL_CLOSE_TIMESTAMP, recv_timestamp);
 }
 
+#[tokio::test]
+async fn undecodable_record() {
+    const RAW_EVENT_KIND_LOG: RawEventKind = RawEventKind::Log;
+    const CHANNEL_CLOSE_TIMESTAMP: i64 = -1;
+    const CHANNEL_CLOSE_MESSAGE: &[u8; 12] = b"channel done";
+
+    let _lock = get_token().lock().await;
+    let db_dir = tempfile::tempdir().unwrap();
+    run_server(db_dir);
+
+    let client = TestClient::new().await;
+    let (mut send_log, mut recv_log) = client.conn.open_bi().await.expect("failed to open stream");
+
+    send_record_header(&mut send_log, RAW_EVENT_KIND_LOG)
+        .await
+        .unwrap();
+    send_events(
+        &mut send_log,
+        Utc::now().timestamp_nanos_opt().unwrap(),
+        0xff_u8,
+    )
+    .await
+    .unwrap();
+    send_events(
+        &mut send_log,
+        CHANNEL_CLOSE_TIMESTAMP,
+        CHANNEL_CLOSE_MESSAGE,
+    )
+    .await
+    .unwrap();
+
+    // The stream is still alive after the undecodable record.
+    let mut ts_buf = [0; std::mem::size_of::<u64>()];
+    recv_bytes(&mut recv_log, &mut ts_buf).await.unwrap();
+    let recv_timestamp = i64::from_be_bytes(ts_buf);
+
+    send_log.finish().expect("failed to shutdown stream");
+    client.conn.close(0u32.into(), b"log_done");
+    client.endpoint.wait_idle().await;
+    assert_eq!(CHANNEL_CLOSE_TIMESTAMP, recv_timestamp);
+}
+
+#[tokio::test]
+async fn source_with_running_job() {
+    let _lock = get_token().lock().await;
+    let db_dir = tempfile::tempdir().unwrap();
+    let source_jobs = new_source_jobs(Vec::new());
+    source_jobs
+        .write()
+        .await
+        .push(SourceJob::new(0, HOST, SourceJobKind::Delete));
+    run_server_with_jobs(db_dir, source_jobs);
+
+    let client = TestClient::new().await;
+    match client.conn.closed().await {
+        ConnectionError::ApplicationClosed(close) => {
+            assert_eq!(&close.reason[..], b"node1 is being changed by job 0");
+        }
+        e => panic!("unexpected close: {e}"),
+    }
+    client.endpoint.wait_idle().await;
+}
+
 fn run_server(db_dir: TempDir) -> JoinHandle<()> {
+    run_server_with_jobs(db_dir, new_source_jobs(Vec::new()))
+}
+
+fn run_server_with_jobs(db_dir: TempDir, source_jobs: SourceJobs) -> JoinHandle<()> {
     let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
     let pcap_sources = new_pcap_sources();
     let ingest_sources = new_ingest_sources(&db);
     let runtime_ingest_sources = new_runtime_ingest_sources();
     let stream_direct_channels = new_stream_direct_channels();
+    let limiter = Arc::new(IngestLimiter::new(Vec::new(), db.clone()));
+    let timestamp_checker = Arc::new(TimestampChecker::new(TimestampWindow::default()));
     tokio::spawn(server().run(
         db,
         pcap_sources,
@@ -1205,6 +1277,11 @@ fn run_server(db_dir: TempDir) -> JoinHandle<()> {
         Arc::new(Notify::new()),
         Some(Arc::new(Notify::new())),
         Arc::new(RwLock::new(1024)),
+        SyncPolicy::Ack,
+        std::time::Duration::from_secs(1),
+        limiter,
+        timestamp_checker,
+        source_jobs,
     ))
 }
 
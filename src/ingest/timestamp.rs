@@ -0,0 +1,259 @@
+//! Checks of the event timestamps against the server time, and estimates of
+//! the clock skews of the sources.
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+
+use crate::settings::{TimestampWindow, WindowAction};
+
+const SECS_PER_MINUTE: i64 = 60;
+
+/// What the ingest server does with an event, given its timestamp.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TimestampCheck {
+    Accept,
+    /// Stores the event with the timestamp instead of its own.
+    Clamp(i64),
+    Reject,
+    Quarantine,
+}
+
+/// The estimated clock skew of a source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClockSkew {
+    pub source: String,
+    /// How far the clock of the source is ahead of the server, in
+    /// nanoseconds, or behind it if negative. It is never more than the actual
+    /// skew, and less by the smallest delay of the events in the last two
+    /// minutes. `None` if the source has sent no events in the last two
+    /// minutes.
+    pub skew: Option<i64>,
+    /// When the source last sent events.
+    pub updated_at: DateTime<Utc>,
+    /// The events whose timestamps were outside the window.
+    pub out_of_window: u64,
+}
+
+#[derive(Debug, Default)]
+struct Skew {
+    // The current window, in minutes since the epoch.
+    minute: i64,
+    current: Option<i64>,
+    previous: Option<i64>,
+    updated_at: DateTime<Utc>,
+    out_of_window: u64,
+}
+
+impl Skew {
+    /// Moves the windows forward to `minute`.
+    fn roll(&mut self, minute: i64) {
+        if minute != self.minute {
+            self.previous = if minute == self.minute + 1 {
+                self.current
+            } else {
+                None
+            };
+            self.minute = minute;
+            self.current = None;
+        }
+    }
+
+    /// Returns the largest difference in the windows, which is at most the
+    /// skew.
+    fn estimate(&self) -> Option<i64> {
+        self.current.max(self.previous)
+    }
+}
+
+/// The window of the accepted timestamps, and the clock skews of the sources.
+pub struct TimestampChecker {
+    window: TimestampWindow,
+    skews: Mutex<HashMap<String, Skew>>,
+}
+
+impl TimestampChecker {
+    #[must_use]
+    pub fn new(window: TimestampWindow) -> Self {
+        Self {
+            window,
+            skews: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Decides what to do with an event with the timestamp, received at `now`,
+    /// both in nanoseconds since the epoch.
+    #[must_use]
+    pub fn check(&self, now: i64, timestamp: i64) -> TimestampCheck {
+        let bound = |duration: Duration| i64::try_from(duration.as_nanos()).unwrap_or(i64::MAX);
+        let earliest = self
+            .window
+            .max_past
+            .map(|max_past| now.saturating_sub(bound(max_past)));
+        let latest = self
+            .window
+            .max_future
+            .map(|max_future| now.saturating_add(bound(max_future)));
+        let clamped = if earliest.is_some_and(|earliest| timestamp < earliest) {
+            earliest
+        } else if latest.is_some_and(|latest| timestamp > latest) {
+            latest
+        } else {
+            None
+        };
+        match (clamped, self.window.action) {
+            (None, _) => TimestampCheck::Accept,
+            (Some(clamped), WindowAction::Clamp) => TimestampCheck::Clamp(clamped),
+            (Some(_), WindowAction::Reject) => TimestampCheck::Reject,
+            (Some(_), WindowAction::Quarantine) => TimestampCheck::Quarantine,
+        }
+    }
+
+    /// Updates the clock skew of the source with a frame of events received
+    /// at `now`, whose latest timestamp is `latest`, and `out_of_window` of
+    /// which were outside the window.
+    ///
+    /// The difference between the latest timestamp and the time it is
+    /// received is the skew less the delay of the event, which cannot be
+    /// negative, so the skew is an upper bound of every difference. The
+    /// estimate is the largest difference in the current and the previous
+    /// minutes, and falls short of the skew by the smallest delay in them: a
+    /// source whose events all arrive late looks behind by at least that
+    /// delay.
+    pub fn observe(&self, source: &str, now: DateTime<Utc>, latest: i64, out_of_window: u64) {
+        let now_nanos = now.timestamp_nanos_opt().unwrap_or(i64::MAX);
+        let mut skews = self.skews.lock().expect("not poisoned");
+        let skew = skews.entry(source.to_string()).or_default();
+        skew.roll(now.timestamp().div_euclid(SECS_PER_MINUTE));
+        skew.current = skew.current.max(Some(latest.saturating_sub(now_nanos)));
+        skew.updated_at = now;
+        skew.out_of_window = skew.out_of_window.saturating_add(out_of_window);
+    }
+
+    /// Returns the clock skews of the sources that have sent events since
+    /// giganto started, ordered by source.
+    #[must_use]
+    pub fn skews(&self) -> Vec<ClockSkew> {
+        self.skews_at(Utc::now())
+    }
+
+    fn skews_at(&self, now: DateTime<Utc>) -> Vec<ClockSkew> {
+        let mut skews = self.skews.lock().expect("not poisoned");
+        let mut skews: Vec<_> = skews
+            .iter_mut()
+            .map(|(source, skew)| {
+                skew.roll(now.timestamp().div_euclid(SECS_PER_MINUTE));
+                ClockSkew {
+                    source: source.clone(),
+                    skew: skew.estimate(),
+                    updated_at: skew.updated_at,
+                    out_of_window: skew.out_of_window,
+                }
+            })
+            .collect();
+        skews.sort_by(|a, b| a.source.cmp(&b.source));
+        skews
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, TimeDelta, Utc};
+
+    use super::{TimestampCheck, TimestampChecker};
+    use crate::settings::{TimestampWindow, WindowAction};
+
+    const SEC: i64 = 1_000_000_000;
+
+    #[test]
+    fn check() {
+        let window = TimestampWindow {
+            max_past: Some(Duration::from_secs(60)),
+            max_future: Some(Duration::from_secs(10)),
+            action: WindowAction::Clamp,
+        };
+        let checker = TimestampChecker::new(window);
+        let now = 1_000 * SEC;
+        assert_eq!(checker.check(now, now - 60 * SEC), TimestampCheck::Accept);
+        assert_eq!(checker.check(now, now + 10 * SEC), TimestampCheck::Accept);
+        assert_eq!(
+            checker.check(now, now - 61 * SEC),
+            TimestampCheck::Clamp(now - 60 * SEC)
+        );
+        assert_eq!(
+            checker.check(now, i64::MAX),
+            TimestampCheck::Clamp(now + 10 * SEC)
+        );
+
+        let checker = TimestampChecker::new(TimestampWindow {
+            action: WindowAction::Reject,
+            ..window
+        });
+        assert_eq!(checker.check(now, 0), TimestampCheck::Reject);
+        let checker = TimestampChecker::new(TimestampWindow {
+            max_past: None,
+            action: WindowAction::Quarantine,
+            ..window
+        });
+        assert_eq!(checker.check(now, 0), TimestampCheck::Accept);
+        assert_eq!(
+            checker.check(now, now + 11 * SEC),
+            TimestampCheck::Quarantine
+        );
+        let checker = TimestampChecker::new(TimestampWindow::default());
+        assert_eq!(checker.check(now, i64::MAX), TimestampCheck::Accept);
+    }
+
+    #[test]
+    fn clock_skews() {
+        let checker = TimestampChecker::new(TimestampWindow::default());
+        let now: DateTime<Utc> = "2024-01-01T00:00:30Z".parse().unwrap();
+        let nanos = now.timestamp_nanos_opt().unwrap();
+
+        // The events of `src1` are 5 seconds ahead, and delayed sometimes.
+        checker.observe("src1", now, nanos + 5 * SEC, 0);
+        checker.observe("src1", now, nanos - 100 * SEC, 2);
+        checker.observe("src2", now, nanos - 3 * SEC, 0);
+        let later = now + TimeDelta::seconds(40);
+        checker.observe("src1", later, nanos, 0);
+
+        let skews = checker.skews_at(later);
+        assert_eq!(skews.len(), 2);
+        assert_eq!(skews[0].source, "src1");
+        assert_eq!(skews[0].skew, Some(5 * SEC));
+        assert_eq!(skews[0].updated_at, later);
+        assert_eq!(skews[0].out_of_window, 2);
+        assert_eq!(skews[1].source, "src2");
+        assert_eq!(skews[1].skew, Some(-3 * SEC));
+
+        let skews = checker.skews_at(now + TimeDelta::minutes(3));
+        assert_eq!(skews[0].skew, None);
+        assert_eq!(skews[0].out_of_window, 2);
+    }
+
+    #[test]
+    fn lagging_source() {
+        let checker = TimestampChecker::new(TimestampWindow::default());
+        let now: DateTime<Utc> = "2024-01-01T00:00:10Z".parse().unwrap();
+        let nanos = now.timestamp_nanos_opt().unwrap();
+
+        // The clock of `src` is accurate, but its events arrive 5 to 8
+        // seconds after they happen.
+        checker.observe("src", now, nanos - 8 * SEC, 0);
+        checker.observe("src", now, nanos - 5 * SEC, 0);
+        let skews = checker.skews_at(now);
+        assert_eq!(skews[0].skew, Some(-5 * SEC));
+
+        // A less delayed frame in the previous minute brings the estimate
+        // closer to the skew, which it never exceeds.
+        let later = now + TimeDelta::seconds(60);
+        checker.observe("src", later, nanos + 60 * SEC - 7 * SEC, 0);
+        let skews = checker.skews_at(later);
+        assert_eq!(skews[0].skew, Some(-5 * SEC));
+        checker.observe("src", later, nanos + 60 * SEC - SEC, 0);
+        let skews = checker.skews_at(later);
+        assert_eq!(skews[0].skew, Some(-SEC));
+    }
+}
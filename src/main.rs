@@ -1,5 +1,8 @@
+mod admin;
+mod auth;
 mod graphql;
 mod ingest;
+mod metrics;
 mod peer;
 mod publish;
 mod server;
@@ -10,10 +13,11 @@ mod web;
 use std::{
     collections::{HashMap, HashSet},
     env, fs,
+    net::SocketAddr,
     path::Path,
     process::exit,
     sync::{Arc, Mutex},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{anyhow, bail, Context, Result};
@@ -22,9 +26,9 @@ use clap::Parser;
 use peer::{PeerIdentity, PeerIdents, PeerInfo, Peers};
 use quinn::Connection;
 use rocksdb::DB;
-use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer};
 use settings::Settings;
-use storage::Database;
+use storage::{Database, IntegrityReport, RetentionPolicies, SourceJob};
 use tokio::{
     runtime, select,
     sync::{
@@ -42,19 +46,25 @@ use tracing_subscriber::{
 
 use crate::{
     graphql::NodeName,
-    server::{subject_from_cert, Certs, SERVER_REBOOT_DELAY},
+    server::{
+        client_tls_config, config_client, config_server, subject_from_cert, web_server_tls_config,
+        Certs, SERVER_REBOOT_DELAY,
+    },
     settings::Args,
     storage::migrate_data_dir,
 };
 
 const ONE_DAY: u64 = 60 * 60 * 24;
 const WAIT_SHUTDOWN: u64 = 15;
+const TLS_FILES_CHECK_INTERVAL: Duration = Duration::from_secs(10);
 
 pub type PcapSources = Arc<RwLock<HashMap<String, Vec<Connection>>>>;
 pub type IngestSources = Arc<RwLock<HashSet<String>>>;
 pub type RunTimeIngestSources = Arc<RwLock<HashMap<String, DateTime<Utc>>>>;
 pub type StreamDirectChannels = Arc<RwLock<HashMap<String, UnboundedSender<Vec<u8>>>>>;
 pub type AckTransmissionCount = Arc<RwLock<u16>>;
+pub type StorageIntegrity = Arc<RwLock<Option<IntegrityReport>>>;
+pub type SourceJobs = Arc<RwLock<Vec<SourceJob>>>;
 
 #[allow(clippy::too_many_lines)]
 #[tokio::main]
@@ -69,19 +79,24 @@ async fn main() -> Result<()> {
 
     let cfg_path = settings.cfg_path.clone();
 
-    let cert_pem = fs::read(&args.cert)
-        .with_context(|| format!("failed to read certificate file: {}", args.cert))?;
-    let cert = to_cert_chain(&cert_pem).context("cannot read certificate chain")?;
-    assert!(!cert.is_empty());
-    let key_pem = fs::read(&args.key)
-        .with_context(|| format!("failed to read private key file: {}", args.key))?;
-    let key = to_private_key(&key_pem).context("cannot read private key")?;
-    let root_cert = to_root_cert(&args.ca_certs)?;
-    let certs = Arc::new(Certs {
-        certs: cert.clone(),
-        key: key.clone_key(),
-        root: root_cert.clone(),
-    });
+    if let Some(command) = args.command {
+        if !is_local_config {
+            bail!("administration commands are not allowed on remote config");
+        }
+        return admin::run(command, &settings.config);
+    }
+
+    // `clap` requires them unless a subcommand is given.
+    let (Some(cert_path), Some(key_path)) = (args.cert, args.key) else {
+        bail!("the certificate and the key are required");
+    };
+    let tls_files = TlsFiles {
+        cert: cert_path,
+        key: key_path,
+        ca_certs: args.ca_certs,
+        crls: args.crls,
+    };
+    let mut tls = tls_files.load()?;
 
     let _guard = init_tracing(&settings.config.log_dir)?;
 
@@ -91,6 +106,8 @@ async fn main() -> Result<()> {
         settings.config.max_mb_of_level_base,
         settings.config.num_of_thread,
         settings.config.max_sub_compactions,
+        settings.config.cf_options.clone(),
+        settings.config.storage_tiers.clone(),
     );
 
     if args.repair {
@@ -98,7 +115,7 @@ async fn main() -> Result<()> {
             bail!("repair is not allowed on remote config");
         }
         let start = Instant::now();
-        let (db_opts, _) = storage::rocksdb_options(&db_options);
+        let (db_opts, _) = storage::rocksdb_options(&db_options)?;
         info!("repair db start.");
         match DB::repair(&db_opts, db_path) {
             Ok(()) => info!("repair ok"),
@@ -109,68 +126,169 @@ async fn main() -> Result<()> {
         exit(0);
     }
 
+    if args.backup {
+        if !is_local_config {
+            bail!("backup is not allowed on remote config");
+        }
+        // The running giganto holds the lock of the database, so it is asked
+        // to create the backup.
+        let addr = settings.config.graphql_srv_addr;
+        if let Some(path) = request_backup(addr, &tls.certs, args.token.as_deref()).await? {
+            info!("backup created: {path}");
+            exit(0);
+        }
+    }
+
+    if let Some(backup) = args.restore {
+        if !is_local_config {
+            bail!("restore is not allowed on remote config");
+        }
+        storage::restore_backup(Path::new(&backup), &settings.config.data_dir, &db_options)?;
+    }
+
     let mut is_reboot = false;
     let mut is_power_off = false;
 
     let database = storage::Database::open(&db_path, &db_options)?;
+    let storage_paths = db_options.storage_paths(&db_path);
 
     if let Err(e) = migrate_data_dir(&settings.config.data_dir, &database) {
         error!("migration failed: {e}");
         return Ok(());
     }
 
+    if args.backup {
+        let backup = database.backup(&settings.config.backup_dir)?;
+        info!("backup created: {}", backup.path.display());
+        database.shutdown()?;
+        exit(0);
+    }
+
     let notify_terminate = Arc::new(Notify::new());
     let r = notify_terminate.clone();
     if let Err(ctrlc::Error::System(e)) = ctrlc::set_handler(move || r.notify_one()) {
         return Err(anyhow!("failed to set signal handler: {}", e));
     }
 
-    let request_client_pool = reqwest::Client::builder()
-        .danger_accept_invalid_certs(true)
-        .tls_sni(false)
-        .build()
-        .expect("Failed to build request client pool");
+    // The integrity report and the source jobs are kept across reloads of the
+    // configuration.
+    let storage_integrity = new_storage_integrity();
+    let jobs = database.source_job_store()?.list()?;
+    // The jobs that were running when giganto stopped are resumed once the
+    // sources are loaded.
+    let mut interrupted_jobs: Vec<_> = jobs
+        .iter()
+        .filter(|job| job.is_running())
+        .cloned()
+        .collect();
+    let source_jobs = new_source_jobs(jobs);
+
+    let (tls_reload_tx, mut tls_reload_rx) = mpsc::channel::<Tls>(1);
+    task::spawn(watch_tls_files(tls_files, tls_reload_tx));
 
     loop {
         let pcap_sources = new_pcap_sources();
         let ingest_sources = new_ingest_sources(&database);
+        for job in interrupted_jobs.drain(..) {
+            task::spawn(graphql::source::resume_source_job(
+                database.clone(),
+                ingest_sources.clone(),
+                source_jobs.clone(),
+                job,
+            ));
+        }
         let runtime_ingest_sources = new_runtime_ingest_sources();
         let stream_direct_channels = new_stream_direct_channels();
         let (peers, peer_idents) = new_peers_data(settings.config.peers.clone());
+        let certs = tls.certs.clone();
+        let request_client_pool = peer::request_client(&certs, peer_idents.clone())?;
         let (reload_tx, mut reload_rx) = mpsc::channel::<String>(1);
         let notify_shutdown = Arc::new(Notify::new());
         let notify_reboot = Arc::new(Notify::new());
         let notify_power_off = Arc::new(Notify::new());
         let mut notify_source_change = None;
         let ack_transmission_cnt = new_ack_transmission_count(settings.config.ack_transmission);
+        let ingest_limiter = Arc::new(ingest::IngestLimiter::new(
+            settings.config.ingest_limits.clone(),
+            database.clone(),
+        ));
+        let timestamp_checker = Arc::new(ingest::TimestampChecker::new(
+            settings.config.timestamp_window,
+        ));
+        let authenticator = settings
+            .config
+            .jwt_key
+            .as_deref()
+            .map(auth::Authenticator::from_key_file)
+            .transpose()?
+            .map(Arc::new);
 
         let schema = graphql::schema(
-            NodeName(subject_from_cert(&cert)?.1),
+            NodeName(subject_from_cert(&certs.certs)?.1),
             database.clone(),
             pcap_sources.clone(),
             ingest_sources.clone(),
+            runtime_ingest_sources.clone(),
+            stream_direct_channels.clone(),
             peers.clone(),
-            request_client_pool.clone(),
+            peer_idents.clone(),
+            request_client_pool,
             settings.config.export_dir.clone(),
             reload_tx,
             notify_reboot.clone(),
             notify_power_off.clone(),
             notify_terminate.clone(),
             ack_transmission_cnt.clone(),
+            storage_integrity.clone(),
+            source_jobs.clone(),
+            ingest_limiter.clone(),
+            timestamp_checker.clone(),
             is_local_config,
             settings.clone(),
         );
 
+        let retain_flag = Arc::new(Mutex::new(false));
+        let peer_server = settings
+            .config
+            .addr_to_peers
+            .map(|addr_to_peers| peer::Peer::new(addr_to_peers, &certs.clone()))
+            .transpose()?;
+        let publish_server = publish::Server::new(settings.config.publish_srv_addr, &certs.clone());
+        let ingest_server = ingest::Server::new(settings.config.ingest_srv_addr, &certs.clone());
+        let readiness = web::Readiness {
+            data_dir: settings.config.data_dir.clone(),
+            ingest_bound: ingest_server.bound(),
+            publish_bound: publish_server.bound(),
+            peer_bound: peer_server.as_ref().map(peer::Peer::bound),
+            retention_running: retain_flag.clone(),
+        };
+
         task::spawn(web::serve(
             schema,
+            database.clone(),
+            pcap_sources.clone(),
+            stream_direct_channels.clone(),
+            peers.clone(),
+            peer_idents.clone(),
+            readiness,
+            authenticator,
             settings.config.graphql_srv_addr,
-            cert_pem.clone(),
-            key_pem.clone(),
+            certs.clone(),
             notify_shutdown.clone(),
         ));
 
-        let retain_flag = Arc::new(Mutex::new(false));
+        let retention_policies = RetentionPolicies::new(
+            settings.config.retention,
+            settings.config.retention_policies.clone(),
+        );
+        let archiver = settings
+            .config
+            .archive_dir
+            .as_deref()
+            .map(|dir| storage::Archiver::new(dir, settings.config.archive_format))
+            .transpose()?;
         let db = database.clone();
+        let db_storage_paths = storage_paths.clone();
         let notify_shutdown_copy = notify_shutdown.clone();
         let running_flag = retain_flag.clone();
         std::thread::spawn(move || {
@@ -181,8 +299,10 @@ async fn main() -> Result<()> {
                 .expect("Cannot create runtime for retain_periodically.")
                 .block_on(storage::retain_periodically(
                     time::Duration::from_secs(ONE_DAY),
-                    settings.config.retention,
+                    retention_policies,
                     db,
+                    db_storage_paths,
+                    archiver,
                     notify_shutdown_copy,
                     running_flag,
                 ))
@@ -191,8 +311,7 @@ async fn main() -> Result<()> {
                 });
         });
 
-        if let Some(addr_to_peers) = settings.config.addr_to_peers {
-            let peer_server = peer::Peer::new(addr_to_peers, &certs.clone())?;
+        if let Some(peer_server) = peer_server {
             let notify_source = Arc::new(Notify::new());
             task::spawn(peer_server.run(
                 ingest_sources.clone(),
@@ -205,7 +324,6 @@ async fn main() -> Result<()> {
             notify_source_change = Some(notify_source);
         }
 
-        let publish_server = publish::Server::new(settings.config.publish_srv_addr, &certs.clone());
         task::spawn(publish_server.run(
             database.clone(),
             pcap_sources.clone(),
@@ -217,7 +335,6 @@ async fn main() -> Result<()> {
             notify_shutdown.clone(),
         ));
 
-        let ingest_server = ingest::Server::new(settings.config.ingest_srv_addr, &certs.clone());
         task::spawn(ingest_server.run(
             database.clone(),
             pcap_sources,
@@ -227,6 +344,11 @@ async fn main() -> Result<()> {
             notify_shutdown.clone(),
             notify_source_change,
             ack_transmission_cnt,
+            settings.config.sync_policy,
+            settings.config.sync_interval,
+            ingest_limiter,
+            timestamp_checker,
+            source_jobs.clone(),
         ));
 
         loop {
@@ -246,6 +368,12 @@ async fn main() -> Result<()> {
                         }
                     }
                 },
+                Some(new_tls) = tls_reload_rx.recv() => {
+                    info!("Certificate files changed: restarting the servers with them");
+                    tls = new_tls;
+                    notify_and_wait_shutdown(notify_shutdown.clone()).await;
+                    break;
+                },
                 () = notify_terminate.notified() => {
                     info!("Termination signal: giganto daemon exit");
                     notify_and_wait_shutdown(notify_shutdown).await;
@@ -296,6 +424,129 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Asks the giganto serving GraphQL at `addr` to create a backup, and returns
+/// the path of the backup, or `None` if no giganto is listening at `addr`.
+async fn request_backup(
+    addr: SocketAddr,
+    certs: &Certs,
+    token: Option<&str>,
+) -> Result<Option<String>> {
+    if tokio::net::TcpStream::connect(addr).await.is_err() {
+        return Ok(None);
+    }
+    // The server is verified against the hostname in its certificate, which is
+    // also the hostname of this giganto.
+    let (_, hostname) = subject_from_cert(&certs.certs)?;
+    let client = reqwest::Client::builder()
+        .use_preconfigured_tls(client_tls_config(certs)?)
+        .resolve(&hostname, addr)
+        .build()
+        .context("failed to build the request client")?;
+    let mut request = client
+        .post(format!("https://{hostname}:{}/graphql", addr.port()))
+        .json(&serde_json::json!({ "query": "mutation { backup { path } }" }));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response: serde_json::Value = request
+        .send()
+        .await
+        .context("cannot request the backup")?
+        .json()
+        .await
+        .context("invalid response to the backup request")?;
+    if let Some(errors) = response.get("errors") {
+        bail!("backup failed: {errors}");
+    }
+    let path = response
+        .pointer("/data/backup/path")
+        .and_then(serde_json::Value::as_str)
+        .context("invalid response to the backup request")?;
+    Ok(Some(path.to_string()))
+}
+
+/// The certificate, the key, the root certificates, and the CRLs of giganto.
+struct Tls {
+    certs: Arc<Certs>,
+}
+
+/// The paths of the files of [`Tls`].
+struct TlsFiles {
+    cert: String,
+    key: String,
+    ca_certs: Vec<String>,
+    crls: Vec<String>,
+}
+
+impl TlsFiles {
+    /// Loads the files, and checks that the QUIC and the web server
+    /// configurations can be built with them.
+    fn load(&self) -> Result<Tls> {
+        let cert_pem = fs::read(&self.cert)
+            .with_context(|| format!("failed to read certificate file: {}", self.cert))?;
+        let cert = to_cert_chain(&cert_pem).context("cannot read certificate chain")?;
+        if cert.is_empty() {
+            bail!("no certificate in {}", self.cert);
+        }
+        let key_pem = fs::read(&self.key)
+            .with_context(|| format!("failed to read private key file: {}", self.key))?;
+        let key = to_private_key(&key_pem).context("cannot read private key")?;
+        let certs = Arc::new(Certs {
+            certs: cert,
+            key,
+            root: to_root_cert(&self.ca_certs)?,
+            crls: to_crls(&self.crls)?,
+        });
+        config_server(&certs)?;
+        config_client(&certs)?;
+        web_server_tls_config(&certs)?;
+        Ok(Tls { certs })
+    }
+
+    /// Returns the modification times of the files, `None` for the files that
+    /// cannot be read.
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        [&self.cert, &self.key]
+            .into_iter()
+            .chain(&self.ca_certs)
+            .chain(&self.crls)
+            .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+/// Sends the certificates to `reload_tx` whenever their files change.
+///
+/// Files being rewritten may not be valid for a while, so the files are loaded
+/// again at every check until they are valid.
+async fn watch_tls_files(files: TlsFiles, reload_tx: mpsc::Sender<Tls>) {
+    let mut loaded = files.modified();
+    let mut failed = None;
+    let mut interval = time::interval(TLS_FILES_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let modified = files.modified();
+        if modified == loaded {
+            continue;
+        }
+        match files.load() {
+            Ok(tls) => {
+                loaded = modified;
+                failed = None;
+                if reload_tx.send(tls).await.is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                if failed.as_ref() != Some(&modified) {
+                    error!("Failed to reload the certificate files: {e:#}");
+                    failed = Some(modified);
+                }
+            }
+        }
+    }
+}
+
 fn to_cert_chain(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>> {
     let certs = rustls_pemfile::certs(&mut &*pem)
         .collect::<Result<_, _>>()
@@ -338,6 +589,22 @@ fn to_root_cert(ca_certs_paths: &[String]) -> Result<rustls::RootCertStore> {
     Ok(root_cert)
 }
 
+fn to_crls(crl_paths: &[String]) -> Result<Vec<CertificateRevocationListDer<'static>>> {
+    let mut crls = Vec::new();
+    for crl_path in crl_paths {
+        let file =
+            fs::read(crl_path).with_context(|| format!("failed to read CRL file: {crl_path}"))?;
+        let file_crls = rustls_pemfile::crls(&mut &*file)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("invalid PEM-encoded CRL: {crl_path}"))?;
+        if file_crls.is_empty() {
+            bail!("no CRL in {crl_path}");
+        }
+        crls.extend(file_crls);
+    }
+    Ok(crls)
+}
+
 fn to_hms(dur: Duration) -> String {
     let total_sec = dur.as_secs();
     let hours = total_sec / 3600;
@@ -370,6 +637,14 @@ fn new_ack_transmission_count(count: u16) -> AckTransmissionCount {
     Arc::new(RwLock::new(count))
 }
 
+fn new_storage_integrity() -> StorageIntegrity {
+    Arc::new(RwLock::new(None))
+}
+
+fn new_source_jobs(jobs: Vec<SourceJob>) -> SourceJobs {
+    Arc::new(RwLock::new(jobs))
+}
+
 fn new_peers_data(peers_list: Option<HashSet<PeerIdentity>>) -> (Peers, PeerIdents) {
     (
         Arc::new(RwLock::new(HashMap::<String, PeerInfo>::new())),
@@ -0,0 +1,272 @@
+//! Metrics of giganto in the Prometheus text format.
+
+use std::{
+    sync::LazyLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+use crate::{
+    peer::Peers,
+    storage::{self, Database},
+    PcapSources, StreamDirectChannels,
+};
+
+/// The metrics of the process, which are kept across reloads of the
+/// configuration.
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+struct Metrics {
+    registry: Registry,
+    ingest_events: IntCounterVec,
+    ingest_bytes: IntCounterVec,
+    ingest_connections: IntGauge,
+    publish_stream_channels: IntGauge,
+    subscription_dropped_events: IntCounterVec,
+    peer_connections: IntGauge,
+    retention_duration: Gauge,
+    retention_last_success: IntGauge,
+    cf_size: IntGaugeVec,
+    cf_pending_compaction: IntGaugeVec,
+    graphql_duration: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("giganto".to_string()), None).expect("valid prefix");
+        let metrics = Self {
+            registry,
+            ingest_events: IntCounterVec::new(
+                Opts::new("ingest_events_total", "Events stored by the ingest server"),
+                &["kind"],
+            )
+            .expect("valid metric"),
+            ingest_bytes: IntCounterVec::new(
+                Opts::new(
+                    "ingest_bytes_total",
+                    "Bytes of the events stored by the ingest server",
+                ),
+                &["kind"],
+            )
+            .expect("valid metric"),
+            ingest_connections: IntGauge::new(
+                "ingest_connections",
+                "Connections of the sources to the ingest server",
+            )
+            .expect("valid metric"),
+            publish_stream_channels: IntGauge::new(
+                "publish_stream_channels",
+                "Channels of the streams requested from the publish server",
+            )
+            .expect("valid metric"),
+            subscription_dropped_events: IntCounterVec::new(
+                Opts::new(
+                    "subscription_dropped_events_total",
+                    "Events dropped for the GraphQL subscribers that fell behind",
+                ),
+                &["kind"],
+            )
+            .expect("valid metric"),
+            peer_connections: IntGauge::new("peer_connections", "Connected peers")
+                .expect("valid metric"),
+            retention_duration: Gauge::new(
+                "retention_duration_seconds",
+                "Duration of the last completed retention run",
+            )
+            .expect("valid metric"),
+            retention_last_success: IntGauge::new(
+                "retention_last_success_timestamp_seconds",
+                "Time when the last retention run completed",
+            )
+            .expect("valid metric"),
+            cf_size: IntGaugeVec::new(
+                Opts::new(
+                    "cf_size_bytes",
+                    "Total size of the SST files of a column family",
+                ),
+                &["cf"],
+            )
+            .expect("valid metric"),
+            cf_pending_compaction: IntGaugeVec::new(
+                Opts::new(
+                    "cf_pending_compaction_bytes",
+                    "Estimated bytes to be rewritten by the compaction of a column family",
+                ),
+                &["cf"],
+            )
+            .expect("valid metric"),
+            graphql_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "graphql_request_duration_seconds",
+                    "Latency of the GraphQL requests",
+                ),
+                &["fields"],
+            )
+            .expect("valid metric"),
+        };
+        for collector in [
+            Box::new(metrics.ingest_events.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(metrics.ingest_bytes.clone()),
+            Box::new(metrics.ingest_connections.clone()),
+            Box::new(metrics.publish_stream_channels.clone()),
+            Box::new(metrics.subscription_dropped_events.clone()),
+            Box::new(metrics.peer_connections.clone()),
+            Box::new(metrics.retention_duration.clone()),
+            Box::new(metrics.retention_last_success.clone()),
+            Box::new(metrics.cf_size.clone()),
+            Box::new(metrics.cf_pending_compaction.clone()),
+            Box::new(metrics.graphql_duration.clone()),
+        ] {
+            metrics.registry.register(collector).expect("unique metric");
+        }
+        metrics
+    }
+}
+
+/// Adds the events of the kind stored by the ingest server.
+pub fn record_ingest(kind: &str, events: u64, bytes: u64) {
+    METRICS
+        .ingest_events
+        .with_label_values(&[kind])
+        .inc_by(events);
+    METRICS
+        .ingest_bytes
+        .with_label_values(&[kind])
+        .inc_by(bytes);
+}
+
+/// Adds an event of the kind dropped for a GraphQL subscriber whose buffer is
+/// full.
+pub fn record_subscription_drop(kind: &str) {
+    METRICS
+        .subscription_dropped_events
+        .with_label_values(&[kind])
+        .inc();
+}
+
+/// Records a completed retention run, which took `duration`.
+pub fn record_retention(duration: Duration) {
+    METRICS.retention_duration.set(duration.as_secs_f64());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    METRICS
+        .retention_last_success
+        .set(now.try_into().unwrap_or(i64::MAX));
+}
+
+/// Records the latency of a GraphQL request, labeled by its root fields, or
+/// `other` if it has none.
+///
+/// The root fields should be those in the schema, so that the labels are
+/// bounded.
+pub fn observe_graphql(fields: &[String], duration: Duration) {
+    let mut fields = fields.to_vec();
+    fields.sort_unstable();
+    fields.dedup();
+    let label = if fields.is_empty() {
+        "other".to_string()
+    } else {
+        fields.join(",")
+    };
+    METRICS
+        .graphql_duration
+        .with_label_values(&[&label])
+        .observe(duration.as_secs_f64());
+}
+
+/// Returns all the metrics in the Prometheus text format, after updating the
+/// gauges of the current state.
+///
+/// # Errors
+///
+/// Returns an error if the properties of a column family cannot be read.
+pub async fn render(
+    db: &Database,
+    pcap_sources: &PcapSources,
+    stream_direct_channels: &StreamDirectChannels,
+    peers: &Peers,
+) -> Result<String> {
+    let connections = pcap_sources
+        .read()
+        .await
+        .values()
+        .map(Vec::len)
+        .sum::<usize>();
+    METRICS
+        .ingest_connections
+        .set(connections.try_into().unwrap_or(i64::MAX));
+    let channels = stream_direct_channels.read().await.len();
+    METRICS
+        .publish_stream_channels
+        .set(channels.try_into().unwrap_or(i64::MAX));
+    let peers = peers.read().await.len();
+    METRICS
+        .peer_connections
+        .set(peers.try_into().unwrap_or(i64::MAX));
+    for cf_name in storage::column_family_names() {
+        let (size, pending_compaction) = db.cf_size(cf_name)?;
+        METRICS
+            .cf_size
+            .with_label_values(&[cf_name])
+            .set(size.try_into().unwrap_or(i64::MAX));
+        METRICS
+            .cf_pending_compaction
+            .with_label_values(&[cf_name])
+            .set(pending_compaction.try_into().unwrap_or(i64::MAX));
+    }
+
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+
+    use tokio::sync::RwLock;
+
+    use crate::storage::{Database, DbOptions};
+
+    #[tokio::test]
+    async fn render() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        super::record_ingest("metrics test", 2, 100);
+        super::record_ingest("metrics test", 1, 50);
+        super::observe_graphql(
+            &[
+                "sources".to_string(),
+                "export".to_string(),
+                "sources".to_string(),
+            ],
+            Duration::from_millis(3),
+        );
+        super::observe_graphql(&[], Duration::from_millis(3));
+
+        let pcap_sources = Arc::new(RwLock::new(HashMap::new()));
+        let stream_direct_channels = Arc::new(RwLock::new(HashMap::new()));
+        let peers = Arc::new(RwLock::new(HashMap::new()));
+        let text = super::render(&db, &pcap_sources, &stream_direct_channels, &peers)
+            .await
+            .unwrap();
+        assert!(text.contains("giganto_ingest_events_total{kind=\"metrics test\"} 3\n"));
+        assert!(text.contains("giganto_ingest_bytes_total{kind=\"metrics test\"} 150\n"));
+        assert!(text.contains("giganto_ingest_connections 0\n"));
+        assert!(text.contains("giganto_cf_size_bytes{cf=\"conn\"}"));
+        assert!(text.contains(
+            "giganto_graphql_request_duration_seconds_count{fields=\"export,sources\"} 1\n"
+        ));
+        assert!(
+            text.contains("giganto_graphql_request_duration_seconds_count{fields=\"other\"} 1\n")
+        );
+    }
+}
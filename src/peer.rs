@@ -2,7 +2,10 @@ use std::{
     collections::{HashMap, HashSet},
     mem,
     net::{SocketAddr, ToSocketAddrs},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
@@ -15,6 +18,7 @@ use num_enum::{IntoPrimitive, TryFromPrimitive};
 use quinn::{
     ClientConfig, Connection, ConnectionError, Endpoint, RecvStream, SendStream, ServerConfig,
 };
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tokio::{
     select,
@@ -33,14 +37,14 @@ use crate::{
         TomlPeers, CONFIG_GRAPHQL_SRV_ADDR, CONFIG_PUBLISH_SRV_ADDR,
     },
     server::{
-        config_client, config_server, extract_cert_from_conn, subject_from_cert,
+        client_tls_config, config_client, config_server, extract_cert_from_conn, subject_from_cert,
         subject_from_cert_verbose, Certs, SERVER_CONNNECTION_DELAY, SERVER_ENDPOINT_DELAY,
     },
     settings::Settings,
     IngestSources,
 };
 
-const PEER_VERSION_REQ: &str = ">=0.21.0,<0.23.0";
+const PEER_VERSION_REQ: &str = ">=0.23.0-alpha.1,<0.24.0";
 const PEER_RETRY_INTERVAL: u64 = 5;
 
 pub type Peers = Arc<RwLock<HashMap<String, PeerInfo>>>;
@@ -108,6 +112,7 @@ pub struct Peer {
     server_config: ServerConfig,
     local_address: SocketAddr,
     local_host_name: String,
+    bound: Arc<AtomicBool>,
 }
 
 impl Peer {
@@ -125,9 +130,16 @@ impl Peer {
             server_config,
             local_address,
             local_host_name,
+            bound: Arc::new(AtomicBool::new(false)),
         })
     }
 
+    /// Returns the flag that tells whether the server is accepting
+    /// connections.
+    pub fn bound(&self) -> Arc<AtomicBool> {
+        self.bound.clone()
+    }
+
     pub async fn run(
         self,
         ingest_sources: IngestSources,
@@ -139,6 +151,7 @@ impl Peer {
     ) -> Result<()> {
         let server_endpoint =
             Endpoint::server(self.server_config, self.local_address).expect("endpoint");
+        self.bound.store(true, Ordering::SeqCst);
         info!(
             "listening on {}",
             server_endpoint
@@ -209,6 +222,7 @@ impl Peer {
                 () = notify_shutdown.notified() => {
                     sleep(Duration::from_millis(SERVER_ENDPOINT_DELAY)).await;      // Wait time for connection to be ready for shutdown.
                     server_endpoint.close(0_u32.into(), &[]);
+                    self.bound.store(false, Ordering::SeqCst);
                     info!("Shutting down peer");
                     return Ok(())
                 }
@@ -726,6 +740,48 @@ async fn update_to_new_source_list(
     peers.write().await.insert(remote_addr, recv_source_list);
 }
 
+/// Resolves the hostnames of the peers to their addresses, so that the GraphQL
+/// requests to a peer are sent to its hostname and verified against it.
+struct PeerResolver(PeerIdents);
+
+impl Resolve for PeerResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let peer_idents = self.0.clone();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = peer_idents
+                .read()
+                .await
+                .iter()
+                .filter(|ident| ident.hostname == name.as_str())
+                .map(|ident| ident.addr)
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("unknown peer hostname: {}", name.as_str()).into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Returns the client of the GraphQL requests to the peers.
+///
+/// The client presents the certificate of this giganto, and accepts only the
+/// peers whose certificates are issued by the root certificates, are not
+/// revoked, and match the hostnames in `peer_idents`.
+///
+/// # Errors
+///
+/// Returns an error if the certificate or the key is not valid.
+pub fn request_client(certs: &Arc<Certs>, peer_idents: PeerIdents) -> Result<reqwest::Client> {
+    let tls_config = client_tls_config(certs)?;
+    reqwest::Client::builder()
+        .use_preconfigured_tls(tls_config)
+        .dns_resolver(Arc::new(PeerResolver(peer_idents)))
+        .build()
+        .context("failed to build the request client")
+}
+
 #[cfg(test)]
 pub mod tests {
     use std::{
@@ -738,11 +794,12 @@ pub mod tests {
 
     use giganto_client::connection::client_handshake;
     use quinn::{Connection, Endpoint, RecvStream, SendStream};
+    use reqwest::dns::Resolve;
     use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
     use tempfile::TempDir;
     use tokio::sync::{Mutex, Notify, RwLock};
 
-    use super::Peer;
+    use super::{Peer, PeerResolver};
     use crate::{
         peer::{receive_peer_data, request_init_info, PeerCode, PeerIdentity},
         server::Certs,
@@ -761,7 +818,7 @@ pub mod tests {
     const CA_CERT_PATH: &str = "tests/certs/ca_cert.pem";
     const HOST: &str = "node1";
     const TEST_PORT: u16 = 60191;
-    const PROTOCOL_VERSION: &str = "0.22.1";
+    const PROTOCOL_VERSION: &str = "0.23.0-alpha.1";
 
     pub struct TestClient {
         send: SendStream,
@@ -851,6 +908,7 @@ pub mod tests {
             certs: cert,
             key,
             root,
+            crls: Vec::new(),
         });
 
         Peer::new(
@@ -939,4 +997,21 @@ pub mod tests {
         assert!(update_source_list.ingest_sources.contains(&source_name));
         assert!(update_source_list.ingest_sources.contains(&source_name2));
     }
+
+    #[tokio::test]
+    async fn peer_resolver() {
+        let addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), TEST_PORT);
+        let peer_idents = Arc::new(RwLock::new(HashSet::from([PeerIdentity {
+            addr,
+            hostname: HOST.to_string(),
+        }])));
+        let resolver = PeerResolver(peer_idents);
+        let addrs: Vec<_> = resolver
+            .resolve(HOST.parse().unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert_eq!(addrs, [addr]);
+        assert!(resolver.resolve("node2".parse().unwrap()).await.is_err());
+    }
 }
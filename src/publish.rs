@@ -6,7 +6,14 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::{anyhow, bail, Context, Result};
 use chrono::{TimeZone, Utc};
@@ -57,14 +64,22 @@ use crate::server::{
     config_client, config_server, extract_cert_from_conn, subject_from_cert_verbose, Certs,
     SERVER_CONNNECTION_DELAY, SERVER_ENDPOINT_DELAY,
 };
-use crate::storage::{Database, Direction, RawEventStore, StorageKey};
+use crate::storage::{timestamp_from_key, Database, Direction, RawEventStore, StorageKey};
 use crate::{IngestSources, PcapSources, StreamDirectChannels};
 
+// The wire protocol is the same as in 0.22.1, so the clients of that version
+// are still accepted. It is bumped together with `INGEST_VERSION_REQ`
+// when the protocol changes.
 const PUBLISH_VERSION_REQ: &str = ">=0.21.0,<0.23.0";
 
+/// The prefix of the channel keys of the GraphQL subscriptions, whose messages
+/// carry the source of the event like those of hog.
+pub const SUBSCRIPTION_CHANNEL_PREFIX: &str = "graphql\0";
+
 pub struct Server {
     server_config: ServerConfig,
     server_address: SocketAddr,
+    bound: Arc<AtomicBool>,
 }
 
 impl Server {
@@ -74,9 +89,16 @@ impl Server {
         Server {
             server_config,
             server_address: addr,
+            bound: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Returns the flag that tells whether the server is accepting
+    /// connections.
+    pub fn bound(&self) -> Arc<AtomicBool> {
+        self.bound.clone()
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn run(
         self,
@@ -90,6 +112,7 @@ impl Server {
         notify_shutdown: Arc<Notify>,
     ) {
         let endpoint = Endpoint::server(self.server_config, self.server_address).expect("endpoint");
+        self.bound.store(true, Ordering::SeqCst);
         info!(
             "listening on {}",
             endpoint.local_addr().expect("for local addr display")
@@ -128,6 +151,7 @@ impl Server {
                 () = notify_shutdown.notified() => {
                     sleep(Duration::from_millis(SERVER_ENDPOINT_DELAY)).await;      // Wait time for channels,connection to be ready for shutdown.
                     endpoint.close(0_u32.into(), &[]);
+                    self.bound.store(false, Ordering::SeqCst);
                     info!("Shutting down publish");
                     break;
                 },
@@ -824,13 +848,16 @@ pub async fn send_direct_stream(
     source: &str,
     stream_direct_channels: StreamDirectChannels,
 ) -> Result<()> {
+    let hog_prefix = format!("{}\0", NodeType::Hog);
     for (req_key, sender) in &*stream_direct_channels.read().await {
-        if req_key.contains(&network_key.source_key) || req_key.contains(&network_key.all_key) {
+        if is_channel_of(req_key, network_key) {
             let raw_len = u32::try_from(raw_event.len())?.to_le_bytes();
             let mut send_buf: Vec<u8> = Vec::new();
             send_buf.extend_from_slice(&timestamp.to_le_bytes());
 
-            if req_key.contains(&NodeType::Hog.to_string()) {
+            let with_source = req_key.starts_with(&hog_prefix)
+                || req_key.starts_with(SUBSCRIPTION_CHANNEL_PREFIX);
+            if with_source {
                 let source_bytes = bincode::serialize(&source)?;
                 let source_len = u32::try_from(source_bytes.len())?.to_le_bytes();
                 send_buf.extend_from_slice(&source_len);
@@ -845,6 +872,19 @@ pub async fn send_direct_stream(
     Ok(())
 }
 
+/// Returns whether a channel key, `{prefix}\0{source}\0{protocol}`, is for
+/// the events of `network_key`, comparing the source as a whole so that the
+/// key of `ax` is not matched by the events of `x`.
+fn is_channel_of(req_key: &str, network_key: &NetworkKey) -> bool {
+    [&network_key.source_key, &network_key.all_key]
+        .into_iter()
+        .any(|key| {
+            req_key
+                .strip_suffix(key.as_str())
+                .is_some_and(|prefix| prefix.ends_with('\0'))
+        })
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn send_stream<T, N>(
     store: RawEventStore<'_, T>,
@@ -891,7 +931,7 @@ where
             info!("start crusher's publish stream : {:?}", record_type);
 
             let key_builder = StorageKey::builder()
-                .start_key(&msg.source()?)
+                .start_key(store.source_key(&msg.source()?))
                 .mid_key(kind.map(|s| s.as_bytes().to_vec()));
             let from_key = key_builder
                 .clone()
@@ -906,8 +946,7 @@ where
                     bail!("Failed to deserialize database data");
                 };
                 if msg.filter_ip(orig_addr, resp_addr) {
-                    let timestamp =
-                        i64::from_be_bytes(key[(key.len() - TIMESTAMP_SIZE)..].try_into()?);
+                    let timestamp = timestamp_from_key(&key)?;
                     send_crusher_data(&mut sender, timestamp, val).await?;
                     last_ts = timestamp;
                 }
@@ -1972,7 +2011,7 @@ async fn process_range_data_in_current_giganto<'c, T>(
 where
     T: DeserializeOwned + ResponseRangeData,
 {
-    let key_builder = StorageKey::builder().start_key(&request_range.source);
+    let key_builder = StorageKey::builder().start_key(store.source_key(&request_range.source));
     let key_builder = if availed_kind {
         key_builder.mid_key(Some(request_range.kind.as_bytes().to_vec()))
     } else {
@@ -1991,7 +2030,7 @@ where
 
     for item in iter.take(request_range.count) {
         let (key, val) = item.context("Failed to read Database")?;
-        let timestamp = i64::from_be_bytes(key[(key.len() - TIMESTAMP_SIZE)..].try_into()?);
+        let timestamp = timestamp_from_key(&key)?;
         send_range_data(send, Some((val, timestamp, &request_range.source))).await?;
     }
 
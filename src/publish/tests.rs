@@ -103,6 +103,7 @@ fn server() -> Server {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     Server::new(
@@ -167,15 +168,16 @@ fn init_client() -> Endpoint {
     endpoint
 }
 
-fn gen_network_event_key(source: &str, kind: Option<&str>, timestamp: i64) -> Vec<u8> {
+fn gen_network_event_key(source_key: &[u8], kind: Option<&str>, timestamp: i64) -> Vec<u8> {
     let mut key = Vec::new();
-    key.extend_from_slice(source.as_bytes());
+    key.extend_from_slice(source_key);
     key.push(0);
     if let Some(kind) = kind {
         key.extend_from_slice(kind.as_bytes());
         key.push(0);
     }
     key.extend(timestamp.to_be_bytes());
+    key.extend(0_u32.to_be_bytes());
     key
 }
 
@@ -595,42 +597,42 @@ fn gen_dhcp_raw_event() -> Vec<u8> {
 }
 
 fn insert_conn_raw_event(store: &RawEventStore<Conn>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_conn_body = gen_conn_raw_event();
     store.append(&key, &ser_conn_body).unwrap();
     ser_conn_body
 }
 
 fn insert_dns_raw_event(store: &RawEventStore<Dns>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_dns_body = gen_dns_raw_event();
     store.append(&key, &ser_dns_body).unwrap();
     ser_dns_body
 }
 
 fn insert_rdp_raw_event(store: &RawEventStore<Rdp>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_rdp_body = gen_rdp_raw_event();
     store.append(&key, &ser_rdp_body).unwrap();
     ser_rdp_body
 }
 
 fn insert_http_raw_event(store: &RawEventStore<Http>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_http_body = gen_http_raw_event();
     store.append(&key, &ser_http_body).unwrap();
     ser_http_body
 }
 
 fn insert_smtp_raw_event(store: &RawEventStore<Smtp>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_smtp_body = gen_smtp_raw_event();
     store.append(&key, &ser_smtp_body).unwrap();
     ser_smtp_body
 }
 
 fn insert_ntlm_raw_event(store: &RawEventStore<Ntlm>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_ntlm_body = gen_ntlm_raw_event();
     store.append(&key, &ser_ntlm_body).unwrap();
     ser_ntlm_body
@@ -641,14 +643,14 @@ fn insert_kerberos_raw_event(
     source: &str,
     timestamp: i64,
 ) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_kerberos_body = gen_kerberos_raw_event();
     store.append(&key, &ser_kerberos_body).unwrap();
     ser_kerberos_body
 }
 
 fn insert_ssh_raw_event(store: &RawEventStore<Ssh>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_ssh_body = gen_ssh_raw_event();
     store.append(&key, &ser_ssh_body).unwrap();
     ser_ssh_body
@@ -659,7 +661,7 @@ fn insert_dce_rpc_raw_event(
     source: &str,
     timestamp: i64,
 ) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_dce_rpc_body = gen_dce_rpc_raw_event();
     store.append(&key, &ser_dce_rpc_body).unwrap();
     ser_dce_rpc_body
@@ -671,7 +673,11 @@ fn insert_log_raw_event(
     kind: &str,
     timestamp: i64,
 ) -> Vec<u8> {
-    let key = gen_network_event_key(source, Some(kind), timestamp);
+    let key = gen_network_event_key(
+        &store.assign_source_key(source).unwrap(),
+        Some(kind),
+        timestamp,
+    );
     let ser_log_body = gen_log_raw_event();
     store.append(&key, &ser_log_body).unwrap();
     ser_log_body
@@ -682,63 +688,63 @@ fn insert_periodic_time_series_raw_event(
     source: &str,
     timestamp: i64,
 ) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_periodic_time_series_body = gen_periodic_time_series_raw_event();
     store.append(&key, &ser_periodic_time_series_body).unwrap();
     ser_periodic_time_series_body
 }
 
 fn insert_ftp_raw_event(store: &RawEventStore<Ftp>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_ftp_body = gen_ftp_raw_event();
     store.append(&key, &ser_ftp_body).unwrap();
     ser_ftp_body
 }
 
 fn insert_mqtt_raw_event(store: &RawEventStore<Mqtt>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_mqtt_body = gen_mqtt_raw_event();
     store.append(&key, &ser_mqtt_body).unwrap();
     ser_mqtt_body
 }
 
 fn insert_ldap_raw_event(store: &RawEventStore<Ldap>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_ldap_body = gen_ldap_raw_event();
     store.append(&key, &ser_ldap_body).unwrap();
     ser_ldap_body
 }
 
 fn insert_tls_raw_event(store: &RawEventStore<Tls>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_tls_body = gen_tls_raw_event();
     store.append(&key, &ser_tls_body).unwrap();
     ser_tls_body
 }
 
 fn insert_smb_raw_event(store: &RawEventStore<Smb>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_smb_body = gen_smb_raw_event();
     store.append(&key, &ser_smb_body).unwrap();
     ser_smb_body
 }
 
 fn insert_nfs_raw_event(store: &RawEventStore<Nfs>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_nfs_body = gen_nfs_raw_event();
     store.append(&key, &ser_nfs_body).unwrap();
     ser_nfs_body
 }
 
 fn insert_bootp_raw_event(store: &RawEventStore<Bootp>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_bootp_body = gen_bootp_raw_event();
     store.append(&key, &ser_bootp_body).unwrap();
     ser_bootp_body
 }
 
 fn insert_dhcp_raw_event(store: &RawEventStore<Dhcp>, source: &str, timestamp: i64) -> Vec<u8> {
-    let key = gen_network_event_key(source, None, timestamp);
+    let key = gen_network_event_key(&store.assign_source_key(source).unwrap(), None, timestamp);
     let ser_dhcp_body = gen_dhcp_raw_event();
     store.append(&key, &ser_dhcp_body).unwrap();
     ser_dhcp_body
@@ -790,6 +796,7 @@ async fn request_range_data_with_protocol() {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     tokio::spawn(server().run(
@@ -1908,6 +1915,7 @@ async fn request_range_data_with_log() {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     tokio::spawn(server().run(
@@ -2015,6 +2023,7 @@ async fn request_range_data_with_period_time_series() {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     tokio::spawn(server().run(
@@ -2163,6 +2172,7 @@ async fn request_network_event_stream() {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     tokio::spawn(server().run(
@@ -4053,6 +4063,7 @@ async fn request_raw_events() {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     tokio::spawn(server().run(
@@ -4138,6 +4149,7 @@ async fn request_range_data_with_protocol_giganto_cluster() {
             certs: cert,
             key,
             root,
+            crls: Vec::new(),
         });
 
         let peers = Arc::new(tokio::sync::RwLock::new(HashMap::from([(
@@ -4236,6 +4248,7 @@ async fn request_range_data_with_protocol_giganto_cluster() {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     tokio::spawn(server().run(
@@ -4345,6 +4358,7 @@ async fn request_range_data_with_log_giganto_cluster() {
             certs: cert,
             key,
             root,
+            crls: Vec::new(),
         });
 
         let peers = Arc::new(tokio::sync::RwLock::new(HashMap::from([(
@@ -4443,6 +4457,7 @@ async fn request_range_data_with_log_giganto_cluster() {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     tokio::spawn(server().run(
@@ -4550,6 +4565,7 @@ async fn request_range_data_with_period_time_series_giganto_cluster() {
             certs: cert,
             key,
             root,
+            crls: Vec::new(),
         });
 
         let peers = Arc::new(tokio::sync::RwLock::new(HashMap::from([(
@@ -4653,6 +4669,7 @@ async fn request_range_data_with_period_time_series_giganto_cluster() {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     tokio::spawn(server().run(
@@ -4760,6 +4777,7 @@ async fn request_raw_events_giganto_cluster() {
             certs: cert,
             key,
             root,
+            crls: Vec::new(),
         });
 
         let peers = Arc::new(tokio::sync::RwLock::new(HashMap::from([(
@@ -4855,6 +4873,7 @@ async fn request_raw_events_giganto_cluster() {
         certs: cert,
         key,
         root,
+        crls: Vec::new(),
     });
 
     tokio::spawn(server().run(
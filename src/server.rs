@@ -6,7 +6,9 @@ use quinn::{
     ClientConfig, Connection, ServerConfig, TransportConfig,
 };
 use rustls::{
-    pki_types::{CertificateDer, PrivateKeyDer},
+    client::WebPkiServerVerifier,
+    pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer},
+    server::{ClientCertVerifierBuilder, WebPkiClientVerifier},
     RootCertStore,
 };
 use tracing::info;
@@ -22,6 +24,8 @@ pub struct Certs {
     pub certs: Vec<CertificateDer<'static>>,
     pub key: PrivateKeyDer<'static>,
     pub root: RootCertStore,
+    /// The certificates revoked by the issuers in `root`.
+    pub crls: Vec<CertificateRevocationListDer<'static>>,
 }
 
 impl Clone for Certs {
@@ -30,14 +34,25 @@ impl Clone for Certs {
             certs: self.certs.clone(),
             key: self.key.clone_key(),
             root: self.root.clone(),
+            crls: self.crls.clone(),
         }
     }
 }
 
+/// Returns the builder of the verifier of the client certificates, which
+/// checks them with the root certificates and the CRLs in `certs`.
+fn client_verifier_builder(certs: &Certs) -> ClientCertVerifierBuilder {
+    // Only the revocation of the end-entity certificates is checked, so that
+    // the issuers without a CRL are still accepted.
+    WebPkiClientVerifier::builder(Arc::new(certs.root.clone()))
+        .with_crls(certs.crls.clone())
+        .only_check_end_entity_revocation()
+        .allow_unknown_revocation_status()
+}
+
 #[allow(clippy::module_name_repetitions)]
 pub fn config_server(certs: &Arc<Certs>) -> Result<ServerConfig> {
-    let client_auth =
-        rustls::server::WebPkiClientVerifier::builder(Arc::new(certs.root.clone())).build()?;
+    let client_auth = client_verifier_builder(certs).build()?;
 
     let server_crypto = rustls::ServerConfig::builder()
         .with_client_cert_verifier(client_auth)
@@ -54,6 +69,21 @@ pub fn config_server(certs: &Arc<Certs>) -> Result<ServerConfig> {
     Ok(server_config)
 }
 
+/// Returns the TLS configuration of the web server, which verifies the client
+/// certificates with the root certificates and the CRLs in `certs`, as the
+/// QUIC servers do, but also accepts the clients without a certificate.
+pub fn web_server_tls_config(certs: &Certs) -> Result<rustls::ServerConfig> {
+    let client_auth = client_verifier_builder(certs)
+        .allow_unauthenticated()
+        .build()?;
+    let mut tls_config = rustls::ServerConfig::builder()
+        .with_client_cert_verifier(client_auth)
+        .with_single_cert(certs.certs.clone(), certs.key.clone_key())
+        .context("web server config error")?;
+    tls_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(tls_config)
+}
+
 pub fn extract_cert_from_conn(connection: &Connection) -> Result<Vec<CertificateDer>> {
     let Some(conn_info) = connection.peer_identity() else {
         bail!("no peer identity");
@@ -102,10 +132,23 @@ pub fn subject_from_cert_opt(
     }
 }
 
-pub fn config_client(certs: &Arc<Certs>) -> Result<ClientConfig> {
+/// Returns the TLS configuration of the clients, which presents the
+/// certificate in `certs` and verifies the servers with the root certificates
+/// and the CRLs in it.
+pub fn client_tls_config(certs: &Certs) -> Result<rustls::ClientConfig> {
+    let verifier = WebPkiServerVerifier::builder(Arc::new(certs.root.clone()))
+        .with_crls(certs.crls.clone())
+        .only_check_end_entity_revocation()
+        .allow_unknown_revocation_status()
+        .build()?;
     let tls_config = rustls::ClientConfig::builder()
-        .with_root_certificates(certs.root.clone())
+        .with_webpki_verifier(verifier)
         .with_client_auth_cert(certs.certs.clone(), certs.key.clone_key())?;
+    Ok(tls_config)
+}
+
+pub fn config_client(certs: &Arc<Certs>) -> Result<ClientConfig> {
+    let tls_config = client_tls_config(certs)?;
 
     let mut transport = TransportConfig::default();
     transport.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
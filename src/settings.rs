@@ -1,7 +1,8 @@
 //! Configurations for the application.
 use std::{collections::HashSet, net::SocketAddr, path::PathBuf, time::Duration};
 
-use clap::{ArgAction, Parser};
+use chrono::{DateTime, Utc};
+use clap::{ArgAction, Parser, Subcommand};
 use config::{builder::DefaultState, Config as ConfConfig, ConfigBuilder, ConfigError, File};
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 use toml::ser::Error as TomlError;
@@ -18,29 +19,112 @@ const DEFAULT_MAX_OPEN_FILES: i32 = 8000;
 const DEFAULT_MAX_MB_OF_LEVEL_BASE: u64 = 512;
 const DEFAULT_NUM_OF_THREAD: i32 = 8;
 const DEFAULT_MAX_SUB_COMPACTIONS: u32 = 2;
+const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Parser, Debug)]
-#[command(version)]
+#[command(version, subcommand_negates_reqs = true)]
 pub struct Args {
     /// Path to the local configuration TOML file.
     #[arg(short, value_name = "CONFIG_PATH")]
     pub config: Option<String>,
 
     /// Path to the certificate file.
-    #[arg(long, value_name = "CERT_PATH")]
-    pub cert: String,
+    #[arg(long, value_name = "CERT_PATH", required = true)]
+    pub cert: Option<String>,
 
     /// Path to the key file.
-    #[arg(long, value_name = "KEY_PATH")]
-    pub key: String,
+    #[arg(long, value_name = "KEY_PATH", required = true)]
+    pub key: Option<String>,
 
     /// Paths to the CA certificate files.
     #[arg(long, value_name = "CA_CERTS_PATHS", action = ArgAction::Append, required = true)]
     pub ca_certs: Vec<String>,
 
+    /// Paths to the certificate revocation list (CRL) files.
+    #[arg(long = "crl", value_name = "CRL_PATHS", action = ArgAction::Append)]
+    pub crls: Vec<String>,
+
     /// Enable the repair mode.
     #[arg(long)]
     pub repair: bool,
+
+    /// Create a backup of the database in the backup directory and exit.
+    ///
+    /// If giganto is running, it is asked to create the backup.
+    #[arg(long)]
+    pub backup: bool,
+
+    /// Bearer token of the `--backup` request to the running giganto, if its
+    /// GraphQL API requires one.
+    #[arg(long, value_name = "TOKEN", requires = "backup")]
+    pub token: Option<String>,
+
+    /// Restore the database from the given backup before starting.
+    #[arg(long, value_name = "BACKUP_PATH")]
+    pub restore: Option<String>,
+
+    /// Offline administration command to run instead of the server.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Offline administration commands.
+///
+/// These don't need the certificate, and they work on the database in
+/// `data_dir` of the local configuration.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List the column families with their estimated numbers of keys and sizes.
+    ListCfs,
+
+    /// List the sources that have sent data.
+    ListSources,
+
+    /// Dump the events of a record type from a source as JSON lines.
+    Dump {
+        /// The record type, as in the `protocol` of the `export` GraphQL API.
+        #[arg(long)]
+        protocol: String,
+
+        /// The source of the events.
+        #[arg(long)]
+        source: String,
+
+        /// The kind of `log` and `secu log` events.
+        #[arg(long)]
+        kind: Option<String>,
+
+        /// The agent name of `op_log` events.
+        #[arg(long)]
+        agent_name: Option<String>,
+
+        /// The start of the time range in RFC 3339 format, inclusive.
+        #[arg(long)]
+        start: Option<DateTime<Utc>>,
+
+        /// The end of the time range in RFC 3339 format, exclusive.
+        #[arg(long)]
+        end: Option<DateTime<Utc>>,
+
+        /// Path to the output file.
+        #[arg(long, value_name = "OUTPUT_PATH")]
+        output: PathBuf,
+    },
+
+    /// Load the events in an archive created by retention back into the
+    /// database.
+    Import {
+        /// Path to the archive directory.
+        #[arg(long, value_name = "ARCHIVE_PATH")]
+        archive: PathBuf,
+    },
+
+    /// Compact a column family manually.
+    Compact {
+        /// The name of the column family.
+        #[arg(long)]
+        cf: String,
+    },
 }
 
 impl Args {
@@ -59,16 +143,28 @@ pub struct Config {
     pub data_dir: PathBuf, // DB storage path
     #[serde(with = "humantime_serde")]
     pub retention: Duration, // Data retention period
+    #[serde(default)]
+    pub retention_policies: Vec<RetentionPolicy>, // Retention periods overriding `retention`
     #[serde(deserialize_with = "deserialize_socket_addr")]
     pub graphql_srv_addr: SocketAddr, // IP address & port to graphql
     pub log_dir: PathBuf,  // giganto's syslog path
     pub export_dir: PathBuf, // giganto's export file path
+    #[serde(default = "default_backup_dir")]
+    pub backup_dir: PathBuf, // giganto's database backup path
+    #[serde(default)]
+    pub archive_dir: Option<PathBuf>, // path to archive the data before retention deletes it
+    #[serde(default)]
+    pub archive_format: ArchiveFormat, // format of the archived events besides the raw ones
 
     // db options
     pub max_open_files: i32,
     pub max_mb_of_level_base: u64,
     pub num_of_thread: i32,
     pub max_sub_compactions: u32,
+    #[serde(default)]
+    pub cf_options: Vec<CfOptions>, // RocksDB options overriding the db options per column family
+    #[serde(default)]
+    pub storage_tiers: Vec<StorageTier>, // Directories of the SST files, from the fastest
 
     // peers
     #[serde(default, deserialize_with = "deserialize_peer_addr")]
@@ -77,6 +173,177 @@ pub struct Config {
 
     // ack transmission interval
     pub ack_transmission: u16,
+
+    // durability of ingested data
+    #[serde(default)]
+    pub sync_policy: SyncPolicy, // When to fsync the data before acknowledging it
+    #[serde(default = "default_sync_interval", with = "humantime_serde")]
+    pub sync_interval: Duration, // fsync interval for the `periodic` sync policy
+
+    // ingest limits
+    #[serde(default)]
+    pub ingest_limits: Vec<IngestLimit>, // Limits on the events ingested from the sources
+
+    // timestamp checks
+    #[serde(default)]
+    pub timestamp_window: TimestampWindow, // Event timestamps accepted relative to the server time
+
+    // graphql authentication
+    #[serde(default)]
+    pub jwt_key: Option<PathBuf>, // Key to verify the bearer tokens of the GraphQL requests
+}
+
+/// The range of the event timestamps accepted at ingest, relative to the time
+/// of the server when the events are received.
+///
+/// A side without a limit accepts any timestamp.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimestampWindow {
+    #[serde(default, with = "humantime_serde")]
+    pub max_past: Option<Duration>, // How far behind the server time a timestamp can be
+    #[serde(default, with = "humantime_serde")]
+    pub max_future: Option<Duration>, // How far ahead of the server time a timestamp can be
+    #[serde(default)]
+    pub action: WindowAction, // What to do with the events outside the window
+}
+
+/// What the ingest server does with the events whose timestamps are outside
+/// the `timestamp_window`.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowAction {
+    /// Acknowledges the events without storing them.
+    #[default]
+    Reject,
+    /// Stores the events with their timestamps moved to the nearest end of
+    /// the window.
+    Clamp,
+    /// Stores the events in the `quarantine` column family instead of the
+    /// column family of their kind.
+    Quarantine,
+}
+
+/// Limits on the events ingested from each source.
+///
+/// If `source` is given, the limits apply only to that source, and if `kind`
+/// is given, only to the events of that kind. Without `kind`, the events of
+/// all the kinds from a source count together.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IngestLimit {
+    pub source: Option<String>,
+    pub kind: Option<String>,
+    pub events_per_sec: Option<u64>,
+    pub bytes_per_sec: Option<u64>,
+    pub daily_bytes: Option<u64>, // in bytes per UTC day
+    #[serde(default)]
+    pub action: LimitAction, // What to do with the events over the limits
+}
+
+/// What the ingest server does with the events over a limit.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitAction {
+    /// Stops reading and acknowledging the events until the limit allows
+    /// them, so that the sender slows down.
+    #[default]
+    Delay,
+    /// Acknowledges the events without storing them, counting them as
+    /// dropped.
+    Drop,
+}
+
+/// When the ingested data is fsynced to the disk.
+///
+/// Regardless of the policy, the data is written to the operating system
+/// before it is acknowledged, so it survives a crash of giganto.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPolicy {
+    /// Fsyncs the data before each acknowledgement.
+    #[default]
+    Ack,
+    /// Fsyncs the data every `sync_interval`.
+    Periodic,
+    /// Leaves fsyncing the data to the operating system.
+    None,
+}
+
+/// The format of the archived events for reading them without giganto.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    /// The JSON lines of the `export` API.
+    #[default]
+    Json,
+    /// The tab-separated lines of the `export` API.
+    Csv,
+}
+
+impl ArchiveFormat {
+    /// Returns the `export_type` of the `export` API for the format.
+    #[must_use]
+    pub fn export_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Json => "json",
+            ArchiveFormat::Csv => "csv",
+        }
+    }
+}
+
+/// A retention period applied to a record type instead of the global
+/// `retention`.
+///
+/// If `source` is given, the period applies only to the data of that source.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub record_type: String,
+    pub source: Option<String>,
+    #[serde(with = "humantime_serde")]
+    pub retention: Duration,
+}
+
+/// RocksDB options of a column family that override the ones derived from the
+/// db options.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CfOptions {
+    pub name: String,
+    pub compression: Option<Compression>,
+    pub bottommost_compression: Option<Compression>,
+    pub block_size: Option<u64>,        // in bytes
+    pub write_buffer_size: Option<u64>, // in bytes
+    /// Whether to keep a bloom filter of the sources of the keys in each SST
+    /// file, so that the files without the source are skipped when the data of
+    /// a source is read.
+    #[serde(default)]
+    pub source_bloom_filter: bool,
+    /// The directories of the SST files of the column family, overriding
+    /// `storage_tiers` of the configuration.
+    #[serde(default)]
+    pub storage_tiers: Vec<StorageTier>,
+}
+
+/// A directory where RocksDB places the SST files, up to the target size.
+///
+/// The files of each level are placed in the first tier that can hold the
+/// level, so the newer data in the upper levels stay in the first tiers and
+/// the older data move to the later tiers as they are compacted. The last tier
+/// takes the rest of the files regardless of its target size.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StorageTier {
+    pub path: PathBuf,
+    pub target_size: u64, // in bytes
+}
+
+/// The compression algorithm of the SST files.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    None,
+    Snappy,
+    Zlib,
+    Lz4,
+    Lz4hc,
+    Zstd,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -144,6 +411,17 @@ impl Settings {
     }
 }
 
+fn default_sync_interval() -> Duration {
+    DEFAULT_SYNC_INTERVAL
+}
+
+fn default_backup_dir() -> PathBuf {
+    directories::ProjectDirs::from_path(PathBuf::from("backup"))
+        .expect("unreachable backup dir")
+        .data_dir()
+        .to_path_buf()
+}
+
 /// Creates a new `ConfigBuilder` instance with the default configuration.
 fn default_config_builder() -> ConfigBuilder<DefaultState> {
     let db_dir =
@@ -1,17 +1,29 @@
 //! Raw event storage based on RocksDB.
 
+mod archive;
+mod audit;
+mod backup;
+mod ingest_counter;
+mod key_sequence;
+mod legal_hold;
 mod migration;
+mod scrub;
+mod source_jobs;
 
 use std::{
-    collections::HashSet,
+    collections::{BTreeSet, HashSet},
     marker::PhantomData,
     ops::Deref,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+pub use archive::{import_archive, read_manifest, ArchiveEntry, Archiver};
+use audit::AUDIT_COLUMN_FAMILY_NAME;
+pub use audit::{AuditEntry, AuditStore};
+pub use backup::{list_backups, prune_backups, restore_backup, BackupInfo};
 use chrono::{DateTime, Utc};
 pub use giganto_client::ingest::network::{Conn, Http, Ntlm, Smtp, Ssh, Tls};
 use giganto_client::ingest::{
@@ -27,20 +39,31 @@ use giganto_client::ingest::{
     timeseries::PeriodicTimeSeries,
     Packet,
 };
-pub use migration::migrate_data_dir;
-#[cfg(debug_assertions)]
-use rocksdb::properties;
+use ingest_counter::{merge_counts, INGEST_COUNTERS_COLUMN_FAMILY_NAME};
+pub use ingest_counter::{IngestCount, IngestCounterStore};
+use key_sequence::KeySequences;
+use legal_hold::{unheld_ranges, LEGAL_HOLDS_COLUMN_FAMILY_NAME};
+pub use legal_hold::{LegalHold, LegalHoldStore};
+use migration::MIGRATION_COLUMN_FAMILY_NAME;
+pub use migration::{is_migrated, migrate_data_dir};
 pub use rocksdb::Direction;
 use rocksdb::{
-    ColumnFamily, ColumnFamilyDescriptor, DBIteratorWithThreadMode, Options, ReadOptions, DB,
+    properties, BlockBasedOptions, ColumnFamily, ColumnFamilyDescriptor, DBCompressionType,
+    DBIteratorWithThreadMode, DBPath, MergeOperands, Options, ReadOptions, SliceTransform,
+    Snapshot, WriteBatch, DB,
 };
-use serde::de::DeserializeOwned;
+pub use scrub::{CfIntegrity, IntegrityReport, SourceIntegrity};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use source_jobs::SOURCE_JOBS_COLUMN_FAMILY_NAME;
+pub use source_jobs::{SourceJob, SourceJobKind, SourceJobStore};
+use sysinfo::{DiskExt, System, SystemExt};
 use tokio::{select, sync::Notify, time};
 use tracing::{debug, error, info, warn};
 
 use crate::{
-    graphql::{NetworkFilter, RawEventFilter, TIMESTAMP_SIZE},
-    ingest::implement::EventFilter,
+    graphql::{NetworkFilter, RawEventFilter, SEQUENCE_SIZE, TIMESTAMP_SIZE},
+    ingest::{implement::EventFilter, DeadLetter},
+    settings::{CfOptions, Compression, RetentionPolicy, StorageTier, SyncPolicy},
 };
 
 const RAW_DATA_COLUMN_FAMILY_NAMES: [&str; 39] = [
@@ -84,25 +107,45 @@ const RAW_DATA_COLUMN_FAMILY_NAMES: [&str; 39] = [
     "netflow9",
     "seculog",
 ];
-const META_DATA_COLUMN_FAMILY_NAMES: [&str; 1] = ["sources"];
-
-// Not a `source`+`timestamp` event.
-const NON_STANDARD_CFS: [&str; 8] = [
-    "log",
-    "periodic time series",
-    "statistics",
-    "oplog",
-    "packet",
-    "seculog",
-    "netflow5", // netflow5 + timestamp
-    "netflow9", // netflow9 + timestamp
+const META_DATA_COLUMN_FAMILY_NAMES: [&str; 10] = [
+    "sources",
+    "dead letters",
+    "quarantine",
+    SOURCE_IDS_COLUMN_FAMILY_NAME,
+    LEGAL_HOLDS_COLUMN_FAMILY_NAME,
+    SOURCE_KINDS_COLUMN_FAMILY_NAME,
+    INGEST_COUNTERS_COLUMN_FAMILY_NAME,
+    AUDIT_COLUMN_FAMILY_NAME,
+    MIGRATION_COLUMN_FAMILY_NAME,
+    SOURCE_JOBS_COLUMN_FAMILY_NAME,
 ];
+// The dictionary of the numeric IDs that stand for the sources in the keys.
+const SOURCE_IDS_COLUMN_FAMILY_NAME: &str = "source ids";
+// The key of the next source ID, which is not valid UTF-8 and so is never the
+// name of a source. The IDs of the deleted sources are not reused, so that a
+// new source never takes over the data of a deleted one.
+const NEXT_SOURCE_ID_KEY: &[u8] = b"\xff";
+// The timestamp of the last event of each kind from each source, keyed by the
+// source ID and the kind.
+const SOURCE_KINDS_COLUMN_FAMILY_NAME: &str = "source kinds";
+// Serializes the assignment of new source IDs.
+static SOURCE_ID_LOCK: Mutex<()> = Mutex::new(());
+
+// `source`+`mid key`+`timestamp` events. The `packet` event also has a mid
+// key, but it is the request timestamp, so it is ordered by time like a
+// `source`+`timestamp` event.
+const NON_STANDARD_CFS: [&str; 4] = ["log", "statistics", "oplog", "seculog"];
+// `id`+`timestamp` events, which are not keyed by source.
+const SOURCELESS_CFS: [&str; 1] = ["periodic time series"];
+// The number of bits per source in the bloom filter of `source_bloom_filter`.
+const SOURCE_BLOOM_FILTER_BITS_PER_KEY: f64 = 10.0;
 const USAGE_THRESHOLD: u64 = 95;
 const USAGE_LOW: u64 = 85;
 
 pub struct RetentionStores<'db, T> {
-    pub standard_cfs: Vec<RawEventStore<'db, T>>,
-    pub non_standard_cfs: Vec<RawEventStore<'db, T>>,
+    pub standard_cfs: Vec<(&'static str, RawEventStore<'db, T>)>,
+    pub non_standard_cfs: Vec<(&'static str, RawEventStore<'db, T>)>,
+    pub sourceless_cfs: Vec<(&'static str, RawEventStore<'db, T>)>,
 }
 
 impl<'db, T> RetentionStores<'db, T> {
@@ -110,11 +153,11 @@ impl<'db, T> RetentionStores<'db, T> {
         RetentionStores {
             standard_cfs: Vec::new(),
             non_standard_cfs: Vec::new(),
+            sourceless_cfs: Vec::new(),
         }
     }
 }
 
-#[cfg(debug_assertions)]
 pub struct CfProperties {
     pub estimate_live_data_size: u64,
     pub estimate_num_keys: u64,
@@ -126,6 +169,8 @@ pub struct DbOptions {
     max_mb_of_level_base: u64,
     num_of_thread: i32,
     max_sub_compactions: u32,
+    cf_options: Vec<CfOptions>,
+    storage_tiers: Vec<StorageTier>,
 }
 
 impl Default for DbOptions {
@@ -135,6 +180,8 @@ impl Default for DbOptions {
             max_mb_of_level_base: 512,
             num_of_thread: 8,
             max_sub_compactions: 2,
+            cf_options: Vec::new(),
+            storage_tiers: Vec::new(),
         }
     }
 }
@@ -145,37 +192,91 @@ impl DbOptions {
         max_mb_of_level_base: u64,
         num_of_thread: i32,
         max_sub_compactions: u32,
+        cf_options: Vec<CfOptions>,
+        storage_tiers: Vec<StorageTier>,
     ) -> Self {
         DbOptions {
             max_open_files,
             max_mb_of_level_base,
             num_of_thread,
             max_sub_compactions,
+            cf_options,
+            storage_tiers,
         }
     }
+
+    /// Returns the directories where the database at `db_path` keeps its
+    /// files, which are `db_path` and the paths of all the storage tiers.
+    pub fn storage_paths(&self, db_path: &Path) -> Vec<PathBuf> {
+        let mut paths = vec![db_path.to_path_buf()];
+        let tiers = self
+            .storage_tiers
+            .iter()
+            .chain(self.cf_options.iter().flat_map(|o| o.storage_tiers.iter()));
+        for tier in tiers {
+            if !paths.contains(&tier.path) {
+                paths.push(tier.path.clone());
+            }
+        }
+        paths
+    }
 }
 
 #[derive(Clone)]
 pub struct Database {
     db: Arc<DB>,
+    sequences: Arc<KeySequences>,
 }
 
 impl Database {
     /// Opens the database at the given path.
     pub fn open(path: &Path, db_options: &DbOptions) -> Result<Database> {
-        let (db_opts, cf_opts) = rocksdb_options(db_options);
-        let mut cfs_name: Vec<&str> = Vec::with_capacity(
-            RAW_DATA_COLUMN_FAMILY_NAMES.len() + META_DATA_COLUMN_FAMILY_NAMES.len(),
-        );
-        cfs_name.extend(RAW_DATA_COLUMN_FAMILY_NAMES);
-        cfs_name.extend(META_DATA_COLUMN_FAMILY_NAMES);
-
-        let cfs = cfs_name
-            .into_iter()
-            .map(|name| ColumnFamilyDescriptor::new(name, cf_opts.clone()));
+        let (db_opts, cf_opts) = rocksdb_options(db_options)?;
+        let cfs = column_family_descriptors(db_options, &cf_opts)?;
 
         let db = DB::open_cf_descriptors(&db_opts, path, cfs).context("cannot open database")?;
-        Ok(Database { db: Arc::new(db) })
+        Ok(Database {
+            db: Arc::new(db),
+            sequences: Arc::default(),
+        })
+    }
+
+    /// Opens the database at the given path in read-only mode.
+    ///
+    /// Nothing can be written to the database opened in this mode, and the data
+    /// written by other processes after it is opened are not visible.
+    pub fn open_read_only(path: &Path, db_options: &DbOptions) -> Result<Database> {
+        let (db_opts, cf_opts) = rocksdb_options(db_options)?;
+        let cfs = column_family_descriptors(db_options, &cf_opts)?;
+
+        let db = DB::open_cf_descriptors_read_only(&db_opts, path, cfs, false)
+            .context("cannot open database")?;
+        Ok(Database {
+            db: Arc::new(db),
+            sequences: Arc::default(),
+        })
+    }
+
+    /// Writes the buffered write-ahead log to the file, and fsyncs it if the
+    /// sync policy is `SyncPolicy::Ack`.
+    ///
+    /// This must be called before acknowledging the ingested data.
+    pub fn flush_wal(&self, sync_policy: SyncPolicy) -> Result<()> {
+        self.db.flush_wal(sync_policy == SyncPolicy::Ack)?;
+        Ok(())
+    }
+
+    /// Fsyncs the write-ahead log.
+    pub fn sync_wal(&self) -> Result<()> {
+        self.db.flush_wal(true)?;
+        Ok(())
+    }
+
+    /// Returns whether the database responds to a read.
+    pub fn is_responsive(&self) -> bool {
+        self.db
+            .property_int_value(properties::ESTIMATE_NUM_KEYS)
+            .is_ok()
     }
 
     /// Shuts down the database, ensuring data integrity and consistency before exiting.
@@ -190,7 +291,6 @@ impl Database {
         Ok(())
     }
 
-    #[cfg(debug_assertions)]
     pub fn properties_cf(&self, cf_name: &str) -> Result<CfProperties> {
         let stats = if let Some(s) = self
             .db
@@ -219,6 +319,28 @@ impl Database {
         })
     }
 
+    /// Returns the total size of the SST files of the column family and the
+    /// estimated number of bytes to be rewritten by its compaction.
+    pub fn cf_size(&self, cf_name: &str) -> Result<(u64, u64)> {
+        let cf = self.get_cf_handle(cf_name)?;
+        let size = self
+            .db
+            .property_int_value_cf(cf, properties::TOTAL_SST_FILES_SIZE)?
+            .unwrap_or_default();
+        let pending_compaction = self
+            .db
+            .property_int_value_cf(cf, properties::ESTIMATE_PENDING_COMPACTION_BYTES)?
+            .unwrap_or_default();
+        Ok((size, pending_compaction))
+    }
+
+    /// Compacts the whole key range of the given column family.
+    pub fn compact_cf(&self, cf_name: &str) -> Result<()> {
+        let cf = self.get_cf_handle(cf_name)?;
+        self.db.compact_range_cf(cf, None::<&[u8]>, None::<&[u8]>);
+        Ok(())
+    }
+
     /// Returns the raw event store for all type.
     pub fn retain_period_store(&self) -> Result<RetentionStores<()>> {
         let mut stores = RetentionStores::new();
@@ -228,10 +350,17 @@ impl Database {
                 let cf = self.get_cf_handle(store)?;
                 stores
                     .non_standard_cfs
-                    .push(RawEventStore::new(&self.db, cf));
+                    .push((store, RawEventStore::new(self, cf)));
+            } else if SOURCELESS_CFS.contains(&store) {
+                let cf = self.get_cf_handle(store)?;
+                stores
+                    .sourceless_cfs
+                    .push((store, RawEventStore::sourceless(self, cf)));
             } else {
                 let cf = self.get_cf_handle(store)?;
-                stores.standard_cfs.push(RawEventStore::new(&self.db, cf));
+                stores
+                    .standard_cfs
+                    .push((store, RawEventStore::new(self, cf)));
             }
         }
         Ok(stores)
@@ -246,247 +375,270 @@ impl Database {
     /// Returns the raw event store for connections.
     pub fn conn_store(&self) -> Result<RawEventStore<Conn>> {
         let cf = self.get_cf_handle("conn")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the raw event store for dns.
     pub fn dns_store(&self) -> Result<RawEventStore<Dns>> {
         let cf = self.get_cf_handle("dns")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the raw event store for log.
     pub fn log_store(&self) -> Result<RawEventStore<Log>> {
         let cf = self.get_cf_handle("log")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the raw event store for http.
     pub fn http_store(&self) -> Result<RawEventStore<Http>> {
         let cf = self.get_cf_handle("http")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the raw event store for rdp.
     pub fn rdp_store(&self) -> Result<RawEventStore<Rdp>> {
         let cf = self.get_cf_handle("rdp")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the raw event store for periodic time series.
     pub fn periodic_time_series_store(&self) -> Result<RawEventStore<PeriodicTimeSeries>> {
         let cf = self.get_cf_handle("periodic time series")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::sourceless(self, cf))
     }
 
     /// Returns the raw event store for smtp.
     pub fn smtp_store(&self) -> Result<RawEventStore<Smtp>> {
         let cf = self.get_cf_handle("smtp")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the raw event store for ntlm.
     pub fn ntlm_store(&self) -> Result<RawEventStore<Ntlm>> {
         let cf = self.get_cf_handle("ntlm")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the raw event store for kerberos.
     pub fn kerberos_store(&self) -> Result<RawEventStore<Kerberos>> {
         let cf = self.get_cf_handle("kerberos")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the raw event store for ssh.
     pub fn ssh_store(&self) -> Result<RawEventStore<Ssh>> {
         let cf = self.get_cf_handle("ssh")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the raw event store for dce rpc.
     pub fn dce_rpc_store(&self) -> Result<RawEventStore<DceRpc>> {
         let cf = self.get_cf_handle("dce rpc")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for statistics
     pub fn statistics_store(&self) -> Result<RawEventStore<Statistics>> {
         let cf = self.get_cf_handle("statistics")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for operation log
     pub fn op_log_store(&self) -> Result<RawEventStore<OpLog>> {
         let cf = self.get_cf_handle("oplog")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for packet
     pub fn packet_store(&self) -> Result<RawEventStore<Packet>> {
         let cf = self.get_cf_handle("packet")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for connection sources
     pub fn sources_store(&self) -> Result<SourceStore> {
         let cf = self.get_cf_handle("sources")?;
-        Ok(SourceStore { db: &self.db, cf })
+        let ids = self.get_cf_handle(SOURCE_IDS_COLUMN_FAMILY_NAME)?;
+        let kinds = self.get_cf_handle(SOURCE_KINDS_COLUMN_FAMILY_NAME)?;
+        Ok(SourceStore {
+            db: &self.db,
+            cf,
+            ids,
+            kinds,
+        })
+    }
+
+    /// Returns the store for the records that could not be decoded at ingest
+    pub fn dead_letter_store(&self) -> Result<RawEventStore<DeadLetter>> {
+        let cf = self.get_cf_handle("dead letters")?;
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for Ftp
     pub fn ftp_store(&self) -> Result<RawEventStore<Ftp>> {
         let cf = self.get_cf_handle("ftp")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for Mqtt
     pub fn mqtt_store(&self) -> Result<RawEventStore<Mqtt>> {
         let cf = self.get_cf_handle("mqtt")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for ldap
     pub fn ldap_store(&self) -> Result<RawEventStore<Ldap>> {
         let cf = self.get_cf_handle("ldap")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for tls
     pub fn tls_store(&self) -> Result<RawEventStore<Tls>> {
         let cf = self.get_cf_handle("tls")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for smb
     pub fn smb_store(&self) -> Result<RawEventStore<Smb>> {
         let cf = self.get_cf_handle("smb")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for nfs
     pub fn nfs_store(&self) -> Result<RawEventStore<Nfs>> {
         let cf = self.get_cf_handle("nfs")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for bootp
     pub fn bootp_store(&self) -> Result<RawEventStore<Bootp>> {
         let cf = self.get_cf_handle("bootp")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for dhcp
     pub fn dhcp_store(&self) -> Result<RawEventStore<Dhcp>> {
         let cf = self.get_cf_handle("dhcp")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `ProcessCreate` (#1).
     pub fn process_create_store(&self) -> Result<RawEventStore<ProcessCreate>> {
         let cf = self.get_cf_handle("process create")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `FileCreateTime` (#2).
     pub fn file_create_time_store(&self) -> Result<RawEventStore<FileCreationTimeChanged>> {
         let cf = self.get_cf_handle("file create time")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `NetworkConnect` (#3).
     pub fn network_connect_store(&self) -> Result<RawEventStore<NetworkConnection>> {
         let cf = self.get_cf_handle("network connect")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `ProcessTerminate` (#5).
     pub fn process_terminate_store(&self) -> Result<RawEventStore<ProcessTerminated>> {
         let cf = self.get_cf_handle("process terminate")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `ImageLoad` (#7).
     pub fn image_load_store(&self) -> Result<RawEventStore<ImageLoaded>> {
         let cf = self.get_cf_handle("image load")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `FileCreate` (#11).
     pub fn file_create_store(&self) -> Result<RawEventStore<FileCreate>> {
         let cf = self.get_cf_handle("file create")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `RegistryValueSet` (#13).
     pub fn registry_value_set_store(&self) -> Result<RawEventStore<RegistryValueSet>> {
         let cf = self.get_cf_handle("registry value set")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `RegistryKeyRename` (#14).
     pub fn registry_key_rename_store(&self) -> Result<RawEventStore<RegistryKeyValueRename>> {
         let cf = self.get_cf_handle("registry key rename")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `FileCreateStreamHash` (#15).
     pub fn file_create_stream_hash_store(&self) -> Result<RawEventStore<FileCreateStreamHash>> {
         let cf = self.get_cf_handle("file create stream hash")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `PipeEvent` (#17).
     pub fn pipe_event_store(&self) -> Result<RawEventStore<PipeEvent>> {
         let cf = self.get_cf_handle("pipe event")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `DnsQuery` (#22).
     pub fn dns_query_store(&self) -> Result<RawEventStore<DnsEvent>> {
         let cf = self.get_cf_handle("dns query")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `FileDelete` (#23).
     pub fn file_delete_store(&self) -> Result<RawEventStore<FileDelete>> {
         let cf = self.get_cf_handle("file delete")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `ProcessTamper` (#25).
     pub fn process_tamper_store(&self) -> Result<RawEventStore<ProcessTampering>> {
         let cf = self.get_cf_handle("process tamper")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for sysmon event `FileDeleteDetected` (#26).
     pub fn file_delete_detected_store(&self) -> Result<RawEventStore<FileDeleteDetected>> {
         let cf = self.get_cf_handle("file delete detected")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for event `netflow5`.
     pub fn netflow5_store(&self) -> Result<RawEventStore<Netflow5>> {
         let cf = self.get_cf_handle("netflow5")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for event `netflow9`.
     pub fn netflow9_store(&self) -> Result<RawEventStore<Netflow9>> {
         let cf = self.get_cf_handle("netflow9")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 
     /// Returns the store for security log.
     pub fn secu_log_store(&self) -> Result<RawEventStore<SecuLog>> {
         let cf = self.get_cf_handle("seculog")?;
-        Ok(RawEventStore::new(&self.db, cf))
+        Ok(RawEventStore::new(self, cf))
     }
 }
 
+/// Returns the names of all the column families in the database.
+pub fn column_family_names() -> impl Iterator<Item = &'static str> {
+    RAW_DATA_COLUMN_FAMILY_NAMES
+        .into_iter()
+        .chain(META_DATA_COLUMN_FAMILY_NAMES)
+}
+
 pub struct RawEventStore<'db, T> {
     db: &'db DB,
     cf: &'db ColumnFamily,
+    sequences: &'db KeySequences,
+    // `None` if the keys start with something other than a source.
+    source_ids: Option<&'db ColumnFamily>,
     phantom: PhantomData<T>,
 }
 
@@ -495,19 +647,132 @@ pub struct RawEventStore<'db, T> {
 unsafe impl<'db, T> Send for RawEventStore<'db, T> {}
 
 impl<'db, T> RawEventStore<'db, T> {
-    fn new(db: &'db DB, cf: &'db ColumnFamily) -> RawEventStore<'db, T> {
+    fn new(database: &'db Database, cf: &'db ColumnFamily) -> RawEventStore<'db, T> {
+        RawEventStore {
+            db: &database.db,
+            cf,
+            sequences: &database.sequences,
+            source_ids: database.db.cf_handle(SOURCE_IDS_COLUMN_FAMILY_NAME),
+            phantom: PhantomData,
+        }
+    }
+
+    // Creates a store whose keys start with an ID of the sender's choice, such
+    // as the ID of a time series, instead of a source.
+    fn sourceless(database: &'db Database, cf: &'db ColumnFamily) -> RawEventStore<'db, T> {
         RawEventStore {
-            db,
+            db: &database.db,
             cf,
+            sequences: &database.sequences,
+            source_ids: None,
             phantom: PhantomData,
         }
     }
 
+    /// Returns the first part of the keys of the source, which is the encoded
+    /// ID of the source.
+    ///
+    /// The returned key is empty, and matches no keys, if the source has no
+    /// ID. For a store not keyed by source, it is the given name as is.
+    pub fn source_key(&self, source: &str) -> Vec<u8> {
+        let Some(ids) = self.source_ids else {
+            return source.as_bytes().to_vec();
+        };
+        match source_id(self.db, ids, source) {
+            Ok(Some(id)) => encode_source_id(id),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the first part of the keys of the source like `source_key`,
+    /// assigning a new ID to the source if it has none.
+    pub fn assign_source_key(&self, source: &str) -> Result<Vec<u8>> {
+        let Some(ids) = self.source_ids else {
+            return Ok(source.as_bytes().to_vec());
+        };
+        Ok(encode_source_id(assign_source_id(self.db, ids, source)?))
+    }
+
     pub fn append(&self, key: &[u8], raw_event: &[u8]) -> Result<()> {
         self.db.put_cf(self.cf, key, raw_event)?;
         Ok(())
     }
 
+    /// Appends the raw events atomically; either all of them or none of them
+    /// are written.
+    pub fn append_batch<'a>(
+        &self,
+        raw_events: impl IntoIterator<Item = (&'a [u8], &'a [u8])>,
+    ) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for (key, raw_event) in raw_events {
+            batch.put_cf(self.cf, key, raw_event);
+        }
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// Appends the raw events atomically, writing in the same batch the
+    /// quarantined ones to the `quarantine` column family, keyed by the name
+    /// of this column family and their keys, as `check_integrity` does with
+    /// the invalid records, and the dead letters to the `dead letters` column
+    /// family.
+    ///
+    /// Each record is given a key prefix, which ends with its timestamp, and
+    /// is stored with the smallest sequence number after it that is used
+    /// neither by a stored record nor by a record before it. So the records
+    /// with the same prefix never overwrite each other, whether they come from
+    /// one stream or several, or from before a restart. The writers of records
+    /// with different prefixes, up to the timestamp, do not wait for each
+    /// other.
+    pub fn append_unique<'a>(
+        &self,
+        raw_events: impl IntoIterator<Item = (&'a [u8], &'a [u8])>,
+        quarantined: impl IntoIterator<Item = (&'a [u8], &'a [u8])>,
+        dead_letters: impl IntoIterator<Item = (&'a [u8], &'a [u8])>,
+    ) -> Result<()> {
+        let mut records: Vec<(&ColumnFamily, Vec<u8>, &[u8])> = raw_events
+            .into_iter()
+            .map(|(prefix, raw_event)| (self.cf, prefix.to_vec(), raw_event))
+            .collect();
+        let mut quarantined = quarantined.into_iter().peekable();
+        if quarantined.peek().is_some() {
+            let quarantine = self
+                .db
+                .cf_handle("quarantine")
+                .context("cannot access quarantine column family")?;
+            let cf_name = self.cf_name()?;
+            for (prefix, raw_event) in quarantined {
+                let mut quarantine_prefix = Vec::with_capacity(cf_name.len() + 1 + prefix.len());
+                quarantine_prefix.extend_from_slice(cf_name.as_bytes());
+                quarantine_prefix.push(0);
+                quarantine_prefix.extend_from_slice(prefix);
+                records.push((quarantine, quarantine_prefix, raw_event));
+            }
+        }
+        let mut dead_letters = dead_letters.into_iter().peekable();
+        if dead_letters.peek().is_some() {
+            let dead_letter_cf = self
+                .db
+                .cf_handle("dead letters")
+                .context("cannot access dead letters column family")?;
+            for (prefix, dead_letter) in dead_letters {
+                records.push((dead_letter_cf, prefix.to_vec(), dead_letter));
+            }
+        }
+        self.sequences.write(self.db, &records)
+    }
+
+    fn cf_name(&self) -> Result<&'static str> {
+        column_family_names()
+            .find(|name| {
+                self.db
+                    .cf_handle(name)
+                    .is_some_and(|cf| std::ptr::eq(cf, self.cf))
+            })
+            .context("unknown column family")
+    }
+
     pub fn delete(&self, key: &[u8]) -> Result<()> {
         self.db.delete_cf(self.cf, key)?;
         Ok(())
@@ -518,19 +783,72 @@ impl<'db, T> RawEventStore<'db, T> {
         Ok(())
     }
 
+    /// Deletes the keys in the range [`from`, `to`) and compacts the range.
+    ///
+    /// The SST files entirely within the range are dropped first, so that most
+    /// of the data is removed without writing tombstones.
+    pub fn delete_range(&self, from: &[u8], to: &[u8]) -> Result<()> {
+        self.flush()?;
+        self.db
+            .delete_file_in_range_cf(self.cf, from, to)
+            .context("cannot delete files in range")?;
+        self.flush()?;
+        self.db
+            .delete_range_cf(self.cf, from, to)
+            .context("cannot delete range")?;
+        self.db.compact_range_cf(self.cf, Some(from), Some(to));
+        Ok(())
+    }
+
+    /// Returns the distinct prefixes, i.e. the keys without the timestamp and
+    /// the sequence number, of the keys starting with `prefix`.
+    ///
+    /// Only the first key of each prefix is read, so this takes time
+    /// proportional to the number of prefixes, not the number of keys.
+    pub fn key_prefixes(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        let mut prefixes = Vec::new();
+        let read_options = if source_prefix(prefix) == Some(prefix) {
+            source_read_options()
+        } else {
+            total_order_read_options()
+        };
+        let mut iter = self.db.raw_iterator_cf_opt(self.cf, read_options);
+        iter.seek(prefix);
+        while let Some(key) = iter.key() {
+            if !key.starts_with(prefix) {
+                break;
+            }
+            if key.len() < prefix.len() + TIMESTAMP_SIZE + SEQUENCE_SIZE {
+                iter.next();
+                continue;
+            }
+            let key_prefix = key[..key.len() - TIMESTAMP_SIZE - SEQUENCE_SIZE].to_vec();
+            let mut next = key_prefix.clone();
+            next.extend_from_slice(&[u8::MAX; TIMESTAMP_SIZE + SEQUENCE_SIZE]);
+            prefixes.push(key_prefix);
+            iter.seek(&next);
+            if iter.key() == Some(next.as_slice()) {
+                iter.next();
+            }
+        }
+        prefixes
+    }
+
     pub fn batched_multi_get_from_ts(
         &self,
         source: &str,
         timestamps: &[DateTime<Utc>],
     ) -> Vec<(DateTime<Utc>, Vec<u8>)> {
+        let source_key = self.source_key(source);
         let mut timestamps = timestamps.to_vec();
         timestamps.sort_unstable();
         let keys = timestamps
             .iter()
             .map(|timestamp| {
                 StorageKey::builder()
-                    .start_key(source)
+                    .start_key(&source_key)
                     .end_key(timestamp.timestamp_nanos_opt().unwrap_or(i64::MAX))
+                    .sequence(0)
                     .build()
                     .key()
             })
@@ -554,14 +872,16 @@ impl<'db, T> RawEventStore<'db, T> {
         source: &str,
         timestamps: &[i64],
     ) -> Vec<(i64, String, Vec<u8>)> {
+        let source_key = self.source_key(source);
         let mut timestamps = timestamps.to_vec();
         timestamps.sort_unstable();
         let keys = timestamps
             .iter()
             .map(|timestamp| {
                 StorageKey::builder()
-                    .start_key(source)
+                    .start_key(&source_key)
                     .end_key(*timestamp)
+                    .sequence(0)
                     .build()
                     .key()
             })
@@ -590,7 +910,13 @@ impl<'db, T: DeserializeOwned> RawEventStore<'db, T> {
         to: &[u8],
         direction: Direction,
     ) -> BoundaryIter<'db, T> {
-        let mut read_options = ReadOptions::default();
+        // The keys between two keys of a source are all of the source.
+        let mut read_options = match (source_prefix(from), source_prefix(to)) {
+            (Some(from_source), Some(to_source)) if from_source == to_source => {
+                source_read_options()
+            }
+            _ => total_order_read_options(),
+        };
         match direction {
             Direction::Forward => {
                 read_options.set_iterate_upper_bound(to);
@@ -606,14 +932,81 @@ impl<'db, T: DeserializeOwned> RawEventStore<'db, T> {
         ))
     }
 
+    /// Returns an iterator over the raw events in the range [`from`, `to`).
+    pub fn range_iter(&self, from: &[u8], to: &[u8]) -> Iter<'db> {
+        Iter::new(self.db.iterator_cf_opt(
+            self.cf,
+            range_read_options(from, to),
+            rocksdb::IteratorMode::From(from, Direction::Forward),
+        ))
+    }
+
+    /// Returns an iterator over the raw events in the range [`from`, `to`) as
+    /// of `snapshot`, which must outlive the iterator.
+    fn snapshot_range_iter(&self, snapshot: &Snapshot<'_>, from: &[u8], to: &[u8]) -> Iter<'db> {
+        let mut read_options = range_read_options(from, to);
+        read_options.set_snapshot(snapshot);
+        Iter::new(self.db.iterator_cf_opt(
+            self.cf,
+            read_options,
+            rocksdb::IteratorMode::From(from, Direction::Forward),
+        ))
+    }
+
     pub fn iter_forward(&self) -> Iter<'db> {
-        Iter::new(self.db.iterator_cf(self.cf, rocksdb::IteratorMode::Start))
+        Iter::new(self.db.iterator_cf_opt(
+            self.cf,
+            total_order_read_options(),
+            rocksdb::IteratorMode::Start,
+        ))
+    }
+}
+
+/// What is known about a source.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SourceMetadata {
+    pub first_seen: DateTime<Utc>,
+    pub last_active: DateTime<Utc>,
+    pub last_connected: Option<DateTime<Utc>>,
+    pub last_disconnected: Option<DateTime<Utc>>,
+    /// The names of the agents that have sent the data of the source, taken
+    /// from the common names of their certificates.
+    pub agents: BTreeSet<String>,
+    /// The protocol version of the client that connected last.
+    pub protocol_version: Option<String>,
+}
+
+impl SourceMetadata {
+    #[must_use]
+    pub fn new(first_seen: DateTime<Utc>) -> Self {
+        Self {
+            first_seen,
+            last_active: first_seen,
+            ..Default::default()
+        }
+    }
+
+    // Combines two records of the source, keeping the earliest first-seen
+    // time, the latest of the other times, and the protocol version of the
+    // latest connection.
+    fn merge(mut self, other: Self) -> Self {
+        if other.last_connected.is_some() && other.last_connected >= self.last_connected {
+            self.protocol_version = other.protocol_version;
+        }
+        self.first_seen = self.first_seen.min(other.first_seen);
+        self.last_active = self.last_active.max(other.last_active);
+        self.last_connected = self.last_connected.max(other.last_connected);
+        self.last_disconnected = self.last_disconnected.max(other.last_disconnected);
+        self.agents.extend(other.agents);
+        self
     }
 }
 
 pub struct SourceStore<'db> {
     db: &'db DB,
     cf: &'db ColumnFamily,
+    ids: &'db ColumnFamily,
+    kinds: &'db ColumnFamily,
 }
 
 impl<'db> SourceStore<'db> {
@@ -621,30 +1014,119 @@ impl<'db> SourceStore<'db> {
     ///
     /// If the source already exists, its last active time is updated.
     pub fn insert(&self, name: &str, last_active: DateTime<Utc>) -> Result<()> {
-        self.db.put_cf(
-            self.cf,
-            name,
-            last_active
-                .timestamp_nanos_opt()
-                .unwrap_or(i64::MAX)
-                .to_be_bytes(),
-        )?;
+        self.update(name, last_active, |_| {})
+    }
+
+    /// Records that an agent of the source has connected with the protocol
+    /// version.
+    pub fn connect(
+        &self,
+        name: &str,
+        agent: &str,
+        protocol_version: &str,
+        time: DateTime<Utc>,
+    ) -> Result<()> {
+        self.update(name, time, |metadata| {
+            metadata.last_connected = Some(time);
+            metadata.agents.insert(agent.to_string());
+            metadata.protocol_version = Some(protocol_version.to_string());
+        })
+    }
+
+    /// Records that an agent of the source has disconnected.
+    pub fn disconnect(&self, name: &str, time: DateTime<Utc>) -> Result<()> {
+        self.update(name, time, |metadata| {
+            metadata.last_disconnected = Some(time);
+        })
+    }
+
+    // Merges the change into the metadata of the source, so that the changes
+    // made at the same time, by different connections for example, are all
+    // kept.
+    fn update(
+        &self,
+        name: &str,
+        last_active: DateTime<Utc>,
+        f: impl FnOnce(&mut SourceMetadata),
+    ) -> Result<()> {
+        let mut change = SourceMetadata::new(last_active);
+        f(&mut change);
+        self.db
+            .merge_cf(self.cf, name, bincode::serialize(&change)?)?;
         Ok(())
     }
 
-    /// Returns the names of all sources.
-    pub fn names(&self) -> Vec<Vec<u8>> {
+    /// Returns what is known about the source.
+    pub fn metadata(&self, name: &str) -> Result<Option<SourceMetadata>> {
         self.db
-            .iterator_cf(self.cf, rocksdb::IteratorMode::Start)
-            .flatten()
-            .map(|(key, _value)| key.to_vec())
-            .collect()
+            .get_cf(self.cf, name)?
+            .map(|value| bincode::deserialize(&value))
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    /// Records the timestamp of the last event of the kind from the source with
+    /// the ID, unless a later one is already recorded.
+    pub fn set_last_event(&self, id: u32, kind: &str, timestamp: i64) -> Result<()> {
+        self.db
+            .merge_cf(self.kinds, kind_key(id, kind), timestamp.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Returns the kinds of the events the source has sent, along with the
+    /// timestamp of the last event of each kind.
+    pub fn last_events(&self, name: &str) -> Result<Vec<(String, i64)>> {
+        let Some(id) = self.id(name)? else {
+            return Ok(Vec::new());
+        };
+        let prefix = kind_key(id, "");
+        let mut last_events = Vec::new();
+        for item in self.db.iterator_cf_opt(
+            self.kinds,
+            total_order_read_options(),
+            rocksdb::IteratorMode::From(&prefix, Direction::Forward),
+        ) {
+            let (key, value) = item?;
+            let Some(kind) = key.strip_prefix(prefix.as_slice()) else {
+                break;
+            };
+            last_events.push((
+                String::from_utf8_lossy(kind).into_owned(),
+                i64::from_be_bytes(value[..].try_into()?),
+            ));
+        }
+        Ok(last_events)
+    }
+
+    /// Returns whether the source has a last active time.
+    pub fn contains(&self, name: &str) -> Result<bool> {
+        Ok(self.db.get_cf(self.cf, name)?.is_some())
+    }
+
+    /// Returns the ID of the source, which stands for the source in the keys.
+    pub fn id(&self, name: &str) -> Result<Option<u32>> {
+        source_id(self.db, self.ids, name)
+    }
+
+    /// Returns the ID of the source, assigning a new one if the source has
+    /// none.
+    pub fn assign_id(&self, name: &str) -> Result<u32> {
+        assign_source_id(self.db, self.ids, name)
+    }
+
+    /// Returns the names of all sources with IDs, along with the IDs.
+    pub fn ids(&self) -> Vec<(String, u32)> {
+        source_ids(self.db, self.ids)
     }
 
     /// Returns the source list that sent the data to ingest.
     pub fn source_list(&self) -> HashSet<String> {
         self.db
-            .iterator_cf(self.cf, rocksdb::IteratorMode::Start)
+            .iterator_cf_opt(
+                self.cf,
+                total_order_read_options(),
+                rocksdb::IteratorMode::Start,
+            )
             .flatten()
             .map(|(key, _)| String::from_utf8(key.to_vec()).expect("from utf8"))
             .collect()
@@ -683,8 +1165,8 @@ pub struct StorageKeyBuilder {
 }
 
 impl StorageKeyBuilder {
-    pub fn start_key(mut self, key: &str) -> Self {
-        let start_key = key.as_bytes();
+    pub fn start_key(mut self, key: impl AsRef<[u8]>) -> Self {
+        let start_key = key.as_ref();
         self.pre_key.reserve(start_key.len() + 1);
         self.pre_key.extend_from_slice(start_key);
         self.pre_key.push(0);
@@ -706,6 +1188,12 @@ impl StorageKeyBuilder {
         self
     }
 
+    pub fn sequence(mut self, sequence: u32) -> Self {
+        self.pre_key.reserve(SEQUENCE_SIZE);
+        self.pre_key.extend_from_slice(&sequence.to_be_bytes());
+        self
+    }
+
     pub fn lower_closed_bound_end_key(mut self, time: Option<DateTime<Utc>>) -> Self {
         self.pre_key.reserve(TIMESTAMP_SIZE);
         let ns = if let Some(time) = time {
@@ -729,17 +1217,19 @@ impl StorageKeyBuilder {
     }
 
     pub fn upper_closed_bound_end_key(mut self, time: Option<DateTime<Utc>>) -> Self {
-        self.pre_key.reserve(TIMESTAMP_SIZE);
+        self.pre_key.reserve(TIMESTAMP_SIZE + SEQUENCE_SIZE);
         if let Some(time) = time {
             let ns = time.timestamp_nanos_opt().unwrap_or(i64::MAX);
             if let Some(ns) = ns.checked_sub(1) {
                 if ns >= 0 {
                     self.pre_key.extend_from_slice(&ns.to_be_bytes());
+                    self.pre_key.extend_from_slice(&u32::MAX.to_be_bytes());
                     return self;
                 }
             }
         }
         self.pre_key.extend_from_slice(&i64::MAX.to_be_bytes());
+        self.pre_key.extend_from_slice(&u32::MAX.to_be_bytes());
         self
     }
 
@@ -748,6 +1238,147 @@ impl StorageKeyBuilder {
     }
 }
 
+fn kind_key(id: u32, kind: &str) -> Vec<u8> {
+    let mut key = encode_source_id(id);
+    key.push(0x00);
+    key.extend_from_slice(kind.as_bytes());
+    key
+}
+
+/// Takes the latest of the timestamps, which is the merge operator of the last
+/// events of the source kinds, so that the last event never goes back in time.
+fn merge_last_events(
+    _key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    existing
+        .into_iter()
+        .chain(operands)
+        .filter_map(|value| Some(i64::from_be_bytes(value.try_into().ok()?)))
+        .max()
+        .map(|timestamp| timestamp.to_be_bytes().to_vec())
+}
+
+/// Merges the records of a source, which is the merge operator of the sources.
+fn merge_source_metadata(
+    _key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    let merged = existing
+        .into_iter()
+        .chain(operands)
+        .filter_map(|value| bincode::deserialize::<SourceMetadata>(value).ok())
+        .reduce(SourceMetadata::merge)?;
+    bincode::serialize(&merged).ok()
+}
+
+/// Encodes the source ID for the keys.
+///
+/// The ID is written in base 255 with the digits from 1 to 255, so that the
+/// encoded ID never contains the separator of the parts of a key.
+#[must_use]
+pub fn encode_source_id(id: u32) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(4);
+    let mut id = u64::from(id);
+    loop {
+        encoded.push(u8::try_from(id % 255).expect("less than 255") + 1);
+        id /= 255;
+        if id == 0 {
+            break;
+        }
+    }
+    encoded.reverse();
+    encoded
+}
+
+/// Decodes the source ID encoded by `encode_source_id`.
+#[must_use]
+pub fn decode_source_id(encoded: &[u8]) -> Option<u32> {
+    // Only zero is encoded with the leading digit 0.
+    if encoded.is_empty() || (encoded.len() > 1 && encoded[0] == 1) {
+        return None;
+    }
+    let mut id = 0_u32;
+    for digit in encoded {
+        let digit = digit.checked_sub(1)?;
+        id = id.checked_mul(255)?.checked_add(u32::from(digit))?;
+    }
+    Some(id)
+}
+
+fn source_id(db: &DB, ids: &ColumnFamily, name: &str) -> Result<Option<u32>> {
+    Ok(db
+        .get_cf(ids, name)?
+        .and_then(|value| Some(u32::from_be_bytes(value[..].try_into().ok()?))))
+}
+
+fn assign_source_id(db: &DB, ids: &ColumnFamily, name: &str) -> Result<u32> {
+    if let Some(id) = source_id(db, ids, name)? {
+        return Ok(id);
+    }
+    let _lock = SOURCE_ID_LOCK.lock().expect("not poisoned");
+    if let Some(id) = source_id(db, ids, name)? {
+        return Ok(id);
+    }
+    let id = if let Some(next_id) = db.get_cf(ids, NEXT_SOURCE_ID_KEY)? {
+        u32::from_be_bytes(
+            next_id
+                .as_slice()
+                .try_into()
+                .context("invalid next source ID")?,
+        )
+    } else {
+        // The sources assigned IDs before the next ID was kept.
+        source_ids(db, ids)
+            .into_iter()
+            .map(|(_, id)| id + 1)
+            .max()
+            .unwrap_or_default()
+    };
+    let next_id = id.checked_add(1).context("no source ID left")?;
+    let mut batch = WriteBatch::default();
+    batch.put_cf(ids, name, id.to_be_bytes());
+    batch.put_cf(ids, NEXT_SOURCE_ID_KEY, next_id.to_be_bytes());
+    db.write(batch)?;
+    info!("Source {name} is assigned ID {id}");
+    Ok(id)
+}
+
+fn source_ids(db: &DB, ids: &ColumnFamily) -> Vec<(String, u32)> {
+    db.iterator_cf_opt(
+        ids,
+        total_order_read_options(),
+        rocksdb::IteratorMode::Start,
+    )
+    .flatten()
+    .filter(|(key, _)| key.as_ref() != NEXT_SOURCE_ID_KEY)
+    .filter_map(|(key, value)| {
+        Some((
+            String::from_utf8_lossy(&key).into_owned(),
+            u32::from_be_bytes(value[..].try_into().ok()?),
+        ))
+    })
+    .collect()
+}
+
+/// Returns the timestamp of the given key, which ends with the timestamp and
+/// the sequence number.
+///
+/// # Errors
+///
+/// Returns an error if the key is too short.
+pub fn timestamp_from_key(key: &[u8]) -> Result<i64> {
+    if key.len() > TIMESTAMP_SIZE + SEQUENCE_SIZE {
+        let end = key.len() - SEQUENCE_SIZE;
+        return Ok(i64::from_be_bytes(
+            key[(end - TIMESTAMP_SIZE)..end].try_into()?,
+        ));
+    }
+    Err(anyhow!("invalid database key length"))
+}
+
 pub type KeyValue<T> = (Box<[u8]>, T);
 pub type RawValue = (Box<[u8]>, Box<[u8]>);
 
@@ -865,11 +1496,72 @@ impl<'d> Iterator for Iter<'d> {
     }
 }
 
+/// Retention periods of each record type.
+///
+/// A record type without a matching policy is kept for the default retention
+/// period.
+#[derive(Clone, Debug)]
+pub struct RetentionPolicies {
+    default: Duration,
+    policies: Vec<RetentionPolicy>,
+}
+
+impl RetentionPolicies {
+    pub fn new(default: Duration, policies: Vec<RetentionPolicy>) -> Self {
+        for policy in &policies {
+            if !RAW_DATA_COLUMN_FAMILY_NAMES.contains(&policy.record_type.as_str()) {
+                warn!(
+                    "Unknown record type in retention policy: {}",
+                    policy.record_type
+                );
+            }
+        }
+        Self { default, policies }
+    }
+
+    /// Returns the retention period of the given record type and source.
+    ///
+    /// A policy for the source takes precedence over a policy for the whole
+    /// record type.
+    fn period(&self, record_type: &str, source: &[u8]) -> Duration {
+        let mut period = self.default;
+        for policy in self
+            .policies
+            .iter()
+            .filter(|policy| policy.record_type == record_type)
+        {
+            match &policy.source {
+                Some(name) if name.as_bytes() == source => return policy.retention,
+                Some(_) => {}
+                None => period = policy.retention,
+            }
+        }
+        period
+    }
+
+    /// Returns the longest retention period among all policies.
+    fn longest(&self) -> Duration {
+        self.policies
+            .iter()
+            .map(|policy| policy.retention)
+            .fold(self.default, Duration::max)
+    }
+
+    /// Returns the timestamp before which the data of the given record type
+    /// and source should be deleted.
+    fn retention_timestamp(&self, record_type: &str, source: &[u8], now: i64) -> Result<i64> {
+        let period = i64::try_from(self.period(record_type, source).as_nanos())?;
+        Ok(now.saturating_sub(period))
+    }
+}
+
 #[allow(clippy::too_many_lines)]
 pub async fn retain_periodically(
     interval: Duration,
-    retention_period: Duration,
+    retention_policies: RetentionPolicies,
     db: Database,
+    storage_paths: Vec<PathBuf>,
+    mut archiver: Option<Archiver>,
     notify_shutdown: Arc<Notify>,
     running_flag: Arc<Mutex<bool>>,
 ) -> Result<()> {
@@ -877,83 +1569,98 @@ pub async fn retain_periodically(
     const ONE_DAY_TIMESTAMP_NANOS: i64 = 86_400_000_000_000;
 
     let mut itv = time::interval(interval);
-    let retention_duration = i64::try_from(retention_period.as_nanos())?;
-    let from_timestamp = DEFAULT_FROM_TIMESTAMP_NANOS.to_be_bytes();
+    let longest_retention = i64::try_from(retention_policies.longest().as_nanos())?;
     loop {
         select! {
             _ = itv.tick() => {
                 info!("Begin to cleanup the database.");
+                let started = Instant::now();
                 {
                     let mut running_flag = running_flag.lock().unwrap();
                     *running_flag = true;
                 }
-                let now = Utc::now();
-                let mut retention_timestamp = now
-                    .timestamp_nanos_opt()
-                    .unwrap_or(retention_duration)
-                    - retention_duration;
+                let now = Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX);
+                // Shifts every retention timestamp forward while the disk usage is high.
+                let mut usage_offset = 0;
                 let mut usage_flag = false;
 
-                if check_db_usage().await.0 {
+                if check_db_usage(&storage_paths).await.0 {
                     info!("Disk usage is over {USAGE_THRESHOLD}%.");
-                    retention_timestamp += ONE_DAY_TIMESTAMP_NANOS;
+                    usage_offset += ONE_DAY_TIMESTAMP_NANOS;
                     usage_flag = true;
                 }
 
                 loop {
-                    let retention_timestamp_vec = retention_timestamp.to_be_bytes();
-                    let sources = db.sources_store()?.names();
+                    let sources = db.sources_store()?.ids();
                     let all_store = db.retain_period_store()?;
+                    let holds = db.legal_hold_store()?.list()?;
+
+                    for (source_name, id) in sources {
+                        let source = source_name.as_bytes();
+                        let mut source_prefix = encode_source_id(id);
+                        source_prefix.push(0x00);
+
+                        for (cf_name, store) in &all_store.standard_cfs {
+                            let retention_timestamp = retention_policies
+                                .retention_timestamp(cf_name, source, now)?
+                                + usage_offset;
+
+                            delete_expired(
+                                store,
+                                archiver.as_mut(),
+                                &holds,
+                                cf_name,
+                                &source_name,
+                                &source_prefix,
+                                DEFAULT_FROM_TIMESTAMP_NANOS,
+                                retention_timestamp,
+                            )?;
+                        }
 
-                    for source in sources {
-                        let mut from: Vec<u8> = source.clone();
-                        from.push(0x00);
-                        from.extend_from_slice(&from_timestamp);
-
-                        let mut to: Vec<u8> = source.clone();
-                        to.push(0x00);
-                        to.extend_from_slice(&retention_timestamp_vec);
-
-                        for store in &all_store.standard_cfs {
-                            store.flush()?;
-                            if store
-                                .db
-                                .delete_file_in_range_cf(store.cf, &from, &to)
-                                .is_ok()
-                            {
-                                store.flush()?;
-                                if store.db.delete_range_cf(store.cf, &from, &to).is_ok() {
-                                    store.db.compact_range_cf(store.cf, Some(&from), Some(&to));
-                                }
-                            } else {
-                                error!("Failed to delete file in range");
+                        for (cf_name, store) in &all_store.non_standard_cfs {
+                            let retention_timestamp = retention_policies
+                                .retention_timestamp(cf_name, source, now)?
+                                + usage_offset;
+
+                            for key_prefix in store.key_prefixes(&source_prefix) {
+                                delete_expired(
+                                    store,
+                                    archiver.as_mut(),
+                                    &holds,
+                                    cf_name,
+                                    &source_name,
+                                    &key_prefix,
+                                    DEFAULT_FROM_TIMESTAMP_NANOS,
+                                    retention_timestamp,
+                                )?;
                             }
                         }
+                    }
 
-                        for store in &all_store.non_standard_cfs {
-                            let iterator = store
-                                .db
-                                .prefix_iterator_cf(store.cf, source.clone())
-                                .flatten();
-
-                            for (key, _) in iterator {
-                                let data_timestamp =
-                                    i64::from_be_bytes(key[(key.len() - TIMESTAMP_SIZE)..].try_into()?);
-
-                                if retention_timestamp > data_timestamp {
-                                    if store.delete(&key).is_err() {
-                                        error!("Failed to delete data");
-                                    }
-                                } else {
-                                    break;
-                                }
-                            }
-                            store.flush()?;
+                    // The policies and the holds of these column families are
+                    // looked up with the id in place of the source.
+                    for (cf_name, store) in &all_store.sourceless_cfs {
+                        for key_prefix in store.key_prefixes(&[]) {
+                            let id = key_prefix.strip_suffix(&[0x00]).unwrap_or(&key_prefix);
+                            let retention_timestamp = retention_policies
+                                .retention_timestamp(cf_name, id, now)?
+                                + usage_offset;
+
+                            delete_expired(
+                                store,
+                                archiver.as_mut(),
+                                &holds,
+                                cf_name,
+                                &String::from_utf8_lossy(id),
+                                &key_prefix,
+                                DEFAULT_FROM_TIMESTAMP_NANOS,
+                                retention_timestamp,
+                            )?;
                         }
                     }
-                    if check_db_usage().await.1 && usage_flag {
-                        retention_timestamp += ONE_DAY_TIMESTAMP_NANOS;
-                        if retention_timestamp > now.timestamp_nanos_opt().unwrap_or(0) {
+                    if check_db_usage(&storage_paths).await.1 && usage_flag {
+                        usage_offset += ONE_DAY_TIMESTAMP_NANOS;
+                        if usage_offset > longest_retention {
                             warn!("cannot delete data to usage under {USAGE_LOW}");
                             break;
                         }
@@ -965,6 +1672,7 @@ pub async fn retain_periodically(
                     }
                 }
                 info!("Database cleanup completed.");
+                crate::metrics::record_retention(started.elapsed());
                 {
                     let mut running_flag = running_flag.lock().unwrap();
                     *running_flag = false;
@@ -977,15 +1685,148 @@ pub async fn retain_periodically(
     }
 }
 
+/// Deletes the keys starting with `prefix`, followed by a timestamp in
+/// [`from`, `to`), except those held by `holds`.
+#[allow(clippy::too_many_arguments)]
+fn delete_expired(
+    store: &RawEventStore<'_, ()>,
+    mut archiver: Option<&mut Archiver>,
+    holds: &[LegalHold],
+    cf_name: &str,
+    source: &str,
+    prefix: &[u8],
+    from: i64,
+    to: i64,
+) -> Result<()> {
+    for (start, end) in unheld_ranges(holds, cf_name, source, from, to) {
+        let mut from = prefix.to_vec();
+        from.extend_from_slice(&start.to_be_bytes());
+        let mut to = prefix.to_vec();
+        to.extend_from_slice(&end.to_be_bytes());
+
+        // The range is deleted again in the next round if it fails now.
+        if let Err(e) =
+            archive_and_delete_range(store, archiver.as_deref_mut(), cf_name, source, &from, &to)
+        {
+            error!("Failed to delete {cf_name} of {source}: {e:#}");
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the keys in the range [`from`, `to`), archiving them first if
+/// `archiver` is given.
+///
+/// The range is kept if it cannot be archived, so that no data is lost. The
+/// events are archived as of a snapshot, and only the keys in the snapshot are
+/// deleted, so that the events written into the range while archiving are
+/// kept.
+fn archive_and_delete_range(
+    store: &RawEventStore<'_, ()>,
+    archiver: Option<&mut Archiver>,
+    cf_name: &str,
+    source: &str,
+    from: &[u8],
+    to: &[u8],
+) -> Result<()> {
+    let Some(archiver) = archiver else {
+        return store.delete_range(from, to);
+    };
+    let snapshot = store.db.snapshot();
+    let events = store.snapshot_range_iter(&snapshot, from, to);
+    match archiver.archive(cf_name, source, events) {
+        Ok(_) => delete_snapshot_range(store, &snapshot, from, to),
+        Err(e) => {
+            error!("Failed to archive {cf_name} of {source}, which is not deleted: {e:#}");
+            Ok(())
+        }
+    }
+}
+
+/// Deletes the keys in the range [`from`, `to`) as of `snapshot`, in batches
+/// of `DELETE_BATCH_SIZE`, and compacts the range if any is deleted.
+fn delete_snapshot_range(
+    store: &RawEventStore<'_, ()>,
+    snapshot: &Snapshot<'_>,
+    from: &[u8],
+    to: &[u8],
+) -> Result<()> {
+    const DELETE_BATCH_SIZE: usize = 10_000;
+
+    let mut batch = WriteBatch::default();
+    let mut deleted = false;
+    for event in store.snapshot_range_iter(snapshot, from, to) {
+        let (key, _) = event?;
+        batch.delete_cf(store.cf, key);
+        deleted = true;
+        if batch.len() == DELETE_BATCH_SIZE {
+            store
+                .db
+                .write(std::mem::take(&mut batch))
+                .context("cannot delete archived keys")?;
+        }
+    }
+    store
+        .db
+        .write(batch)
+        .context("cannot delete archived keys")?;
+    if deleted {
+        store.db.compact_range_cf(store.cf, Some(from), Some(to));
+    }
+    Ok(())
+}
+
 /// Returns the boolean of the disk usages over `USAGE_THRESHOLD` and `USAGE_LOW`.
-async fn check_db_usage() -> (bool, bool) {
-    let resource_usage = roxy::resource_usage().await;
-    let usage = (resource_usage.used_disk_space * 100) / resource_usage.total_disk_space;
+///
+/// The usage is the highest one among the file systems of `storage_paths`, or
+/// that of the whole system if none of them is found.
+async fn check_db_usage(storage_paths: &[PathBuf]) -> (bool, bool) {
+    let usage = if let Some(usage) = max_disk_usage(storage_paths) {
+        usage
+    } else {
+        let resource_usage = roxy::resource_usage().await;
+        (resource_usage.used_disk_space * 100) / resource_usage.total_disk_space
+    };
     debug!("Disk usage: {usage}%");
     (usage > USAGE_THRESHOLD, usage > USAGE_LOW)
 }
 
-pub(crate) fn rocksdb_options(db_options: &DbOptions) -> (Options, Options) {
+/// Returns the highest usage in percent among the file systems of the paths.
+fn max_disk_usage(paths: &[PathBuf]) -> Option<u64> {
+    let mut system = System::new();
+    system.refresh_disks_list();
+    let mut max_usage = None;
+    for path in paths {
+        let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+        // The file system of the path is the one mounted at its nearest
+        // ancestor.
+        let Some(disk) = system
+            .disks()
+            .iter()
+            .filter(|disk| path.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().components().count())
+        else {
+            warn!("Cannot find the file system of {}", path.display());
+            continue;
+        };
+        if disk.total_space() == 0 {
+            continue;
+        }
+        let used = disk.total_space().saturating_sub(disk.available_space());
+        let usage = used * 100 / disk.total_space();
+        debug!("Disk usage of {}: {usage}%", path.display());
+        max_usage = max_usage.max(Some(usage));
+    }
+    max_usage
+}
+
+/// Returns the options of the database and the default options of the column
+/// families.
+///
+/// # Errors
+///
+/// Returns an error if a storage tier has an invalid path.
+pub(crate) fn rocksdb_options(db_options: &DbOptions) -> Result<(Options, Options)> {
     let max_bytes = db_options.max_mb_of_level_base * 1024 * 1024;
     let mut db_opts = Options::default();
     db_opts.create_if_missing(true);
@@ -1007,5 +1848,535 @@ pub(crate) fn rocksdb_options(db_options: &DbOptions) -> (Options, Options) {
     cf_opts.set_bottommost_compression_type(rocksdb::DBCompressionType::Zstd);
     cf_opts.set_bottommost_zstd_max_train_bytes(0, true);
 
-    (db_opts, cf_opts)
+    if !db_options.storage_tiers.is_empty() {
+        db_opts.set_db_paths(&db_paths(&db_options.storage_tiers)?);
+    }
+
+    Ok((db_opts, cf_opts))
+}
+
+fn db_paths(tiers: &[StorageTier]) -> Result<Vec<DBPath>> {
+    tiers
+        .iter()
+        .map(|tier| {
+            DBPath::new(&tier.path, tier.target_size)
+                .with_context(|| format!("invalid storage tier: {}", tier.path.display()))
+        })
+        .collect()
+}
+
+/// Returns the descriptors of all the column families, whose options are
+/// `cf_opts` overridden by the options of each column family in `db_options`.
+fn column_family_descriptors(
+    db_options: &DbOptions,
+    cf_opts: &Options,
+) -> Result<Vec<ColumnFamilyDescriptor>> {
+    for cf_options in &db_options.cf_options {
+        if !column_family_names().any(|name| name == cf_options.name) {
+            warn!("Unknown column family in cf_options: {}", cf_options.name);
+        }
+    }
+    column_family_names()
+        .map(|name| -> Result<ColumnFamilyDescriptor> {
+            let mut opts = cf_opts.clone();
+            if let Some(cf_options) = db_options.cf_options.iter().find(|o| o.name == name) {
+                apply_cf_options(&mut opts, cf_options)?;
+            }
+            if name == INGEST_COUNTERS_COLUMN_FAMILY_NAME {
+                opts.set_merge_operator_associative("ingest counts", merge_counts);
+            }
+            if name == "sources" {
+                opts.set_merge_operator_associative("source metadata", merge_source_metadata);
+            }
+            if name == SOURCE_KINDS_COLUMN_FAMILY_NAME {
+                opts.set_merge_operator_associative("last events", merge_last_events);
+            }
+            Ok(ColumnFamilyDescriptor::new(name, opts))
+        })
+        .collect()
+}
+
+fn apply_cf_options(opts: &mut Options, cf_options: &CfOptions) -> Result<()> {
+    if let Some(compression) = cf_options.compression {
+        opts.set_compression_type(compression_type(compression));
+    }
+    if let Some(compression) = cf_options.bottommost_compression {
+        opts.set_bottommost_compression_type(compression_type(compression));
+    }
+    if let Some(size) = cf_options.write_buffer_size {
+        opts.set_write_buffer_size(size.try_into().expect("u64 to usize"));
+    }
+    if !cf_options.storage_tiers.is_empty() {
+        opts.set_cf_paths(&db_paths(&cf_options.storage_tiers)?);
+    }
+    if cf_options.block_size.is_none() && !cf_options.source_bloom_filter {
+        return Ok(());
+    }
+    let mut block_opts = BlockBasedOptions::default();
+    if let Some(size) = cf_options.block_size {
+        block_opts.set_block_size(size.try_into().expect("u64 to usize"));
+    }
+    if cf_options.source_bloom_filter {
+        // The whole keys are still added to the filter for point lookups.
+        block_opts.set_bloom_filter(SOURCE_BLOOM_FILTER_BITS_PER_KEY, false);
+        opts.set_prefix_extractor(SliceTransform::create(
+            "source",
+            source_or_key,
+            Some(has_source),
+        ));
+    }
+    opts.set_block_based_table_factory(&block_opts);
+    Ok(())
+}
+
+fn compression_type(compression: Compression) -> DBCompressionType {
+    match compression {
+        Compression::None => DBCompressionType::None,
+        Compression::Snappy => DBCompressionType::Snappy,
+        Compression::Zlib => DBCompressionType::Zlib,
+        Compression::Lz4 => DBCompressionType::Lz4,
+        Compression::Lz4hc => DBCompressionType::Lz4hc,
+        Compression::Zstd => DBCompressionType::Zstd,
+    }
+}
+
+/// Returns the source of the key, including the separator that follows it.
+fn source_prefix(key: &[u8]) -> Option<&[u8]> {
+    key.iter().position(|b| *b == 0).map(|pos| &key[..=pos])
+}
+
+fn source_or_key(key: &[u8]) -> &[u8] {
+    source_prefix(key).unwrap_or(key)
+}
+
+fn has_source(key: &[u8]) -> bool {
+    source_prefix(key).is_some()
+}
+
+// Reads the keys of the source of the first key only. The SST files without
+// the source are skipped if the column family has `source_bloom_filter`.
+fn source_read_options() -> ReadOptions {
+    let mut read_options = ReadOptions::default();
+    read_options.set_prefix_same_as_start(true);
+    read_options
+}
+
+// Reads the keys in the range [`from`, `to`), with the prefix of the source if
+// the range is within a source.
+fn range_read_options(from: &[u8], to: &[u8]) -> ReadOptions {
+    let mut read_options = match (source_prefix(from), source_prefix(to)) {
+        (Some(from_source), Some(to_source)) if from_source == to_source => source_read_options(),
+        _ => total_order_read_options(),
+    };
+    read_options.set_iterate_upper_bound(to);
+    read_options
+}
+
+// Reads the keys regardless of their sources, which is required to iterate
+// over multiple sources of a column family with `source_bloom_filter`.
+fn total_order_read_options() -> ReadOptions {
+    let mut read_options = ReadOptions::default();
+    read_options.set_total_order_seek(true);
+    read_options
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::Path};
+
+    use chrono::Utc;
+
+    use super::{decode_source_id, encode_source_id, Database, DbOptions, Direction, StorageKey};
+    use crate::settings::{CfOptions, Compression, StorageTier};
+
+    #[test]
+    fn source_bloom_filter() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db_options = DbOptions {
+            cf_options: vec![CfOptions {
+                name: "conn".to_string(),
+                compression: Some(Compression::Zstd),
+                block_size: Some(4096),
+                source_bloom_filter: true,
+                ..CfOptions::default()
+            }],
+            ..DbOptions::default()
+        };
+        let db = Database::open(db_dir.path(), &db_options).unwrap();
+        let store = db.conn_store().unwrap();
+
+        for source in ["src1", "src2", "src3"] {
+            for timestamp in 0..3 {
+                let key = StorageKey::builder()
+                    .start_key(source)
+                    .end_key(timestamp)
+                    .sequence(0)
+                    .build()
+                    .key();
+                store.append(&key, b"value").unwrap();
+            }
+            // Puts each source in its own SST file.
+            db.db.flush_cf(db.get_cf_handle("conn").unwrap()).unwrap();
+        }
+
+        let key_builder = StorageKey::builder().start_key("src2");
+        let from = key_builder.clone().lower_closed_bound_end_key(None).build();
+        let to = key_builder.upper_open_bound_end_key(None).build();
+        let keys = store
+            .boundary_iter(&from.key(), &to.key(), Direction::Forward)
+            .count();
+        assert_eq!(keys, 3);
+        assert_eq!(store.key_prefixes(b"src2\x00"), vec![b"src2\x00".to_vec()]);
+        assert!(store.key_prefixes(b"src4\x00").is_empty());
+
+        // Iterating over all the sources is not limited by the filter.
+        assert_eq!(store.key_prefixes(&[]).len(), 3);
+        assert_eq!(store.iter_forward().count(), 9);
+    }
+
+    #[test]
+    fn storage_tiers() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let hot_dir = tempfile::tempdir().unwrap();
+        let cold_dir = tempfile::tempdir().unwrap();
+        let db_options = DbOptions {
+            storage_tiers: vec![
+                StorageTier {
+                    path: hot_dir.path().to_path_buf(),
+                    target_size: 1 << 30,
+                },
+                StorageTier {
+                    path: cold_dir.path().to_path_buf(),
+                    target_size: 1 << 40,
+                },
+            ],
+            ..DbOptions::default()
+        };
+        assert_eq!(
+            db_options.storage_paths(db_dir.path()),
+            vec![
+                db_dir.path().to_path_buf(),
+                hot_dir.path().to_path_buf(),
+                cold_dir.path().to_path_buf()
+            ]
+        );
+
+        let db = Database::open(db_dir.path(), &db_options).unwrap();
+        let store = db.conn_store().unwrap();
+        store.append(b"src1\x00key", b"value").unwrap();
+        db.db.flush_cf(db.get_cf_handle("conn").unwrap()).unwrap();
+
+        let has_sst = |dir: &Path| {
+            std::fs::read_dir(dir)
+                .unwrap()
+                .any(|entry| entry.unwrap().path().extension() == Some("sst".as_ref()))
+        };
+        assert!(has_sst(hot_dir.path()));
+        assert!(!has_sst(cold_dir.path()));
+        assert!(!has_sst(db_dir.path()));
+    }
+
+    #[test]
+    fn source_id_encoding() {
+        for id in [0, 1, 254, 255, 256, 65_024, 65_025, u32::MAX] {
+            let encoded = encode_source_id(id);
+            assert!(!encoded.contains(&0));
+            assert_eq!(decode_source_id(&encoded), Some(id));
+        }
+        assert_eq!(encode_source_id(0), vec![1]);
+        assert_eq!(encode_source_id(255), vec![2, 1]);
+        assert_eq!(decode_source_id(&[]), None);
+        assert_eq!(decode_source_id(&[0]), None);
+        assert_eq!(decode_source_id(&[1, 2]), None);
+    }
+
+    #[test]
+    fn source_ids() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let sources = db.sources_store().unwrap();
+        assert_eq!(sources.id("src1").unwrap(), None);
+        assert_eq!(sources.assign_id("src1").unwrap(), 0);
+        assert_eq!(sources.assign_id("src2").unwrap(), 1);
+        assert_eq!(sources.assign_id("src1").unwrap(), 0);
+        assert_eq!(sources.id("src2").unwrap(), Some(1));
+
+        let store = db.conn_store().unwrap();
+        assert_eq!(store.source_key("src2"), encode_source_id(1));
+        assert!(store.source_key("src3").is_empty());
+        assert_eq!(
+            store.assign_source_key("src3").unwrap(),
+            encode_source_id(2)
+        );
+
+        let mut ids = sources.ids();
+        ids.sort_unstable();
+        assert_eq!(
+            ids,
+            vec![
+                ("src1".to_string(), 0),
+                ("src2".to_string(), 1),
+                ("src3".to_string(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn source_metadata() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let sources = db.sources_store().unwrap();
+        let first_seen = Utc::now();
+        let connected = first_seen + chrono::Duration::seconds(1);
+        let disconnected = first_seen + chrono::Duration::seconds(2);
+
+        sources.insert("src1", first_seen).unwrap();
+        sources
+            .connect("src1", "agent1", "0.22.0", connected)
+            .unwrap();
+        sources
+            .connect("src1", "agent2", "0.22.1", connected)
+            .unwrap();
+        sources.disconnect("src1", disconnected).unwrap();
+        // Inserted again, by the daily check of the connected sources.
+        sources.insert("src1", connected).unwrap();
+        let metadata = sources.metadata("src1").unwrap().unwrap();
+        assert_eq!(metadata.first_seen, first_seen);
+        assert_eq!(metadata.last_active, disconnected);
+        assert_eq!(metadata.last_connected, Some(connected));
+        assert_eq!(metadata.last_disconnected, Some(disconnected));
+        assert_eq!(metadata.protocol_version.as_deref(), Some("0.22.1"));
+        assert_eq!(
+            metadata.agents.into_iter().collect::<Vec<_>>(),
+            vec!["agent1".to_string(), "agent2".to_string()]
+        );
+        assert_eq!(sources.metadata("src2").unwrap(), None);
+
+        let src1 = sources.assign_id("src1").unwrap();
+        let src2 = sources.assign_id("src2").unwrap();
+        sources.set_last_event(src1, "dns", 2).unwrap();
+        sources.set_last_event(src1, "conn", 1).unwrap();
+        sources.set_last_event(src1, "conn", 3).unwrap();
+        // An earlier event, from another stream for example, is ignored.
+        sources.set_last_event(src1, "dns", 1).unwrap();
+        sources.set_last_event(src2, "log", 4).unwrap();
+        assert_eq!(
+            sources.last_events("src1").unwrap(),
+            vec![("conn".to_string(), 3), ("dns".to_string(), 2)]
+        );
+        assert!(sources.last_events("src3").unwrap().is_empty());
+    }
+
+    #[test]
+    fn quarantine_batch() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.dns_store().unwrap();
+        store
+            .append_unique([], [(b"key1".as_slice(), b"event1".as_slice())], [])
+            .unwrap();
+
+        assert!(store
+            .db
+            .get_cf(store.cf, b"key1\0\0\0\0")
+            .unwrap()
+            .is_none());
+        let quarantine = db.get_cf_handle("quarantine").unwrap();
+        assert_eq!(
+            db.db.get_cf(quarantine, b"dns\x00key1\0\0\0\0").unwrap(),
+            Some(b"event1".to_vec())
+        );
+    }
+
+    #[test]
+    fn dead_letter_batch() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.dns_store().unwrap();
+        let dead_letters = db.get_cf_handle("dead letters").unwrap();
+        db.db
+            .put_cf(dead_letters, b"key1\0\0\0\0", b"stored")
+            .unwrap();
+        store
+            .append_unique(
+                [(b"key1".as_slice(), b"event1".as_slice())],
+                [],
+                [
+                    (b"key1".as_slice(), b"letter1".as_slice()),
+                    (b"key1".as_slice(), b"letter2".as_slice()),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(
+            store.db.get_cf(store.cf, b"key1\0\0\0\0").unwrap(),
+            Some(b"event1".to_vec())
+        );
+        assert_eq!(
+            db.db.get_cf(dead_letters, b"key1\0\0\0\0").unwrap(),
+            Some(b"stored".to_vec())
+        );
+        assert_eq!(
+            db.db.get_cf(dead_letters, b"key1\0\0\0\x01").unwrap(),
+            Some(b"letter1".to_vec())
+        );
+        assert_eq!(
+            db.db.get_cf(dead_letters, b"key1\0\0\0\x02").unwrap(),
+            Some(b"letter2".to_vec())
+        );
+    }
+
+    #[test]
+    fn append_unique() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let prefix = |timestamp: i64| {
+            StorageKey::builder()
+                .start_key("src1")
+                .end_key(timestamp)
+                .build()
+                .key()
+        };
+        let (a, b) = (prefix(1), prefix(2));
+
+        // Two streams of the same source and kind, each sending duplicates out
+        // of order, with the same timestamps as the other.
+        std::thread::scope(|scope| {
+            for stream in 0..2_u8 {
+                let db = &db;
+                let (a, b) = (&a, &b);
+                scope.spawn(move || {
+                    let store = db.conn_store().unwrap();
+                    for frame in 0..50_u8 {
+                        let events = [[stream, frame, 0], [stream, frame, 1], [stream, frame, 2]];
+                        store
+                            .append_unique(
+                                [
+                                    (a.as_slice(), events[0].as_slice()),
+                                    (b.as_slice(), events[1].as_slice()),
+                                    (a.as_slice(), events[2].as_slice()),
+                                ],
+                                [],
+                                [],
+                            )
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        let store = db.conn_store().unwrap();
+        let cf = db.get_cf_handle("conn").unwrap();
+        let stored: Vec<_> = db
+            .db
+            .iterator_cf(cf, rocksdb::IteratorMode::Start)
+            .map(|item| item.unwrap())
+            .collect();
+        assert_eq!(stored.len(), 300);
+        let values: HashSet<_> = stored.iter().map(|(_, value)| value.to_vec()).collect();
+        assert_eq!(values.len(), 300);
+        let a_keys = stored.iter().filter(|(key, _)| key.starts_with(&a)).count();
+        assert_eq!(a_keys, 200);
+
+        // The first event with a prefix still gets sequence 0.
+        store
+            .append_unique([(prefix(3).as_slice(), b"c".as_slice())], [], [])
+            .unwrap();
+        let mut key = prefix(3);
+        key.extend_from_slice(&0_u32.to_be_bytes());
+        assert_eq!(db.db.get_cf(cf, key).unwrap(), Some(b"c".to_vec()));
+    }
+
+    #[test]
+    fn append_unique_after_restart() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let prefix = |timestamp: i64| {
+            StorageKey::builder()
+                .start_key("src1")
+                .end_key(timestamp)
+                .build()
+                .key()
+        };
+        let key = |timestamp: i64, sequence: u32| {
+            let mut key = prefix(timestamp);
+            key.extend_from_slice(&sequence.to_be_bytes());
+            key
+        };
+        {
+            let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+            let store = db.conn_store().unwrap();
+            let (a, b) = (prefix(1), prefix(2));
+            store
+                .append_unique(
+                    [
+                        (a.as_slice(), b"a0".as_slice()),
+                        (b.as_slice(), b"b0".as_slice()),
+                        (b.as_slice(), b"b1".as_slice()),
+                    ],
+                    [],
+                    [],
+                )
+                .unwrap();
+        }
+
+        // The counters start from the stored keys, for the latest timestamp
+        // and for an earlier one alike.
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.conn_store().unwrap();
+        let (a, b) = (prefix(1), prefix(2));
+        store
+            .append_unique(
+                [
+                    (b.as_slice(), b"b2".as_slice()),
+                    (a.as_slice(), b"a1".as_slice()),
+                    (a.as_slice(), b"a2".as_slice()),
+                ],
+                [],
+                [],
+            )
+            .unwrap();
+        let cf = db.get_cf_handle("conn").unwrap();
+        for (key, value) in [
+            (key(1, 0), b"a0"),
+            (key(1, 1), b"a1"),
+            (key(1, 2), b"a2"),
+            (key(2, 0), b"b0"),
+            (key(2, 1), b"b1"),
+            (key(2, 2), b"b2"),
+        ] {
+            assert_eq!(db.db.get_cf(cf, key).unwrap(), Some(value.to_vec()));
+        }
+    }
+
+    #[test]
+    fn delete_snapshot_range_keeps_later_events() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let stores = db.retain_period_store().unwrap();
+        let (_, store) = stores
+            .standard_cfs
+            .iter()
+            .find(|(cf_name, _)| *cf_name == "conn")
+            .unwrap();
+        let key = |timestamp: i64| {
+            StorageKey::builder()
+                .start_key("src1")
+                .end_key(timestamp)
+                .sequence(0)
+                .build()
+                .key()
+        };
+        store.append(&key(1), b"1").unwrap();
+        store.append(&key(3), b"3").unwrap();
+        let snapshot = db.db.snapshot();
+
+        // An event written into the range after the snapshot is kept.
+        store.append(&key(2), b"2").unwrap();
+        super::delete_snapshot_range(store, &snapshot, &key(0), &key(4)).unwrap();
+        let keys: Vec<_> = store
+            .iter_forward()
+            .map(|event| event.unwrap().0.to_vec())
+            .collect();
+        assert_eq!(keys, vec![key(2)]);
+    }
 }
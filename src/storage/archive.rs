@@ -0,0 +1,497 @@
+//! Routines to archive the data before retention deletes it, and to load the
+//! archived data back into the database.
+//!
+//! The events of a source in a column family are archived per day as two
+//! gzip files: one in the format of the `export` API to be read without
+//! giganto, and one with the raw events to be imported. Each archiving appends
+//! a gzip member to the files of the day, and the manifest in the archive
+//! directory lists the files with the numbers of their events.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, IntoInnerError, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression, Crc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::{timestamp_from_key, Database, RawEventStore, RawValue};
+use crate::{graphql::export::format_raw_event, settings::ArchiveFormat};
+
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+const IMPORT_BATCH_SIZE: usize = 1024;
+
+/// The archived events of a source in a column family on a day, in UTC.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub cf: String,
+    pub source: String,
+    pub date: NaiveDate,
+    /// The events in the archive format, relative to the archive directory.
+    /// `None` if the events of the column family cannot be exported.
+    pub file: Option<PathBuf>,
+    /// The raw events, relative to the archive directory.
+    pub raw_file: PathBuf,
+    pub events: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Writes the events to be deleted by retention to the archive directory.
+pub struct Archiver {
+    dir: PathBuf,
+    format: ArchiveFormat,
+    manifest: Vec<ArchiveEntry>,
+}
+
+// The files of the day being archived.
+struct DayFiles {
+    date: NaiveDate,
+    file: PathBuf,
+    raw_file: PathBuf,
+    writer: Option<GzEncoder<File>>,
+    raw_writer: GzEncoder<BufWriter<File>>,
+    events: u64,
+}
+
+impl Archiver {
+    /// Creates an archiver writing to `dir`, which continues the manifest
+    /// already in the directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created or the manifest
+    /// cannot be read.
+    pub fn new(dir: &Path, format: ArchiveFormat) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("cannot create archive directory: {}", dir.display()))?;
+        let manifest = read_manifest(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            format,
+            manifest,
+        })
+    }
+
+    /// Archives the events of the source in the column family, and returns the
+    /// number of the archived events.
+    ///
+    /// `events` should be in the order of their keys, which start with the
+    /// source and end with the timestamp. The archived files are synced to the
+    /// disk before this returns, so the events can be deleted afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an event cannot be read or written.
+    pub fn archive(
+        &mut self,
+        cf_name: &str,
+        source: &str,
+        events: impl Iterator<Item = Result<RawValue>>,
+    ) -> Result<u64> {
+        let mut day: Option<DayFiles> = None;
+        let mut archived = 0;
+        let mut undecodable = 0;
+        for event in events {
+            let (key, value) = event?;
+            let (Some(separator), Ok(timestamp)) =
+                (key.iter().position(|b| *b == 0), timestamp_from_key(&key))
+            else {
+                warn!("Not archiving {cf_name} event with invalid key: {key:?}");
+                continue;
+            };
+            let date = DateTime::from_timestamp_nanos(timestamp).date_naive();
+            let files = match day.take() {
+                Some(files) if files.date == date => files,
+                prev => {
+                    if let Some(prev) = prev {
+                        self.finish(cf_name, source, prev)?;
+                    }
+                    self.open(cf_name, source, date)?
+                }
+            };
+            let files = day.insert(files);
+
+            bincode::serialize_into(&mut files.raw_writer, &(&key[separator..], &value[..]))?;
+            match format_raw_event(
+                cf_name,
+                self.format.export_type(),
+                source,
+                timestamp,
+                &value,
+            ) {
+                Ok(Some(line)) => {
+                    if files.writer.is_none() {
+                        files.writer = Some(open_gz(&self.dir.join(&files.file))?);
+                    }
+                    if let Some(writer) = files.writer.as_mut() {
+                        writeln!(writer, "{line}")?;
+                    }
+                }
+                Ok(None) => {}
+                Err(_) => undecodable += 1,
+            }
+            files.events += 1;
+            archived += 1;
+        }
+        if let Some(files) = day {
+            self.finish(cf_name, source, files)?;
+        }
+        if undecodable > 0 {
+            warn!("{undecodable} {cf_name} event(s) of {source} are archived only as raw events");
+        }
+        if archived > 0 {
+            write_manifest(&self.dir, &self.manifest)?;
+            info!("Archived {archived} {cf_name} event(s) of {source}");
+        }
+        Ok(archived)
+    }
+
+    fn open(&self, cf_name: &str, source: &str, date: NaiveDate) -> Result<DayFiles> {
+        let dir = Path::new(&path_component(cf_name)).join(source_dir(source));
+        let file = dir.join(format!("{date}.{}.gz", self.format.export_type()));
+        let raw_file = dir.join(format!("{date}.raw.gz"));
+        // The day already archived keeps its files, whose paths may be from
+        // before the hash was added to the directory of the source.
+        let (file, raw_file) = self
+            .manifest
+            .iter()
+            .find(|e| e.cf == cf_name && e.source == source && e.date == date)
+            .map_or((file, raw_file), |entry| {
+                let file = entry.file.clone().unwrap_or_else(|| {
+                    entry
+                        .raw_file
+                        .with_file_name(format!("{date}.{}.gz", self.format.export_type()))
+                });
+                (file, entry.raw_file.clone())
+            });
+        if let Some(dir) = raw_file.parent() {
+            fs::create_dir_all(self.dir.join(dir))?;
+        }
+        let raw_writer = GzEncoder::new(
+            BufWriter::new(append(&self.dir.join(&raw_file))?),
+            Compression::default(),
+        );
+        Ok(DayFiles {
+            date,
+            file,
+            raw_file,
+            writer: None,
+            raw_writer,
+            events: 0,
+        })
+    }
+
+    fn finish(&mut self, cf_name: &str, source: &str, files: DayFiles) -> Result<()> {
+        let raw = files
+            .raw_writer
+            .finish()?
+            .into_inner()
+            .map_err(IntoInnerError::into_error)?;
+        raw.sync_all()?;
+        let has_file = files.writer.is_some();
+        if let Some(writer) = files.writer {
+            writer.finish()?.sync_all()?;
+        }
+
+        let now = Utc::now();
+        if let Some(entry) = self
+            .manifest
+            .iter_mut()
+            .find(|e| e.cf == cf_name && e.source == source && e.date == files.date)
+        {
+            entry.events += files.events;
+            entry.updated_at = now;
+            if has_file {
+                entry.file = Some(files.file);
+            }
+        } else {
+            self.manifest.push(ArchiveEntry {
+                cf: cf_name.to_string(),
+                source: source.to_string(),
+                date: files.date,
+                file: has_file.then_some(files.file),
+                raw_file: files.raw_file,
+                events: files.events,
+                updated_at: now,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Loads the raw events in the archive at `dir` into the database, and returns
+/// the numbers of the loaded events and of the skipped ones.
+///
+/// An event whose key is already in the database is skipped, so that neither
+/// the event in the database nor one loaded before it is overwritten.
+///
+/// # Errors
+///
+/// Returns an error if the archive cannot be read or the events cannot be
+/// written.
+pub fn import_archive(db: &Database, dir: &Path) -> Result<(u64, u64)> {
+    let manifest = read_manifest(dir)?;
+    let stores = db.retain_period_store()?;
+    let mut imported = 0;
+    let mut skipped = 0;
+    for entry in &manifest {
+        let Some((_, store)) = stores
+            .standard_cfs
+            .iter()
+            .chain(stores.non_standard_cfs.iter())
+            .chain(stores.sourceless_cfs.iter())
+            .find(|(cf_name, _)| *cf_name == entry.cf)
+        else {
+            warn!("Unknown column family in the archive: {}", entry.cf);
+            continue;
+        };
+        let source_key = store.assign_source_key(&entry.source)?;
+        let path = dir.join(&entry.raw_file);
+        let file = File::open(&path)
+            .with_context(|| format!("cannot open archive file: {}", path.display()))?;
+        let mut reader = BufReader::new(MultiGzDecoder::new(file));
+
+        let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+        let mut entry_skipped = 0;
+        loop {
+            let (key_suffix, value): (Vec<u8>, Vec<u8>) =
+                match bincode::deserialize_from(&mut reader) {
+                    Ok(event) => event,
+                    Err(e) if is_eof(&e) => break,
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("invalid archive file: {}", path.display()))
+                    }
+                };
+            let mut key = source_key.clone();
+            key.extend_from_slice(&key_suffix);
+            batch.push((key, value));
+            if batch.len() == IMPORT_BATCH_SIZE {
+                let (loaded, existing) = append_new(store, &batch)?;
+                imported += loaded;
+                entry_skipped += existing;
+                batch.clear();
+            }
+        }
+        let (loaded, existing) = append_new(store, &batch)?;
+        imported += loaded;
+        entry_skipped += existing;
+        if entry_skipped > 0 {
+            warn!(
+                "Skipped {entry_skipped} {} event(s) of {} on {} already in the database",
+                entry.cf, entry.source, entry.date
+            );
+        }
+        skipped += entry_skipped;
+    }
+    Ok((imported, skipped))
+}
+
+// Appends the events whose keys are neither in the database nor earlier in
+// `events`, and returns the numbers of the appended and the skipped events.
+fn append_new(store: &RawEventStore<'_, ()>, events: &[(Vec<u8>, Vec<u8>)]) -> Result<(u64, u64)> {
+    let existing = store
+        .db
+        .multi_get_cf(events.iter().map(|(key, _)| (store.cf, key)));
+    let mut keys = HashSet::with_capacity(events.len());
+    let mut new_events = Vec::with_capacity(events.len());
+    for ((key, value), stored) in events.iter().zip(existing) {
+        if stored?.is_none() && keys.insert(key.as_slice()) {
+            new_events.push((key.as_slice(), value.as_slice()));
+        }
+    }
+    let appended = new_events.len();
+    store.append_batch(new_events)?;
+    Ok((appended as u64, (events.len() - appended) as u64))
+}
+
+fn is_eof(e: &bincode::Error) -> bool {
+    matches!(&**e, bincode::ErrorKind::Io(e) if e.kind() == ErrorKind::UnexpectedEof)
+}
+
+/// Reads the manifest of the archive, which is empty if the archive has none.
+///
+/// # Errors
+///
+/// Returns an error if the manifest cannot be read or parsed.
+pub fn read_manifest(dir: &Path) -> Result<Vec<ArchiveEntry>> {
+    let path = dir.join(MANIFEST_FILE_NAME);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("invalid archive manifest: {}", path.display()))
+}
+
+// Replaces the manifest atomically, so that it is never left half written.
+fn write_manifest(dir: &Path, manifest: &[ArchiveEntry]) -> Result<()> {
+    let path = dir.join(MANIFEST_FILE_NAME);
+    let tmp_path = dir.join(format!("{MANIFEST_FILE_NAME}.tmp"));
+    let mut file = File::create(&tmp_path)?;
+    serde_json::to_writer_pretty(&mut file, manifest)?;
+    file.sync_all()?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+fn append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("cannot open archive file: {}", path.display()))
+}
+
+fn open_gz(path: &Path) -> Result<GzEncoder<File>> {
+    Ok(GzEncoder::new(append(path)?, Compression::default()))
+}
+
+// Returns the directory of the source, which ends with the CRC-32 of the name,
+// so that the sources whose names become the same in `path_component` are
+// archived in different directories.
+fn source_dir(source: &str) -> String {
+    let mut crc = Crc::new();
+    crc.update(source.as_bytes());
+    format!("{}-{:08x}", path_component(source), crc.sum())
+}
+
+// Makes the name usable as a file name, replacing the characters other than
+// alphanumerics, `-`, `_`, and `.` with `_`.
+fn path_component(name: &str) -> String {
+    let component: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if component.chars().all(|c| c == '.') {
+        component.replace('.', "_")
+    } else {
+        component
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::{TimeZone, Utc};
+    use giganto_client::ingest::log::Log;
+
+    use super::{import_archive, path_component, read_manifest, source_dir, Archiver};
+    use crate::{
+        settings::ArchiveFormat,
+        storage::{Database, DbOptions, StorageKey},
+    };
+
+    #[test]
+    fn archive_and_import() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let archive_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.log_store().unwrap();
+
+        let log = bincode::serialize(&Log {
+            kind: "kind".to_string(),
+            log: b"log".to_vec(),
+        })
+        .unwrap();
+        let key_builder = StorageKey::builder()
+            .start_key(store.assign_source_key("src 1").unwrap())
+            .mid_key(Some(b"kind".to_vec()));
+        let day1 = Utc.with_ymd_and_hms(2024, 1, 1, 23, 0, 0).unwrap();
+        let day2 = Utc.with_ymd_and_hms(2024, 1, 2, 1, 0, 0).unwrap();
+        for (timestamp, sequence) in [(day1, 0), (day1, 1), (day2, 0)] {
+            let key = key_builder
+                .clone()
+                .end_key(timestamp.timestamp_nanos_opt().unwrap())
+                .sequence(sequence)
+                .build()
+                .key();
+            store.append(&key, &log).unwrap();
+        }
+        let keys = store
+            .iter_forward()
+            .map(|item| item.unwrap().0.to_vec())
+            .collect::<Vec<_>>();
+
+        let mut archiver = Archiver::new(archive_dir.path(), ArchiveFormat::Json).unwrap();
+        let archived = archiver
+            .archive("log", "src 1", store.iter_forward())
+            .unwrap();
+        assert_eq!(archived, 3);
+
+        let manifest = read_manifest(archive_dir.path()).unwrap();
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest[0].date.to_string(), "2024-01-01");
+        assert_eq!(manifest[0].events, 2);
+        assert_eq!(manifest[1].date.to_string(), "2024-01-02");
+        assert_eq!(manifest[1].events, 1);
+        let file = manifest[0].file.as_ref().unwrap();
+        assert_eq!(
+            file.to_str().unwrap(),
+            format!("log/{}/2024-01-01.json.gz", source_dir("src 1"))
+        );
+        assert!(archive_dir.path().join(file).is_file());
+
+        // Archiving the same day again appends to its files.
+        let mut archiver = Archiver::new(archive_dir.path(), ArchiveFormat::Json).unwrap();
+        archiver
+            .archive("log", "src 1", store.iter_forward().take(1))
+            .unwrap();
+        let manifest = read_manifest(archive_dir.path()).unwrap();
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest[0].events, 3);
+
+        for key in &keys {
+            store.delete(key).unwrap();
+        }
+        assert!(store.iter_forward().next().is_none());
+
+        // The event archived twice is imported once.
+        assert_eq!(import_archive(&db, archive_dir.path()).unwrap(), (3, 1));
+        let imported = store
+            .iter_forward()
+            .map(|item| item.unwrap().0.to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(imported, keys);
+
+        // The events already in the database are kept.
+        store.append(&keys[0], b"live").unwrap();
+        assert_eq!(import_archive(&db, archive_dir.path()).unwrap(), (0, 4));
+        assert_eq!(
+            store.db.get_cf(store.cf, &keys[0]).unwrap(),
+            Some(b"live".to_vec())
+        );
+
+        fs::remove_file(archive_dir.path().join("manifest.json")).unwrap();
+        assert_eq!(import_archive(&db, archive_dir.path()).unwrap(), (0, 0));
+    }
+
+    #[test]
+    fn source_dirs_are_distinct() {
+        assert!(source_dir("src 1").starts_with("src_1-"));
+        assert_ne!(source_dir("src 1"), source_dir("src_1"));
+        assert_ne!(source_dir("a/b"), source_dir("a:b"));
+        assert_eq!(source_dir("src 1"), source_dir("src 1"));
+    }
+
+    #[test]
+    fn path_component_is_file_name() {
+        assert_eq!(path_component("dce rpc"), "dce_rpc");
+        assert_eq!(path_component("../src"), ".._src");
+        assert_eq!(path_component(".."), "__");
+        assert_eq!(path_component("src-1.local"), "src-1.local");
+    }
+}
@@ -0,0 +1,174 @@
+//! The audit trail of the GraphQL operations, which is only appended to.
+
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, DB};
+use serde::{Deserialize, Serialize};
+
+use super::{Database, TIMESTAMP_SIZE};
+
+pub(super) const AUDIT_COLUMN_FAMILY_NAME: &str = "audit";
+
+// Tells apart the entries recorded at the same time.
+static AUDIT_SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+/// A GraphQL operation in the audit trail.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// When the operation was received.
+    pub time: DateTime<Utc>,
+    /// The name of the caller, or `None` if the requests are not
+    /// authenticated.
+    pub caller: Option<String>,
+    pub operation: Option<String>,
+    /// The root fields of the operation, such as `export` and `reboot`.
+    pub fields: Vec<String>,
+    /// The variables in JSON, with the sensitive values redacted.
+    pub variables: String,
+    /// The sources in the arguments of the operation.
+    pub sources: Vec<String>,
+    /// The number of the items returned.
+    pub result_count: u64,
+    pub duration: Duration,
+    pub errors: Vec<String>,
+}
+
+/// The store of the audit trail.
+///
+/// The entries are keyed by the time they were received followed by a
+/// sequence number, and are never updated or deleted.
+pub struct AuditStore<'db> {
+    db: &'db DB,
+    cf: &'db ColumnFamily,
+}
+
+impl Database {
+    /// Returns the store of the audit trail.
+    pub fn audit_store(&self) -> Result<AuditStore> {
+        let cf = self.get_cf_handle(AUDIT_COLUMN_FAMILY_NAME)?;
+        Ok(AuditStore { db: &self.db, cf })
+    }
+}
+
+impl<'db> AuditStore<'db> {
+    /// Appends the entry to the trail.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry cannot be stored.
+    pub fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let time = entry.time.timestamp_nanos_opt().unwrap_or(i64::MAX);
+        let mut key = time.to_be_bytes().to_vec();
+        key.extend(AUDIT_SEQUENCE.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+        self.db.put_cf(self.cf, key, bincode::serialize(entry)?)?;
+        Ok(())
+    }
+
+    /// Returns up to `limit` entries of the caller received in [`start`,
+    /// `end`), newest first. The entries of all the callers are returned if
+    /// `caller` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entries cannot be read.
+    pub fn search(
+        &self,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        caller: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<AuditEntry>> {
+        let end_key = end.map(|end| end.timestamp_nanos_opt().unwrap_or(i64::MAX).to_be_bytes());
+        let mode = match &end_key {
+            // The keys at `end` are after `end_key`, which has no sequence.
+            Some(end_key) => IteratorMode::From(end_key.as_slice(), Direction::Reverse),
+            None => IteratorMode::End,
+        };
+        let start = start.map_or(i64::MIN, |start| {
+            start.timestamp_nanos_opt().unwrap_or(i64::MIN)
+        });
+
+        let mut entries = Vec::new();
+        for item in self.db.iterator_cf(self.cf, mode) {
+            if entries.len() >= limit {
+                break;
+            }
+            let (key, value) = item?;
+            let time = key
+                .get(..TIMESTAMP_SIZE)
+                .and_then(|time| time.try_into().ok())
+                .map_or(i64::MIN, i64::from_be_bytes);
+            if time < start {
+                break;
+            }
+            let entry: AuditEntry = bincode::deserialize(&value)?;
+            if caller.map_or(true, |caller| entry.caller.as_deref() == Some(caller)) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{DateTime, TimeDelta, Utc};
+
+    use super::AuditEntry;
+    use crate::storage::{Database, DbOptions};
+
+    fn entry(time: DateTime<Utc>, caller: &str, field: &str) -> AuditEntry {
+        AuditEntry {
+            time,
+            caller: Some(caller.to_string()),
+            operation: None,
+            fields: vec![field.to_string()],
+            variables: "{}".to_string(),
+            sources: Vec::new(),
+            result_count: 1,
+            duration: Duration::from_millis(3),
+            errors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn search() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.audit_store().unwrap();
+
+        let time: DateTime<Utc> = "2024-01-01T00:00:00Z".parse().unwrap();
+        let later = time + TimeDelta::minutes(1);
+        store.append(&entry(time, "alice", "export")).unwrap();
+        store.append(&entry(time, "bob", "stop")).unwrap();
+        store.append(&entry(later, "alice", "reboot")).unwrap();
+
+        let entries = store.search(None, None, None, 10).unwrap();
+        let fields: Vec<_> = entries.iter().map(|e| e.fields[0].as_str()).collect();
+        assert_eq!(fields, ["reboot", "stop", "export"]);
+
+        let entries = store.search(None, None, Some("alice"), 10).unwrap();
+        assert_eq!(
+            entries,
+            [
+                entry(later, "alice", "reboot"),
+                entry(time, "alice", "export")
+            ]
+        );
+
+        let entries = store.search(Some(time), Some(later), None, 10).unwrap();
+        let fields: Vec<_> = entries.iter().map(|e| e.fields[0].as_str()).collect();
+        assert_eq!(fields, ["stop", "export"]);
+        let entries = store.search(Some(later), None, None, 10).unwrap();
+        assert_eq!(entries.len(), 1);
+        let entries = store.search(None, None, None, 2).unwrap();
+        assert_eq!(entries.len(), 2);
+    }
+}
@@ -0,0 +1,271 @@
+//! Routines to back up the database with checkpoints and restore it from them.
+//!
+//! A checkpoint is an openable copy of the database whose SST files are hard
+//! links to those of the database, so it can be created while giganto is
+//! running without copying the data.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rocksdb::checkpoint::Checkpoint;
+use tracing::info;
+
+use super::{migration::create_version_file, Database, DbOptions};
+
+// The name of a backup is the time it was created, so that the names sort in
+// chronological order.
+const BACKUP_NAME_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// A checkpoint of the database in the backup directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+impl BackupInfo {
+    fn from_path(path: PathBuf) -> Option<Self> {
+        if !path.join("CURRENT").is_file() {
+            return None;
+        }
+        let name = path.file_name()?.to_str()?.to_string();
+        let created_at = NaiveDateTime::parse_from_str(&name, BACKUP_NAME_FORMAT)
+            .ok()?
+            .and_utc();
+        let size = fs::read_dir(&path)
+            .ok()?
+            .filter_map(|entry| entry.ok()?.metadata().ok())
+            .filter(fs::Metadata::is_file)
+            .map(|metadata| metadata.len())
+            .sum();
+        Some(Self {
+            name,
+            path,
+            created_at,
+            size,
+        })
+    }
+}
+
+impl Database {
+    /// Creates a checkpoint of the database in `backup_dir`.
+    ///
+    /// The checkpoint also has the `VERSION` file of the data directory, so
+    /// that the data restored from it can be migrated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backup directory cannot be created or the
+    /// checkpoint fails.
+    pub fn backup(&self, backup_dir: &Path) -> Result<BackupInfo> {
+        fs::create_dir_all(backup_dir)
+            .with_context(|| format!("cannot create backup directory {}", backup_dir.display()))?;
+        let name = Utc::now().format(BACKUP_NAME_FORMAT).to_string();
+        let path = backup_dir.join(&name);
+        if path.exists() {
+            bail!("backup {name} already exists");
+        }
+
+        Checkpoint::new(&*self.db)
+            .and_then(|checkpoint| checkpoint.create_checkpoint(&path))
+            .context("cannot create checkpoint")?;
+        create_version_file(&path.join("VERSION")).context("cannot create VERSION")?;
+        info!("Backup {name} created");
+
+        BackupInfo::from_path(path).ok_or_else(|| anyhow!("invalid backup {name}"))
+    }
+}
+
+/// Returns the backups in `backup_dir`, from the oldest to the newest.
+///
+/// # Errors
+///
+/// Returns an error if the backup directory exists but cannot be read.
+pub fn list_backups(backup_dir: &Path) -> Result<Vec<BackupInfo>> {
+    if !backup_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = fs::read_dir(backup_dir)
+        .with_context(|| format!("cannot read backup directory {}", backup_dir.display()))?
+        .filter_map(|entry| BackupInfo::from_path(entry.ok()?.path()))
+        .collect::<Vec<_>>();
+    backups.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    Ok(backups)
+}
+
+/// Deletes the backups in `backup_dir` except the newest `keep` ones, and
+/// returns the deleted backups.
+///
+/// # Errors
+///
+/// Returns an error if the backup directory cannot be read or a backup cannot
+/// be deleted.
+pub fn prune_backups(backup_dir: &Path, keep: usize) -> Result<Vec<BackupInfo>> {
+    let mut backups = list_backups(backup_dir)?;
+    let pruned = backups.len().saturating_sub(keep);
+    backups.truncate(pruned);
+    for backup in &backups {
+        fs::remove_dir_all(&backup.path)
+            .with_context(|| format!("cannot delete backup {}", backup.name))?;
+        info!("Backup {} deleted", backup.name);
+    }
+    Ok(backups)
+}
+
+/// Replaces the database in `data_dir` with the copy of the given backup.
+///
+/// The existing database is kept in `data_dir` under a name with the time of
+/// the restoration. This must be called before the database is opened, and
+/// the restored data must be migrated with `migrate_data_dir` after it is
+/// opened, as it may be older than the current version.
+///
+/// A backup cannot be restored with storage tiers, since its SST files are
+/// all in one directory while the database looks for them in the tiers their
+/// levels were in.
+///
+/// # Errors
+///
+/// Returns an error if `backup` is not a backup, if `db_options` has storage
+/// tiers, or if the files cannot be moved or copied.
+pub fn restore_backup(backup: &Path, data_dir: &Path, db_options: &DbOptions) -> Result<()> {
+    if !backup.join("CURRENT").is_file() {
+        bail!("{} is not a backup", backup.display());
+    }
+
+    let db_path = data_dir.join("db");
+    if db_options.storage_paths(&db_path).len() > 1 {
+        bail!("a backup cannot be restored with storage tiers");
+    }
+    if db_path.exists() {
+        let old_db_path = data_dir.join(format!("db.{}", Utc::now().format(BACKUP_NAME_FORMAT)));
+        fs::rename(&db_path, &old_db_path).context("cannot move the existing database")?;
+        info!("The existing database moved to {}", old_db_path.display());
+    }
+    fs::create_dir_all(&db_path).context("cannot create database directory")?;
+
+    for entry in fs::read_dir(backup).context("cannot read backup")? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let target = if entry.file_name() == "VERSION" {
+            data_dir.join("VERSION")
+        } else {
+            db_path.join(entry.file_name())
+        };
+        fs::copy(entry.path(), &target)
+            .with_context(|| format!("cannot copy {}", entry.path().display()))?;
+    }
+    info!("Database restored from {}", backup.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{list_backups, prune_backups, restore_backup};
+    use crate::{
+        settings::StorageTier,
+        storage::{Database, DbOptions, StorageKey},
+    };
+
+    #[test]
+    fn backup_and_prune() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+
+        assert!(list_backups(backup_dir.path()).unwrap().is_empty());
+
+        let first = db.backup(backup_dir.path()).unwrap();
+        // The backups are named after the time they are created.
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let second = db.backup(backup_dir.path()).unwrap();
+        assert!(first.created_at < second.created_at);
+        assert!(second.path.join("VERSION").is_file());
+        assert_eq!(
+            list_backups(backup_dir.path()).unwrap(),
+            vec![first.clone(), second.clone()]
+        );
+
+        let pruned = prune_backups(backup_dir.path(), 1).unwrap();
+        assert_eq!(pruned, vec![first]);
+        assert_eq!(list_backups(backup_dir.path()).unwrap(), vec![second]);
+    }
+
+    #[test]
+    fn restore() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let key = StorageKey::builder()
+            .start_key("src1")
+            .end_key(1)
+            .sequence(0)
+            .build()
+            .key();
+
+        let backup = {
+            let db = Database::open(&data_dir.path().join("db"), &DbOptions::default()).unwrap();
+            db.conn_store().unwrap().append(&key, b"before").unwrap();
+            let backup = db.backup(backup_dir.path()).unwrap();
+            db.conn_store().unwrap().append(&key, b"after").unwrap();
+            db.shutdown().unwrap();
+            backup
+        };
+
+        restore_backup(&backup.path, data_dir.path(), &DbOptions::default()).unwrap();
+        assert!(data_dir.path().join("VERSION").is_file());
+        assert_eq!(
+            fs::read_dir(data_dir.path())
+                .unwrap()
+                .filter(|entry| entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("db."))
+                .count(),
+            1
+        );
+
+        let db = Database::open(&data_dir.path().join("db"), &DbOptions::default()).unwrap();
+        let (_, value) = db
+            .conn_store()
+            .unwrap()
+            .iter_forward()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(value.as_ref(), b"before");
+    }
+
+    #[test]
+    fn restore_with_storage_tiers() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let tier_dir = tempfile::tempdir().unwrap();
+        let backup = {
+            let db = Database::open(&data_dir.path().join("db"), &DbOptions::default()).unwrap();
+            db.backup(backup_dir.path()).unwrap()
+        };
+
+        let db_options = DbOptions {
+            storage_tiers: vec![StorageTier {
+                path: tier_dir.path().to_path_buf(),
+                target_size: 1 << 30,
+            }],
+            ..DbOptions::default()
+        };
+        assert!(restore_backup(&backup.path, data_dir.path(), &db_options).is_err());
+        // The existing database is left as it is.
+        assert!(data_dir.path().join("db").join("CURRENT").is_file());
+        assert!(!data_dir.path().join("VERSION").exists());
+    }
+}
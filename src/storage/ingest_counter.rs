@@ -0,0 +1,223 @@
+//! Counters of the events and bytes ingested from each source, per kind and
+//! per hour.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, MergeOperands, DB};
+
+use super::{
+    encode_source_id, source_id, total_order_read_options, Database, SOURCE_IDS_COLUMN_FAMILY_NAME,
+};
+
+pub(super) const INGEST_COUNTERS_COLUMN_FAMILY_NAME: &str = "ingest counters";
+const HOUR_NANOS: i64 = 3_600_000_000_000;
+const COUNT_SIZE: usize = 8;
+
+/// The number of events and bytes of a kind ingested from a source in an hour.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IngestCount {
+    pub kind: String,
+    /// The start of the hour in which the events were received.
+    pub hour: DateTime<Utc>,
+    pub events: u64,
+    pub bytes: u64,
+}
+
+/// The store of the ingest counters.
+///
+/// The counters are keyed by the source ID, the kind, and the start of the
+/// hour, and are added up by the merge operator of the column family, so that
+/// the streams of a source can update them at the same time.
+pub struct IngestCounterStore<'db> {
+    db: &'db DB,
+    cf: &'db ColumnFamily,
+    ids: &'db ColumnFamily,
+}
+
+// RocksDB must manage thread safety for `ColumnFamily`.
+// See rust-rocksdb/rust-rocksdb#407.
+unsafe impl<'db> Send for IngestCounterStore<'db> {}
+
+impl Database {
+    /// Returns the store of the ingest counters.
+    pub fn ingest_counter_store(&self) -> Result<IngestCounterStore> {
+        let cf = self.get_cf_handle(INGEST_COUNTERS_COLUMN_FAMILY_NAME)?;
+        let ids = self.get_cf_handle(SOURCE_IDS_COLUMN_FAMILY_NAME)?;
+        Ok(IngestCounterStore {
+            db: &self.db,
+            cf,
+            ids,
+        })
+    }
+}
+
+impl<'db> IngestCounterStore<'db> {
+    /// Adds the events and bytes of the kind received from the source with the
+    /// ID at `time` to the counter of the hour.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the counter cannot be updated.
+    pub fn add(
+        &self,
+        id: u32,
+        kind: &str,
+        time: DateTime<Utc>,
+        events: u64,
+        bytes: u64,
+    ) -> Result<()> {
+        let nanos = time.timestamp_nanos_opt().unwrap_or(i64::MAX);
+        let mut key = prefix(id, kind);
+        key.extend_from_slice(&(nanos - nanos.rem_euclid(HOUR_NANOS)).to_be_bytes());
+        self.db
+            .merge_cf(self.cf, key, encode_count(events, bytes))?;
+        Ok(())
+    }
+
+    /// Returns the counters of the source in the hours that overlap [`from`,
+    /// `to`), ordered by kind and hour.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the counters cannot be read.
+    pub fn counts(&self, source: &str, from: i64, to: i64) -> Result<Vec<IngestCount>> {
+        let Some(id) = source_id(self.db, self.ids, source)? else {
+            return Ok(Vec::new());
+        };
+        let prefix = prefix(id, "");
+        let mut counts = Vec::new();
+        for item in self.db.iterator_cf_opt(
+            self.cf,
+            total_order_read_options(),
+            IteratorMode::From(&prefix, Direction::Forward),
+        ) {
+            let (key, value) = item?;
+            let Some(rest) = key.strip_prefix(prefix.as_slice()) else {
+                break;
+            };
+            let Some((kind, hour)) = rest
+                .len()
+                .checked_sub(1 + COUNT_SIZE)
+                .map(|len| (&rest[..len], &rest[len + 1..]))
+            else {
+                continue;
+            };
+            let hour = i64::from_be_bytes(hour.try_into()?);
+            if hour.saturating_add(HOUR_NANOS) <= from || hour >= to {
+                continue;
+            }
+            let (events, bytes) = decode_count(&value).unwrap_or_default();
+            counts.push(IngestCount {
+                kind: String::from_utf8_lossy(kind).into_owned(),
+                hour: DateTime::from_timestamp_nanos(hour),
+                events,
+                bytes,
+            });
+        }
+        Ok(counts)
+    }
+}
+
+fn prefix(id: u32, kind: &str) -> Vec<u8> {
+    let mut prefix = encode_source_id(id);
+    prefix.push(0x00);
+    if !kind.is_empty() {
+        prefix.extend_from_slice(kind.as_bytes());
+        prefix.push(0x00);
+    }
+    prefix
+}
+
+fn encode_count(events: u64, bytes: u64) -> [u8; 2 * COUNT_SIZE] {
+    let mut value = [0; 2 * COUNT_SIZE];
+    value[..COUNT_SIZE].copy_from_slice(&events.to_be_bytes());
+    value[COUNT_SIZE..].copy_from_slice(&bytes.to_be_bytes());
+    value
+}
+
+fn decode_count(value: &[u8]) -> Option<(u64, u64)> {
+    let events = u64::from_be_bytes(value.get(..COUNT_SIZE)?.try_into().ok()?);
+    let bytes = u64::from_be_bytes(value.get(COUNT_SIZE..)?.try_into().ok()?);
+    Some((events, bytes))
+}
+
+/// Adds up the counts, which is the merge operator of the ingest counters.
+pub(super) fn merge_counts(
+    _key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    let (mut events, mut bytes) = existing.and_then(decode_count).unwrap_or_default();
+    for operand in operands {
+        let (operand_events, operand_bytes) = decode_count(operand).unwrap_or_default();
+        events = events.saturating_add(operand_events);
+        bytes = bytes.saturating_add(operand_bytes);
+    }
+    Some(encode_count(events, bytes).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::IngestCount;
+    use crate::storage::{Database, DbOptions};
+
+    #[test]
+    fn ingest_counts() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let sources = db.sources_store().unwrap();
+        let src1 = sources.assign_id("src1").unwrap();
+        let src2 = sources.assign_id("src2").unwrap();
+        let store = db.ingest_counter_store().unwrap();
+
+        let time = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        store
+            .add(src1, "conn", time("2024-01-01T00:10:00Z"), 2, 200)
+            .unwrap();
+        store
+            .add(src1, "conn", time("2024-01-01T00:50:00Z"), 3, 300)
+            .unwrap();
+        store
+            .add(src1, "conn", time("2024-01-01T01:00:00Z"), 1, 100)
+            .unwrap();
+        store
+            .add(src1, "dns", time("2024-01-01T00:00:00Z"), 4, 400)
+            .unwrap();
+        store
+            .add(src2, "conn", time("2024-01-01T00:00:00Z"), 5, 500)
+            .unwrap();
+
+        let counts = store.counts("src1", 0, i64::MAX).unwrap();
+        let count = |kind: &str, hour: &str, events, bytes| IngestCount {
+            kind: kind.to_string(),
+            hour: time(hour),
+            events,
+            bytes,
+        };
+        assert_eq!(
+            counts,
+            vec![
+                count("conn", "2024-01-01T00:00:00Z", 5, 500),
+                count("conn", "2024-01-01T01:00:00Z", 1, 100),
+                count("dns", "2024-01-01T00:00:00Z", 4, 400),
+            ]
+        );
+
+        let from = time("2024-01-01T01:00:00Z").timestamp_nanos_opt().unwrap();
+        let counts = store.counts("src1", from, i64::MAX).unwrap();
+        assert_eq!(counts, vec![count("conn", "2024-01-01T01:00:00Z", 1, 100)]);
+        let from = time("2024-01-01T00:30:00Z").timestamp_nanos_opt().unwrap();
+        let to = time("2024-01-01T01:00:00Z").timestamp_nanos_opt().unwrap();
+        let counts = store.counts("src1", from, to).unwrap();
+        assert_eq!(
+            counts,
+            vec![
+                count("conn", "2024-01-01T00:00:00Z", 5, 500),
+                count("dns", "2024-01-01T00:00:00Z", 4, 400),
+            ]
+        );
+        assert!(store.counts("src3", 0, i64::MAX).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+//! The sequence numbers that tell apart the records with the same key prefix.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use anyhow::{Context, Result};
+use rocksdb::{ColumnFamily, WriteBatch, DB};
+
+use super::total_order_read_options;
+use crate::graphql::{SEQUENCE_SIZE, TIMESTAMP_SIZE};
+
+// The number of counters kept in memory, above which the ones not in use are
+// dropped, to be seeded again from the database when needed.
+const MAX_COUNTERS: usize = 65_536;
+
+// A column family, by the address of its handle, and the part of a key prefix
+// before the timestamp.
+type CounterId = (usize, Vec<u8>);
+
+/// The counters of the sequence numbers of the keys written by
+/// `RawEventStore::append_unique`.
+///
+/// There is a counter for each key prefix up to the timestamp, which is seeded
+/// once from the last stored key with the prefix, and is locked only by the
+/// writers of records with the prefix. A record at the latest timestamp of its
+/// prefix, or after it, gets its sequence number from the counter, and only an
+/// earlier one needs to look up the stored keys.
+#[derive(Default)]
+pub(super) struct KeySequences {
+    counters: RwLock<HashMap<CounterId, Arc<Mutex<Counter>>>>,
+}
+
+#[derive(Default)]
+struct Counter {
+    seeded: bool,
+    // The timestamp of the latest key, and the next sequence number at it.
+    latest: Option<(Vec<u8>, u64)>,
+}
+
+impl KeySequences {
+    /// Writes the records atomically, each keyed by its prefix followed by the
+    /// smallest sequence number after it that is used neither by a stored key
+    /// nor by a record before it.
+    ///
+    /// The prefixes end with the timestamps of the records.
+    pub(super) fn write(&self, db: &DB, records: &[(&ColumnFamily, Vec<u8>, &[u8])]) -> Result<()> {
+        // The counters are locked in order, so that no two writers wait for
+        // each other, and until the records are written, so that a counter
+        // seeded later sees them in the database.
+        let ids: BTreeSet<CounterId> = records
+            .iter()
+            .map(|(cf, prefix, _)| counter_id(cf, prefix))
+            .collect();
+        let counters: Vec<_> = ids
+            .into_iter()
+            .map(|id| {
+                let counter = self.counter(&id);
+                (id, counter)
+            })
+            .collect();
+        let mut guards: HashMap<_, _> = counters
+            .iter()
+            .map(|(id, counter)| (id, counter.lock().expect("not poisoned")))
+            .collect();
+
+        let mut batch = WriteBatch::default();
+        let mut late_sequences = HashMap::new();
+        for (cf, prefix, value) in records {
+            let counter = guards
+                .get_mut(&counter_id(cf, prefix))
+                .expect("locked above");
+            let sequence = counter.next(db, cf, prefix, &mut late_sequences)?;
+            let mut key = Vec::with_capacity(prefix.len() + SEQUENCE_SIZE);
+            key.extend_from_slice(prefix);
+            key.extend_from_slice(&sequence.to_be_bytes());
+            batch.put_cf(cf, key, value);
+        }
+        db.write(batch)?;
+        Ok(())
+    }
+
+    fn counter(&self, id: &CounterId) -> Arc<Mutex<Counter>> {
+        if let Some(counter) = self.counters.read().expect("not poisoned").get(id) {
+            return counter.clone();
+        }
+        let mut counters = self.counters.write().expect("not poisoned");
+        if counters.len() >= MAX_COUNTERS {
+            counters.retain(|_, counter| Arc::strong_count(counter) > 1);
+        }
+        counters.entry(id.clone()).or_default().clone()
+    }
+}
+
+impl Counter {
+    /// Returns the sequence number for the prefix, and counts it as used.
+    fn next(
+        &mut self,
+        db: &DB,
+        cf: &ColumnFamily,
+        prefix: &[u8],
+        late_sequences: &mut HashMap<(usize, Vec<u8>), u64>,
+    ) -> Result<u32> {
+        let (base, timestamp) = prefix.split_at(timestamp_start(prefix));
+        if !self.seeded {
+            self.latest = last_key(db, cf, base, prefix.len())?
+                .map(|(timestamp, sequence)| (timestamp, u64::from(sequence) + 1));
+            self.seeded = true;
+        }
+        let sequence = match &mut self.latest {
+            Some((latest, next)) if latest.as_slice() == timestamp => {
+                let sequence = *next;
+                *next += 1;
+                sequence
+            }
+            Some((latest, _)) if latest.as_slice() > timestamp => {
+                let id = (cf_address(cf), prefix.to_vec());
+                let sequence = match late_sequences.get(&id) {
+                    Some(&next) => next,
+                    None => stored_next(db, cf, prefix)?,
+                };
+                late_sequences.insert(id, sequence + 1);
+                sequence
+            }
+            _ => {
+                self.latest = Some((timestamp.to_vec(), 1));
+                0
+            }
+        };
+        u32::try_from(sequence).context("no sequence number left for the key")
+    }
+}
+
+fn counter_id(cf: &ColumnFamily, prefix: &[u8]) -> CounterId {
+    (cf_address(cf), prefix[..timestamp_start(prefix)].to_vec())
+}
+
+fn cf_address(cf: &ColumnFamily) -> usize {
+    std::ptr::from_ref(cf) as usize
+}
+
+// A prefix shorter than a timestamp is taken as one without a timestamp.
+fn timestamp_start(prefix: &[u8]) -> usize {
+    if prefix.len() < TIMESTAMP_SIZE {
+        prefix.len()
+    } else {
+        prefix.len() - TIMESTAMP_SIZE
+    }
+}
+
+/// Returns the timestamp and the sequence number of the last stored key that
+/// starts with `base` and has a prefix of `prefix_len` bytes.
+fn last_key(
+    db: &DB,
+    cf: &ColumnFamily,
+    base: &[u8],
+    prefix_len: usize,
+) -> Result<Option<(Vec<u8>, u32)>> {
+    let mut upper = base.to_vec();
+    upper.resize(prefix_len + SEQUENCE_SIZE, u8::MAX);
+    let mut iter = db.raw_iterator_cf_opt(cf, total_order_read_options());
+    iter.seek_for_prev(&upper);
+    let Some(key) = iter.key() else {
+        iter.status()?;
+        return Ok(None);
+    };
+    if !key.starts_with(base) || key.len() != prefix_len + SEQUENCE_SIZE {
+        return Ok(None);
+    }
+    let (prefix, sequence) = key.split_at(prefix_len);
+    let sequence = u32::from_be_bytes(sequence.try_into().expect("sequence size"));
+    Ok(Some((prefix[base.len()..].to_vec(), sequence)))
+}
+
+/// Returns the sequence number after the last stored one of the prefix.
+fn stored_next(db: &DB, cf: &ColumnFamily, prefix: &[u8]) -> Result<u64> {
+    Ok(last_key(db, cf, prefix, prefix.len())?.map_or(0, |(_, sequence)| u64::from(sequence) + 1))
+}
@@ -0,0 +1,308 @@
+//! Legal holds, which keep the data of a source in a time range from being
+//! deleted by retention.
+
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use rocksdb::{ColumnFamily, IteratorMode, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::{Database, RAW_DATA_COLUMN_FAMILY_NAMES};
+
+pub(super) const LEGAL_HOLDS_COLUMN_FAMILY_NAME: &str = "legal holds";
+
+// The key of the ID of the next hold, which is kept apart from the keys of the
+// holds by its length, so that the IDs of the released holds are not reused.
+const NEXT_ID_KEY: &[u8] = b"next id";
+
+// Serializes the assignment of new hold IDs.
+static LEGAL_HOLD_ID_LOCK: Mutex<()> = Mutex::new(());
+
+/// A hold on the data of a source in a time range.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LegalHold {
+    pub id: u32,
+    pub source: String,
+    /// The record types held, such as `conn` and `log`. All record types are
+    /// held if empty.
+    pub kinds: Vec<String>,
+    /// The start of the held time range, inclusive. `None` for no limit.
+    pub start: Option<DateTime<Utc>>,
+    /// The end of the held time range, exclusive. `None` for no limit.
+    pub end: Option<DateTime<Utc>>,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl LegalHold {
+    /// Returns whether the hold applies to the data of the record type from
+    /// the source.
+    #[must_use]
+    pub fn holds(&self, record_type: &str, source: &str) -> bool {
+        self.source == source
+            && (self.kinds.is_empty() || self.kinds.iter().any(|k| k == record_type))
+    }
+
+    /// Returns the held time range in nanoseconds, [`start`, `end`).
+    #[must_use]
+    pub fn range(&self) -> (i64, i64) {
+        (
+            self.start
+                .map_or(i64::MIN, |t| t.timestamp_nanos_opt().unwrap_or(i64::MIN)),
+            self.end
+                .map_or(i64::MAX, |t| t.timestamp_nanos_opt().unwrap_or(i64::MAX)),
+        )
+    }
+}
+
+/// The store of the legal holds.
+pub struct LegalHoldStore<'db> {
+    db: &'db DB,
+    cf: &'db ColumnFamily,
+}
+
+impl Database {
+    /// Returns the store of the legal holds.
+    pub fn legal_hold_store(&self) -> Result<LegalHoldStore> {
+        let cf = self.get_cf_handle(LEGAL_HOLDS_COLUMN_FAMILY_NAME)?;
+        Ok(LegalHoldStore { db: &self.db, cf })
+    }
+}
+
+impl<'db> LegalHoldStore<'db> {
+    /// Creates a hold and returns it with its ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a kind is not a record type, the time range is
+    /// empty, or the hold cannot be stored.
+    pub fn create(
+        &self,
+        source: &str,
+        kinds: Vec<String>,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        reason: &str,
+    ) -> Result<LegalHold> {
+        for kind in &kinds {
+            if !RAW_DATA_COLUMN_FAMILY_NAMES.contains(&kind.as_str()) {
+                bail!("unknown record type: {kind}");
+            }
+        }
+        if let (Some(start), Some(end)) = (start, end) {
+            if start >= end {
+                bail!("the start of the time range must be before its end");
+            }
+        }
+
+        let _lock = LEGAL_HOLD_ID_LOCK.lock().expect("not poisoned");
+        let id = if let Some(next_id) = self.db.get_cf(self.cf, NEXT_ID_KEY)? {
+            u32::from_be_bytes(
+                next_id
+                    .as_slice()
+                    .try_into()
+                    .context("invalid next legal hold ID")?,
+            )
+        } else {
+            // The holds created before the next ID was kept.
+            self.list()?.last().map_or(0, |hold| hold.id + 1)
+        };
+        let next_id = id.checked_add(1).context("no legal hold ID left")?;
+        let hold = LegalHold {
+            id,
+            source: source.to_string(),
+            kinds,
+            start,
+            end,
+            reason: reason.to_string(),
+            created_at: Utc::now(),
+        };
+        let mut batch = WriteBatch::default();
+        batch.put_cf(self.cf, id.to_be_bytes(), bincode::serialize(&hold)?);
+        batch.put_cf(self.cf, NEXT_ID_KEY, next_id.to_be_bytes());
+        self.db.write(batch)?;
+        info!("Legal hold {id} created on {source}: {reason}");
+        Ok(hold)
+    }
+
+    /// Returns all the holds in the order of their IDs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the holds cannot be read.
+    pub fn list(&self) -> Result<Vec<LegalHold>> {
+        self.db
+            .iterator_cf(self.cf, IteratorMode::Start)
+            .filter(|item| !matches!(item, Ok((key, _)) if key.as_ref() == NEXT_ID_KEY))
+            .map(|item| {
+                let (_, value) = item?;
+                Ok(bincode::deserialize(&value)?)
+            })
+            .collect()
+    }
+
+    /// Releases the hold, and returns it if it existed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the hold cannot be read or deleted.
+    pub fn release(&self, id: u32) -> Result<Option<LegalHold>> {
+        let key = id.to_be_bytes();
+        let Some(value) = self.db.get_cf(self.cf, key)? else {
+            return Ok(None);
+        };
+        let hold: LegalHold = bincode::deserialize(&value)?;
+        self.db.delete_cf(self.cf, key)?;
+        info!("Legal hold {id} on {} released", hold.source);
+        Ok(Some(hold))
+    }
+
+    /// Adds the rewrites of the holds on the source to hold `new_name` instead
+    /// to the batch.
+    pub(super) fn rename_source(
+        &self,
+        batch: &mut WriteBatch,
+        source: &str,
+        new_name: &str,
+    ) -> Result<()> {
+        for mut hold in self.list()? {
+            if hold.source == source {
+                hold.source = new_name.to_string();
+                batch.put_cf(self.cf, hold.id.to_be_bytes(), bincode::serialize(&hold)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the parts of the time range [`from`, `to`) that are not held for
+/// the record type of the source, logging the held parts.
+pub(super) fn unheld_ranges(
+    holds: &[LegalHold],
+    record_type: &str,
+    source: &str,
+    from: i64,
+    to: i64,
+) -> Vec<(i64, i64)> {
+    let mut held = holds
+        .iter()
+        .filter(|hold| hold.holds(record_type, source))
+        .filter_map(|hold| {
+            let (start, end) = hold.range();
+            let (start, end) = (start.max(from), end.min(to));
+            if start >= end {
+                return None;
+            }
+            info!(
+                "Legal hold {} keeps {record_type} of {source} from {} to {}",
+                hold.id,
+                DateTime::from_timestamp_nanos(start),
+                DateTime::from_timestamp_nanos(end)
+            );
+            Some((start, end))
+        })
+        .collect::<Vec<_>>();
+    held.sort_unstable();
+
+    let mut ranges = Vec::new();
+    let mut start = from;
+    for (held_start, held_end) in held {
+        if start < held_start {
+            ranges.push((start, held_start));
+        }
+        start = start.max(held_end);
+    }
+    if start < to {
+        ranges.push((start, to));
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::unheld_ranges;
+    use crate::storage::{Database, DbOptions};
+
+    fn time(nanos: i64) -> Option<DateTime<Utc>> {
+        Some(DateTime::from_timestamp_nanos(nanos))
+    }
+
+    #[test]
+    fn legal_hold_store() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.legal_hold_store().unwrap();
+
+        assert!(store
+            .create("src1", vec!["unknown".to_string()], None, None, "case")
+            .is_err());
+        assert!(store
+            .create("src1", Vec::new(), time(10), time(10), "case")
+            .is_err());
+
+        let first = store
+            .create(
+                "src1",
+                vec!["conn".to_string()],
+                time(10),
+                time(20),
+                "case 1",
+            )
+            .unwrap();
+        let second = store
+            .create("src2", Vec::new(), None, None, "case 2")
+            .unwrap();
+        assert_eq!(first.id, 0);
+        assert_eq!(second.id, 1);
+        assert_eq!(store.list().unwrap(), vec![first.clone(), second.clone()]);
+
+        assert_eq!(store.release(0).unwrap(), Some(first));
+        assert_eq!(store.release(0).unwrap(), None);
+        assert_eq!(store.list().unwrap(), vec![second.clone()]);
+
+        // The ID of a released hold is not reused, even if it was the last.
+        assert_eq!(store.release(1).unwrap(), Some(second));
+        let third = store
+            .create("src3", Vec::new(), None, None, "case 3")
+            .unwrap();
+        assert_eq!(third.id, 2);
+        assert_eq!(store.list().unwrap(), vec![third]);
+    }
+
+    #[test]
+    fn unheld() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.legal_hold_store().unwrap();
+        store
+            .create("src1", vec!["conn".to_string()], time(10), time(20), "a")
+            .unwrap();
+        store
+            .create("src1", Vec::new(), time(15), time(30), "b")
+            .unwrap();
+        store
+            .create("src1", Vec::new(), time(50), None, "c")
+            .unwrap();
+        store.create("src2", Vec::new(), None, None, "d").unwrap();
+        let holds = store.list().unwrap();
+
+        assert_eq!(
+            unheld_ranges(&holds, "conn", "src1", 0, 100),
+            vec![(0, 10), (30, 50)]
+        );
+        assert_eq!(
+            unheld_ranges(&holds, "dns", "src1", 0, 100),
+            vec![(0, 15), (30, 50)]
+        );
+        assert_eq!(unheld_ranges(&holds, "dns", "src1", 0, 12), vec![(0, 12)]);
+        assert!(unheld_ranges(&holds, "conn", "src2", 0, 100).is_empty());
+        assert_eq!(
+            unheld_ranges(&holds, "conn", "src3", 0, 100),
+            vec![(0, 100)]
+        );
+    }
+}
@@ -8,7 +8,9 @@ use std::{
 };
 
 use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
 use giganto_client::ingest::log::SecuLog;
+use rocksdb::{Direction, IteratorMode, WriteBatch};
 use semver::{Version, VersionReq};
 use serde::de::DeserializeOwned;
 use tracing::info;
@@ -16,7 +18,7 @@ use tracing::info;
 use self::migration_structures::{
     ConnBeforeV21, HttpFromV12BeforeV21, NtlmBeforeV21, SmtpBeforeV21, SshBeforeV21, TlsBeforeV21,
 };
-use super::Database;
+use super::{total_order_read_options, Database, SourceMetadata, SOURCELESS_CFS};
 use crate::{
     graphql::TIMESTAMP_SIZE,
     ingest::implement::EventFilter,
@@ -26,7 +28,21 @@ use crate::{
     },
 };
 
-const COMPATIBLE_VERSION_REQ: &str = ">=0.21.0,<0.23.0";
+const COMPATIBLE_VERSION_REQ: &str = ">=0.23.0-alpha.4,<0.24.0";
+// The scratch space of the migrations that rewrite every key of a column
+// family. It keeps the rewritten records and how far the migration of each
+// column family has got, so that an interrupted migration is resumed instead
+// of rewriting the keys twice.
+pub(super) const MIGRATION_COLUMN_FAMILY_NAME: &str = "migration";
+
+// The stages of the migration of a column family, recorded in the migration
+// column family under the name of the column family.
+const STAGE_COPIED: u8 = 1; // The rewritten records are in the scratch space.
+const STAGE_CLEARED: u8 = 2; // The column family is empty.
+const STAGE_DONE: u8 = 3; // The rewritten records are in the column family.
+
+// The number of records written to the database at a time by a migration.
+const MIGRATION_BATCH_SIZE: usize = 10_000;
 
 /// Migrates the data directory to the up-to-date format if necessary.
 ///
@@ -52,6 +68,29 @@ pub fn migrate_data_dir(data_dir: &Path, db: &Database) -> Result<()> {
             Version::parse("0.21.0").expect("valid version"),
             migrate_0_19_to_0_21_0,
         ),
+        (
+            VersionReq::parse(">=0.21.0,<0.23.0-alpha.1").expect("valid version requirement"),
+            Version::parse("0.23.0-alpha.1").expect("valid version"),
+            migrate_0_21_to_0_23_0,
+        ),
+        (
+            VersionReq::parse(">=0.23.0-alpha.1,<0.23.0-alpha.2")
+                .expect("valid version requirement"),
+            Version::parse("0.23.0-alpha.2").expect("valid version"),
+            migrate_0_23_alpha1_to_0_23_alpha2,
+        ),
+        (
+            VersionReq::parse(">=0.23.0-alpha.2,<0.23.0-alpha.3")
+                .expect("valid version requirement"),
+            Version::parse("0.23.0-alpha.3").expect("valid version"),
+            migrate_0_23_alpha2_to_0_23_alpha3,
+        ),
+        (
+            VersionReq::parse(">=0.23.0-alpha.3,<0.23.0-alpha.4")
+                .expect("valid version requirement"),
+            Version::parse("0.23.0-alpha.4").expect("valid version"),
+            migrate_0_23_alpha3_to_0_23_alpha4,
+        ),
     ];
 
     while let Some((_req, to, m)) = migration
@@ -62,13 +101,20 @@ pub fn migrate_data_dir(data_dir: &Path, db: &Database) -> Result<()> {
         m(db)?;
         version = to.clone();
         if compatible.matches(&version) {
-            return create_version_file(&data_dir.join("VERSION"))
-                .context("failed to update VERSION");
+            create_version_file(&data_dir.join("VERSION")).context("failed to update VERSION")?;
+            return clear_migration_stages(db);
         }
     }
     Err(anyhow!("migration from {version} is not supported",))
 }
 
+/// Returns whether the data in `data_dir` are in the format of this version,
+/// that is, no migration is pending.
+pub fn is_migrated(data_dir: &Path) -> bool {
+    let compatible = VersionReq::parse(COMPATIBLE_VERSION_REQ).expect("valid version requirement");
+    read_version_file(&data_dir.join("VERSION")).is_ok_and(|version| compatible.matches(&version))
+}
+
 fn retrieve_or_create_version(path: &Path) -> Result<Version> {
     let file = path.join("VERSION");
     if !path.exists() {
@@ -91,7 +137,7 @@ fn retrieve_or_create_version(path: &Path) -> Result<Version> {
     Ok(version)
 }
 
-fn create_version_file(path: &Path) -> Result<()> {
+pub(super) fn create_version_file(path: &Path) -> Result<()> {
     let mut f = File::create(path).context("cannot create VERSION")?;
     f.write_all(env!("CARGO_PKG_VERSION").as_bytes())
         .context("cannot write VERSION")?;
@@ -216,6 +262,239 @@ fn migrate_0_19_to_0_21_0(db: &Database) -> Result<()> {
     Ok(())
 }
 
+// Rekeys oplog events from `agent_name@source`+`timestamp` to
+// `source`+`agent_name`+`timestamp`, so that they can be found by source.
+fn migrate_0_21_to_0_23_0(db: &Database) -> Result<()> {
+    info!("start migration for oplog");
+    let store = db.op_log_store()?;
+    for raw_event in store.iter_forward() {
+        let (key, value) = raw_event.context("Failed to read Database")?;
+        let Some(agent_id) = key
+            .iter()
+            .position(|c| *c == 0)
+            .and_then(|pos| std::str::from_utf8(&key[..pos]).ok())
+        else {
+            continue;
+        };
+        let (Some((agent_name, source)), Ok(timestamp)) =
+            (agent_id.rsplit_once('@'), get_timestamp_from_key(&key))
+        else {
+            continue;
+        };
+        let new_key = StorageKey::builder()
+            .start_key(source)
+            .mid_key(Some(agent_name.as_bytes().to_vec()))
+            .end_key(timestamp)
+            .build();
+        store.append(&new_key.key(), &value)?;
+        store.delete(&key)?;
+    }
+    info!("oplog migration complete");
+
+    Ok(())
+}
+
+// Appends the sequence number 0 to the keys of all raw events, so that the
+// events with the same timestamp don't overwrite each other.
+fn migrate_0_23_alpha1_to_0_23_alpha2(db: &Database) -> Result<()> {
+    let stores = db.retain_period_store()?;
+    for (cf_name, store) in stores
+        .standard_cfs
+        .iter()
+        .chain(stores.non_standard_cfs.iter())
+        .chain(stores.sourceless_cfs.iter())
+    {
+        info!("start migration for {cf_name}");
+        rewrite_keys(db, &format!("{cf_name} sequence"), store, |key| {
+            let mut new_key = key.to_vec();
+            new_key.extend_from_slice(&0_u32.to_be_bytes());
+            Ok(new_key)
+        })?;
+        info!("{cf_name} migration complete");
+    }
+
+    Ok(())
+}
+
+// Replaces the source at the start of the keys with the ID of the source, which
+// is assigned as the sources are found.
+fn migrate_0_23_alpha2_to_0_23_alpha3(db: &Database) -> Result<()> {
+    let stores = db.retain_period_store()?;
+    for (cf_name, store) in stores
+        .standard_cfs
+        .iter()
+        .chain(stores.non_standard_cfs.iter())
+    {
+        info!("start migration for {cf_name}");
+        migrate_source_to_id(db, cf_name, store)?;
+        info!("{cf_name} migration complete");
+    }
+
+    info!("start migration for dead letters");
+    migrate_source_to_id(db, "dead letters", &db.dead_letter_store()?)?;
+    info!("dead letters migration complete");
+
+    // The quarantined keys are the original keys after the column family name.
+    info!("start migration for quarantine");
+    let store = RawEventStore::<()>::new(db, db.get_cf_handle("quarantine")?);
+    rewrite_keys(db, "quarantine", &store, |key| {
+        let Some(pos) = key.iter().position(|c| *c == 0) else {
+            return Ok(key.to_vec());
+        };
+        let (cf_name, original) = key.split_at(pos + 1);
+        if SOURCELESS_CFS
+            .iter()
+            .any(|name| name.as_bytes() == &cf_name[..pos])
+        {
+            return Ok(key.to_vec());
+        }
+        let mut new_key = cf_name.to_vec();
+        new_key.extend(source_to_id(&store, original)?);
+        Ok(new_key)
+    })?;
+    info!("quarantine migration complete");
+
+    Ok(())
+}
+
+// Replaces the last active time of each source with the metadata of the
+// source, which takes the last active time as the first-seen time as well.
+fn migrate_0_23_alpha3_to_0_23_alpha4(db: &Database) -> Result<()> {
+    let cf = db.get_cf_handle("sources")?;
+    let mut batch = WriteBatch::default();
+    for item in db.db.iterator_cf(cf, IteratorMode::Start) {
+        let (name, value) = item.context("Failed to read Database")?;
+        let Ok(last_active) = <[u8; 8]>::try_from(&value[..]) else {
+            continue;
+        };
+        let last_active = DateTime::from_timestamp_nanos(i64::from_be_bytes(last_active));
+        batch.put_cf(
+            cf,
+            name,
+            bincode::serialize(&SourceMetadata::new(last_active))?,
+        );
+    }
+    db.db.write(batch)?;
+    Ok(())
+}
+
+// Replaces the source at the start of the keys with the ID of the source.
+fn migrate_source_to_id<T>(
+    db: &Database,
+    cf_name: &str,
+    store: &RawEventStore<'_, T>,
+) -> Result<()> {
+    rewrite_keys(db, cf_name, store, |key| source_to_id(store, key))
+}
+
+// Returns the key with the source at the start replaced with its ID.
+fn source_to_id<T>(store: &RawEventStore<'_, T>, key: &[u8]) -> Result<Vec<u8>> {
+    match key
+        .iter()
+        .position(|c| *c == 0)
+        .and_then(|pos| std::str::from_utf8(&key[..pos]).ok())
+    {
+        Some(source) => {
+            let mut new_key = store.assign_source_key(source)?;
+            new_key.extend_from_slice(&key[source.len()..]);
+            Ok(new_key)
+        }
+        // The keys without a source are kept as they are.
+        None => Ok(key.to_vec()),
+    }
+}
+
+// Rewrites the keys in the scratch space first, so that the keys already
+// rewritten are never rewritten again if the migration is interrupted and run
+// again. The stage of the migration is recorded under `name`, in the same
+// batch as the last change of the stage, and the rewritten records are kept
+// under `name` followed by a NUL.
+fn rewrite_keys<T>(
+    db: &Database,
+    name: &str,
+    store: &RawEventStore<'_, T>,
+    mut rewrite: impl FnMut(&[u8]) -> Result<Vec<u8>>,
+) -> Result<()> {
+    let scratch = db.get_cf_handle(MIGRATION_COLUMN_FAMILY_NAME)?;
+    let mut prefix = name.as_bytes().to_vec();
+    prefix.push(0);
+    let stage = db
+        .db
+        .get_cf(scratch, name)?
+        .and_then(|stage| stage.first().copied())
+        .unwrap_or_default();
+
+    if stage < STAGE_COPIED {
+        let mut batch = WriteBatch::default();
+        for raw_event in store.iter_forward() {
+            let (key, value) = raw_event.context("Failed to read Database")?;
+            let mut new_key = prefix.clone();
+            new_key.extend(rewrite(&key)?);
+            batch.put_cf(scratch, new_key, value);
+            if batch.len() >= MIGRATION_BATCH_SIZE {
+                db.db.write(std::mem::take(&mut batch))?;
+            }
+        }
+        batch.put_cf(scratch, name, [STAGE_COPIED]);
+        db.db.write(batch)?;
+    }
+
+    if stage < STAGE_CLEARED {
+        // The whole column family is deleted with a single range tombstone
+        // rather than a tombstone for each key.
+        let mut batch = WriteBatch::default();
+        let mut iter = db
+            .db
+            .raw_iterator_cf_opt(store.cf, total_order_read_options());
+        iter.seek_to_first();
+        if let Some(first) = iter.key().map(<[u8]>::to_vec) {
+            iter.seek_to_last();
+            let mut end = iter.key().context("Failed to read Database")?.to_vec();
+            end.push(0);
+            batch.delete_range_cf(store.cf, first, end);
+        }
+        iter.status().context("Failed to read Database")?;
+        batch.put_cf(scratch, name, [STAGE_CLEARED]);
+        db.db.write(batch)?;
+    }
+
+    if stage < STAGE_DONE {
+        let iter = db
+            .db
+            .iterator_cf(scratch, IteratorMode::From(&prefix, Direction::Forward));
+        let mut batch = WriteBatch::default();
+        for item in iter {
+            let (key, value) = item.context("Failed to read Database")?;
+            let Some(key) = key.strip_prefix(prefix.as_slice()) else {
+                break;
+            };
+            batch.put_cf(store.cf, key, value);
+            if batch.len() >= MIGRATION_BATCH_SIZE {
+                db.db.write(std::mem::take(&mut batch))?;
+            }
+        }
+        let mut end = name.as_bytes().to_vec();
+        end.push(1);
+        batch.delete_range_cf(scratch, &prefix, end);
+        batch.put_cf(scratch, name, [STAGE_DONE]);
+        db.db.write(batch)?;
+    }
+    Ok(())
+}
+
+// Deletes the stages of the migrations, which are not needed once `VERSION` is
+// updated.
+fn clear_migration_stages(db: &Database) -> Result<()> {
+    let scratch = db.get_cf_handle(MIGRATION_COLUMN_FAMILY_NAME)?;
+    let mut batch = WriteBatch::default();
+    for item in db.db.iterator_cf(scratch, IteratorMode::Start) {
+        let (key, _) = item.context("Failed to read Database")?;
+        batch.delete_cf(scratch, key);
+    }
+    db.db.write(batch)?;
+    Ok(())
+}
+
 fn migrate_netflow<T>(store: &RawEventStore<'_, T>) -> Result<()>
 where
     T: DeserializeOwned + EventFilter,
@@ -256,13 +535,17 @@ mod tests {
 
     use chrono::Utc;
     use giganto_client::ingest::{
-        log::SecuLog,
+        log::{OpLog, OpLogLevel, SecuLog},
         netflow::{Netflow5, Netflow9},
     };
     use semver::{Version, VersionReq};
 
-    use super::COMPATIBLE_VERSION_REQ;
+    use super::{
+        migrate_source_to_id, COMPATIBLE_VERSION_REQ, MIGRATION_COLUMN_FAMILY_NAME, STAGE_COPIED,
+        STAGE_DONE,
+    };
     use crate::storage::{
+        encode_source_id,
         migration::migration_structures::{
             ConnBeforeV21, HttpFromV12BeforeV21, NtlmBeforeV21, SmtpBeforeV21, SshBeforeV21,
             TlsBeforeV21,
@@ -293,6 +576,17 @@ mod tests {
         assert!(!compatible.matches(&breaking));
     }
 
+    #[test]
+    fn is_migrated() {
+        let data_dir = tempfile::tempdir().unwrap();
+        assert!(!super::is_migrated(data_dir.path()));
+        let db = Database::open(&data_dir.path().join("db"), &DbOptions::default()).unwrap();
+        super::migrate_data_dir(data_dir.path(), &db).unwrap();
+        assert!(super::is_migrated(data_dir.path()));
+        std::fs::write(data_dir.path().join("VERSION"), "0.21.0").unwrap();
+        assert!(!super::is_migrated(data_dir.path()));
+    }
+
     #[test]
     fn migrate_0_13_to_0_19() {
         const OLD_NETFLOW5_PREFIX_KEY: &str = "netflow5";
@@ -755,4 +1049,366 @@ mod tests {
         };
         assert_eq!(new_tls, store_tls);
     }
+
+    #[test]
+    fn migrate_0_21_to_0_23() {
+        const TEST_SOURCE: &str = "src1";
+        const TEST_AGENT_NAME: &str = "agent1";
+        const TEST_TIMESTAMP: i64 = 1000;
+
+        // open temp db
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+
+        // insert oplog data using the old key.
+        let op_log_store = db.op_log_store().unwrap();
+        let op_log_body = OpLog {
+            agent_name: TEST_AGENT_NAME.to_string(),
+            log_level: OpLogLevel::Info,
+            contents: "op_log".to_string(),
+        };
+        let serialized_op_log = bincode::serialize(&op_log_body).unwrap();
+        let op_log_old_key = StorageKey::builder()
+            .start_key(&format!("{TEST_AGENT_NAME}@{TEST_SOURCE}"))
+            .end_key(TEST_TIMESTAMP)
+            .build()
+            .key();
+        op_log_store
+            .append(&op_log_old_key, &serialized_op_log)
+            .unwrap();
+
+        //migration 0.21.0 to 0.23.0
+        super::migrate_0_21_to_0_23_0(&db).unwrap();
+
+        //check oplog migration
+        let op_log_new_key = StorageKey::builder()
+            .start_key(TEST_SOURCE)
+            .mid_key(Some(TEST_AGENT_NAME.as_bytes().to_vec()))
+            .end_key(TEST_TIMESTAMP)
+            .build()
+            .key();
+        let mut result_iter = op_log_store.iter_forward();
+        let (result_key, result_value) = result_iter.next().unwrap().unwrap();
+
+        assert_ne!(op_log_old_key, result_key.to_vec());
+        assert_eq!(op_log_new_key, result_key.to_vec());
+        assert_eq!(serialized_op_log, result_value.to_vec());
+        assert!(result_iter.next().is_none());
+    }
+
+    #[test]
+    fn migrate_0_23_alpha1_to_0_23_alpha2() {
+        const TEST_SOURCE: &str = "src1";
+        const TEST_KIND: &str = "kind1";
+        const TEST_TIMESTAMP: i64 = 1000;
+
+        // open temp db
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+
+        // insert secuLog data using the key without sequence number.
+        let secu_log_store = db.secu_log_store().unwrap();
+        let secu_log_body = SecuLog {
+            source: TEST_SOURCE.to_string(),
+            kind: TEST_KIND.to_string(),
+            log_type: TEST_KIND.to_string(),
+            version: "V3".to_string(),
+            orig_addr: None,
+            orig_port: None,
+            resp_addr: None,
+            resp_port: None,
+            proto: None,
+            contents: format!("secu_log_contents {TEST_TIMESTAMP}").to_string(),
+        };
+        let serialized_secu_log = bincode::serialize(&secu_log_body).unwrap();
+        let secu_log_old_key = StorageKey::builder()
+            .start_key(TEST_SOURCE)
+            .mid_key(Some(TEST_KIND.as_bytes().to_vec()))
+            .end_key(TEST_TIMESTAMP)
+            .build()
+            .key();
+        secu_log_store
+            .append(&secu_log_old_key, &serialized_secu_log)
+            .unwrap();
+
+        //migration 0.23.0-alpha.1 to 0.23.0-alpha.2
+        super::migrate_0_23_alpha1_to_0_23_alpha2(&db).unwrap();
+
+        //check secuLog migration
+        let secu_log_new_key = StorageKey::builder()
+            .start_key(TEST_SOURCE)
+            .mid_key(Some(TEST_KIND.as_bytes().to_vec()))
+            .end_key(TEST_TIMESTAMP)
+            .sequence(0)
+            .build()
+            .key();
+        let mut result_iter = secu_log_store.iter_forward();
+        let (result_key, result_value) = result_iter.next().unwrap().unwrap();
+
+        assert_ne!(secu_log_old_key, result_key.to_vec());
+        assert_eq!(secu_log_new_key, result_key.to_vec());
+        assert_eq!(serialized_secu_log, result_value.to_vec());
+        assert!(result_iter.next().is_none());
+    }
+
+    #[test]
+    fn rerun_migrate_0_23_alpha1_to_0_23_alpha2() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.conn_store().unwrap();
+        let old_key = |timestamp: i64| {
+            StorageKey::builder()
+                .start_key("src1")
+                .end_key(timestamp)
+                .build()
+                .key()
+        };
+        store.append(&old_key(1000), b"event1").unwrap();
+        store.append(&old_key(2000), b"event2").unwrap();
+
+        // Running the migration again, as after a crash before `VERSION` is
+        // updated, doesn't append another sequence number.
+        for _ in 0..2 {
+            super::migrate_0_23_alpha1_to_0_23_alpha2(&db).unwrap();
+
+            let stored = store
+                .iter_forward()
+                .map(|item| {
+                    let (key, value) = item.unwrap();
+                    (key.to_vec(), value.to_vec())
+                })
+                .collect::<Vec<_>>();
+            let new_key = |timestamp: i64| {
+                let mut key = old_key(timestamp);
+                key.extend_from_slice(&0_u32.to_be_bytes());
+                key
+            };
+            assert_eq!(
+                stored,
+                vec![
+                    (new_key(1000), b"event1".to_vec()),
+                    (new_key(2000), b"event2".to_vec()),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn migrate_0_23_alpha2_to_0_23_alpha3() {
+        const TEST_KIND: &str = "kind1";
+        const TEST_TIMESTAMP: i64 = 1000;
+
+        // open temp db
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+
+        // insert op_log data of two sources using the source names in the keys.
+        let op_log_store = db.op_log_store().unwrap();
+        let op_log_body = OpLog {
+            agent_name: "agent".to_string(),
+            log_level: OpLogLevel::Info,
+            contents: "op_log".to_string(),
+        };
+        let serialized_op_log = bincode::serialize(&op_log_body).unwrap();
+        let old_key = |source: &str| {
+            StorageKey::builder()
+                .start_key(source)
+                .mid_key(Some(TEST_KIND.as_bytes().to_vec()))
+                .end_key(TEST_TIMESTAMP)
+                .sequence(0)
+                .build()
+                .key()
+        };
+        op_log_store
+            .append(&old_key("src2"), &serialized_op_log)
+            .unwrap();
+        op_log_store
+            .append(&old_key("src1"), &serialized_op_log)
+            .unwrap();
+
+        //migration 0.23.0-alpha.2 to 0.23.0-alpha.3
+        super::migrate_0_23_alpha2_to_0_23_alpha3(&db).unwrap();
+
+        //check op_log migration
+        let sources = db.sources_store().unwrap();
+        let src1 = sources.id("src1").unwrap().unwrap();
+        let src2 = sources.id("src2").unwrap().unwrap();
+        assert_ne!(src1, src2);
+
+        let new_key = |id: u32| {
+            StorageKey::builder()
+                .start_key(encode_source_id(id))
+                .mid_key(Some(TEST_KIND.as_bytes().to_vec()))
+                .end_key(TEST_TIMESTAMP)
+                .sequence(0)
+                .build()
+                .key()
+        };
+        let mut result_keys = op_log_store
+            .iter_forward()
+            .map(|item| {
+                let (key, value) = item.unwrap();
+                assert_eq!(serialized_op_log, value.to_vec());
+                key.to_vec()
+            })
+            .collect::<Vec<_>>();
+        result_keys.sort();
+        let mut expected_keys = vec![new_key(src1), new_key(src2)];
+        expected_keys.sort();
+        assert_eq!(expected_keys, result_keys);
+    }
+
+    #[test]
+    fn migrate_quarantine_source_to_id() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let quarantine = db.get_cf_handle("quarantine").unwrap();
+        let key = |source: &[u8]| {
+            StorageKey::builder()
+                .start_key(source)
+                .end_key(1000)
+                .sequence(0)
+                .build()
+                .key()
+        };
+        let quarantine_key = |cf_name: &str, key: &[u8]| {
+            let mut quarantine_key = cf_name.as_bytes().to_vec();
+            quarantine_key.push(0);
+            quarantine_key.extend_from_slice(key);
+            quarantine_key
+        };
+        db.db
+            .put_cf(quarantine, quarantine_key("conn", &key(b"src1")), b"conn")
+            .unwrap();
+        db.db
+            .put_cf(
+                quarantine,
+                quarantine_key("periodic time series", &key(b"ts1")),
+                b"time series",
+            )
+            .unwrap();
+
+        super::migrate_0_23_alpha2_to_0_23_alpha3(&db).unwrap();
+
+        // The source is replaced with its ID, so that the quarantined records
+        // are deleted and archived along with the other data of the source.
+        let src1 = db.sources_store().unwrap().id("src1").unwrap().unwrap();
+        let mut stored = db
+            .db
+            .iterator_cf(quarantine, rocksdb::IteratorMode::Start)
+            .map(|item| {
+                let (key, value) = item.unwrap();
+                (key.to_vec(), value.to_vec())
+            })
+            .collect::<Vec<_>>();
+        stored.sort();
+        let mut expected = vec![
+            (
+                quarantine_key("conn", &key(&encode_source_id(src1))),
+                b"conn".to_vec(),
+            ),
+            (
+                quarantine_key("periodic time series", &key(b"ts1")),
+                b"time series".to_vec(),
+            ),
+        ];
+        expected.sort();
+        assert_eq!(stored, expected);
+        assert_eq!(db.sources_store().unwrap().id("ts1").unwrap(), None);
+    }
+
+    #[test]
+    fn resume_migrate_source_to_id() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.conn_store().unwrap();
+        let scratch = db.get_cf_handle(MIGRATION_COLUMN_FAMILY_NAME).unwrap();
+        let key = |source: &[u8]| {
+            StorageKey::builder()
+                .start_key(source)
+                .end_key(1000)
+                .sequence(0)
+                .build()
+                .key()
+        };
+        store.append(&key(b"src1"), b"event1").unwrap();
+        store.append(&key(b"src2"), b"event2").unwrap();
+
+        // Interrupted while clearing the column family, after all the records
+        // were copied with the source IDs.
+        for (source, value) in [("src1", b"event1"), ("src2", b"event2")] {
+            let mut scratch_key = b"conn\0".to_vec();
+            scratch_key.extend(key(&store.assign_source_key(source).unwrap()));
+            db.db.put_cf(scratch, scratch_key, value).unwrap();
+        }
+        db.db.put_cf(scratch, "conn", [STAGE_COPIED]).unwrap();
+        store.delete(&key(b"src1")).unwrap();
+
+        // Running the migration again has no effect.
+        for _ in 0..2 {
+            migrate_source_to_id(&db, "conn", &store).unwrap();
+
+            let sources = db.sources_store().unwrap();
+            let src1 = sources.id("src1").unwrap().unwrap();
+            let src2 = sources.id("src2").unwrap().unwrap();
+            let stored = store
+                .iter_forward()
+                .map(|item| {
+                    let (key, value) = item.unwrap();
+                    (key.to_vec(), value.to_vec())
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                stored,
+                vec![
+                    (key(&encode_source_id(src1)), b"event1".to_vec()),
+                    (key(&encode_source_id(src2)), b"event2".to_vec()),
+                ]
+            );
+            let scratch_items = db
+                .db
+                .iterator_cf(scratch, rocksdb::IteratorMode::Start)
+                .map(|item| item.unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(scratch_items.len(), 1);
+            assert_eq!(scratch_items[0].1.as_ref(), [STAGE_DONE]);
+        }
+
+        super::clear_migration_stages(&db).unwrap();
+        assert!(db
+            .db
+            .iterator_cf(scratch, rocksdb::IteratorMode::Start)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn migrate_0_23_alpha3_to_0_23_alpha4() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+
+        // insert the last active time of a source in the old format.
+        let last_active = Utc::now();
+        let cf = db.get_cf_handle("sources").unwrap();
+        db.db
+            .put_cf(
+                cf,
+                "src1",
+                last_active.timestamp_nanos_opt().unwrap().to_be_bytes(),
+            )
+            .unwrap();
+
+        super::migrate_0_23_alpha3_to_0_23_alpha4(&db).unwrap();
+
+        let metadata = db
+            .sources_store()
+            .unwrap()
+            .metadata("src1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(metadata.first_seen, last_active);
+        assert_eq!(metadata.last_active, last_active);
+        assert_eq!(metadata.last_connected, None);
+        assert!(metadata.agents.is_empty());
+    }
 }
@@ -0,0 +1,340 @@
+//! Routines to verify that the stored records are still readable.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use giganto_client::ingest::{
+    log::{Log, OpLog, SecuLog},
+    netflow::{Netflow5, Netflow9},
+    network::{
+        Bootp, Conn, DceRpc, Dhcp, Dns, Ftp, Http, Kerberos, Ldap, Mqtt, Nfs, Ntlm, Rdp, Smb, Smtp,
+        Ssh, Tls,
+    },
+    statistics::Statistics,
+    sysmon::{
+        DnsEvent, FileCreate, FileCreateStreamHash, FileCreationTimeChanged, FileDelete,
+        FileDeleteDetected, ImageLoaded, NetworkConnection, PipeEvent, ProcessCreate,
+        ProcessTampering, ProcessTerminated, RegistryKeyValueRename, RegistryValueSet,
+    },
+    timeseries::PeriodicTimeSeries,
+    Packet,
+};
+use rocksdb::{IteratorMode, WriteBatch};
+use serde::de::DeserializeOwned;
+use tracing::{info, warn};
+
+use super::{
+    decode_source_id, timestamp_from_key, total_order_read_options, Database,
+    RAW_DATA_COLUMN_FAMILY_NAMES, SOURCELESS_CFS,
+};
+use crate::graphql::{SEQUENCE_SIZE, TIMESTAMP_SIZE};
+
+/// The result of an integrity check of the raw event column families.
+#[derive(Clone, Debug)]
+pub struct IntegrityReport {
+    pub started_at: DateTime<Utc>,
+    /// `None` while the check is running.
+    pub finished_at: Option<DateTime<Utc>>,
+    pub quarantine: bool,
+    pub column_families: Vec<CfIntegrity>,
+    pub error: Option<String>,
+}
+
+impl IntegrityReport {
+    #[must_use]
+    pub fn new(quarantine: bool) -> Self {
+        Self {
+            started_at: Utc::now(),
+            finished_at: None,
+            quarantine,
+            column_families: Vec::new(),
+            error: None,
+        }
+    }
+
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.finished_at.is_none()
+    }
+}
+
+/// The integrity of the records in a column family.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CfIntegrity {
+    pub name: String,
+    pub records: u64,
+    pub invalid_keys: u64,
+    pub undecodable: u64,
+    pub quarantined: u64,
+    /// The error that stopped reading the column family, if any.
+    pub read_error: Option<String>,
+    /// The sources with invalid records, in the order of their names.
+    pub sources: Vec<SourceIntegrity>,
+}
+
+/// The number of invalid records of a source in a column family.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceIntegrity {
+    pub source: String,
+    pub invalid_keys: u64,
+    pub undecodable: u64,
+}
+
+impl Database {
+    /// Checks that every key of the raw event column families has a source
+    /// and a timestamp, and that every value decodes into the type of its
+    /// column family.
+    ///
+    /// If `quarantine` is true, the invalid records are moved to the
+    /// `quarantine` column family, keyed by the column family name and the
+    /// original key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an invalid record cannot be moved.
+    pub fn check_integrity(&self, quarantine: bool) -> Result<Vec<CfIntegrity>> {
+        let quarantine_cf = self.get_cf_handle("quarantine")?;
+        let source_names: HashMap<u32, String> = self
+            .sources_store()?
+            .ids()
+            .into_iter()
+            .map(|(name, id)| (id, name))
+            .collect();
+        let mut column_families = Vec::with_capacity(RAW_DATA_COLUMN_FAMILY_NAMES.len());
+        for cf_name in RAW_DATA_COLUMN_FAMILY_NAMES {
+            let Some(decodes) = decoder(cf_name) else {
+                continue;
+            };
+            let cf = self.get_cf_handle(cf_name)?;
+            let mut integrity = CfIntegrity {
+                name: cf_name.to_string(),
+                ..CfIntegrity::default()
+            };
+            let mut sources: BTreeMap<String, SourceIntegrity> = BTreeMap::new();
+
+            for item in self
+                .db
+                .iterator_cf_opt(cf, total_order_read_options(), IteratorMode::Start)
+            {
+                let (key, value) = match item {
+                    Ok(item) => item,
+                    Err(e) => {
+                        warn!("Failed to read {cf_name}: {e}");
+                        integrity.read_error = Some(e.to_string());
+                        break;
+                    }
+                };
+                integrity.records += 1;
+
+                let source = key.iter().position(|b| *b == 0).map(|pos| {
+                    let source = &key[..pos];
+                    decode_source_id(source)
+                        .filter(|_| !SOURCELESS_CFS.contains(&cf_name))
+                        .and_then(|id| source_names.get(&id).cloned())
+                        .unwrap_or_else(|| String::from_utf8_lossy(source).into_owned())
+                });
+                let key_is_valid =
+                    key_has_source_and_timestamp(&key) && timestamp_from_key(&key).is_ok();
+                let value_is_valid = key_is_valid && decodes(&value);
+                if value_is_valid {
+                    continue;
+                }
+
+                if key_is_valid {
+                    integrity.undecodable += 1;
+                } else {
+                    integrity.invalid_keys += 1;
+                }
+                if let Some(source) = source {
+                    let entry = sources
+                        .entry(source.clone())
+                        .or_insert_with(|| SourceIntegrity {
+                            source,
+                            ..SourceIntegrity::default()
+                        });
+                    if key_is_valid {
+                        entry.undecodable += 1;
+                    } else {
+                        entry.invalid_keys += 1;
+                    }
+                }
+
+                if quarantine {
+                    let mut quarantine_key = Vec::with_capacity(cf_name.len() + 1 + key.len());
+                    quarantine_key.extend_from_slice(cf_name.as_bytes());
+                    quarantine_key.push(0);
+                    quarantine_key.extend_from_slice(&key);
+
+                    let mut batch = WriteBatch::default();
+                    batch.put_cf(quarantine_cf, quarantine_key, &value);
+                    batch.delete_cf(cf, &key);
+                    self.db.write(batch)?;
+                    integrity.quarantined += 1;
+                }
+            }
+
+            if integrity.invalid_keys + integrity.undecodable > 0 {
+                warn!(
+                    "{cf_name}: {} invalid key(s), {} undecodable value(s)",
+                    integrity.invalid_keys, integrity.undecodable
+                );
+            }
+            integrity.sources = sources.into_values().collect();
+            column_families.push(integrity);
+        }
+        info!("Integrity check finished");
+        Ok(column_families)
+    }
+}
+
+// Whether the key has a separator after the source, followed by enough bytes
+// for the timestamp and the sequence number.
+fn key_has_source_and_timestamp(key: &[u8]) -> bool {
+    key.iter()
+        .position(|b| *b == 0)
+        .is_some_and(|pos| key.len() >= pos + 1 + TIMESTAMP_SIZE + SEQUENCE_SIZE)
+}
+
+fn decodes<T: DeserializeOwned>(value: &[u8]) -> bool {
+    bincode::deserialize::<T>(value).is_ok()
+}
+
+/// Returns the function that checks whether a value decodes into the type of
+/// the column family.
+fn decoder(cf_name: &str) -> Option<fn(&[u8]) -> bool> {
+    let decoder: fn(&[u8]) -> bool = match cf_name {
+        "conn" => decodes::<Conn>,
+        "dns" => decodes::<Dns>,
+        "log" => decodes::<Log>,
+        "http" => decodes::<Http>,
+        "rdp" => decodes::<Rdp>,
+        "periodic time series" => decodes::<PeriodicTimeSeries>,
+        "smtp" => decodes::<Smtp>,
+        "ntlm" => decodes::<Ntlm>,
+        "kerberos" => decodes::<Kerberos>,
+        "ssh" => decodes::<Ssh>,
+        "dce rpc" => decodes::<DceRpc>,
+        "statistics" => decodes::<Statistics>,
+        "oplog" => decodes::<OpLog>,
+        "packet" => decodes::<Packet>,
+        "ftp" => decodes::<Ftp>,
+        "mqtt" => decodes::<Mqtt>,
+        "ldap" => decodes::<Ldap>,
+        "tls" => decodes::<Tls>,
+        "smb" => decodes::<Smb>,
+        "nfs" => decodes::<Nfs>,
+        "bootp" => decodes::<Bootp>,
+        "dhcp" => decodes::<Dhcp>,
+        "process create" => decodes::<ProcessCreate>,
+        "file create time" => decodes::<FileCreationTimeChanged>,
+        "network connect" => decodes::<NetworkConnection>,
+        "process terminate" => decodes::<ProcessTerminated>,
+        "image load" => decodes::<ImageLoaded>,
+        "file create" => decodes::<FileCreate>,
+        "registry value set" => decodes::<RegistryValueSet>,
+        "registry key rename" => decodes::<RegistryKeyValueRename>,
+        "file create stream hash" => decodes::<FileCreateStreamHash>,
+        "pipe event" => decodes::<PipeEvent>,
+        "dns query" => decodes::<DnsEvent>,
+        "file delete" => decodes::<FileDelete>,
+        "process tamper" => decodes::<ProcessTampering>,
+        "file delete detected" => decodes::<FileDeleteDetected>,
+        "netflow5" => decodes::<Netflow5>,
+        "netflow9" => decodes::<Netflow9>,
+        "seculog" => decodes::<SecuLog>,
+        _ => return None,
+    };
+    Some(decoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use giganto_client::ingest::log::Log;
+    use rocksdb::IteratorMode;
+
+    use super::{decoder, SourceIntegrity};
+    use crate::storage::{Database, DbOptions, StorageKey, RAW_DATA_COLUMN_FAMILY_NAMES};
+
+    #[test]
+    fn decoder_for_every_cf() {
+        for cf_name in RAW_DATA_COLUMN_FAMILY_NAMES {
+            assert!(decoder(cf_name).is_some(), "{cf_name}");
+        }
+    }
+
+    #[test]
+    fn check_integrity() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.log_store().unwrap();
+        let sources = db.sources_store().unwrap();
+        sources.assign_id("src1").unwrap();
+        sources.assign_id("src2").unwrap();
+
+        let log = bincode::serialize(&Log {
+            kind: "kind".to_string(),
+            log: b"log".to_vec(),
+        })
+        .unwrap();
+        let key = |source: &str, timestamp: i64| {
+            StorageKey::builder()
+                .start_key(store.source_key(source))
+                .mid_key(Some(b"kind".to_vec()))
+                .end_key(timestamp)
+                .sequence(0)
+                .build()
+                .key()
+        };
+        store.append(&key("src1", 1), &log).unwrap();
+        store.append(&key("src1", 2), b"invalid").unwrap();
+        store.append(&key("src2", 3), &log).unwrap();
+        let mut short_key = store.source_key("src2");
+        short_key.extend_from_slice(b"\x00short");
+        store.append(&short_key, &log).unwrap();
+
+        let report = db.check_integrity(false).unwrap();
+        let log_report = report.iter().find(|cf| cf.name == "log").unwrap();
+        assert_eq!(log_report.records, 4);
+        assert_eq!(log_report.invalid_keys, 1);
+        assert_eq!(log_report.undecodable, 1);
+        assert_eq!(log_report.quarantined, 0);
+        assert_eq!(
+            log_report.sources,
+            vec![
+                SourceIntegrity {
+                    source: "src1".to_string(),
+                    invalid_keys: 0,
+                    undecodable: 1,
+                },
+                SourceIntegrity {
+                    source: "src2".to_string(),
+                    invalid_keys: 1,
+                    undecodable: 0,
+                },
+            ]
+        );
+        assert!(report
+            .iter()
+            .filter(|cf| cf.name != "log")
+            .all(|cf| cf.records == 0));
+
+        let report = db.check_integrity(true).unwrap();
+        let log_report = report.iter().find(|cf| cf.name == "log").unwrap();
+        assert_eq!(log_report.quarantined, 2);
+
+        let report = db.check_integrity(false).unwrap();
+        let log_report = report.iter().find(|cf| cf.name == "log").unwrap();
+        assert_eq!(log_report.records, 2);
+        assert!(log_report.sources.is_empty());
+
+        let quarantine_cf = db.get_cf_handle("quarantine").unwrap();
+        let quarantined = db
+            .db
+            .iterator_cf(quarantine_cf, IteratorMode::Start)
+            .map(|item| item.unwrap().0.to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(quarantined.len(), 2);
+        assert!(quarantined.iter().all(|key| key.starts_with(b"log\x00")));
+    }
+}
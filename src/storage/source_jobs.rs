@@ -0,0 +1,450 @@
+//! Routines to delete or rename all the data of a source.
+
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use rocksdb::{ColumnFamily, Direction, IteratorMode, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::{
+    encode_source_id, total_order_read_options, Database, INGEST_COUNTERS_COLUMN_FAMILY_NAME,
+    RAW_DATA_COLUMN_FAMILY_NAMES, SOURCELESS_CFS, SOURCE_ID_LOCK,
+};
+use crate::ingest::DeadLetter;
+
+// The jobs, keyed by their IDs, so that they are listed and the interrupted
+// ones are resumed after a restart.
+pub(super) const SOURCE_JOBS_COLUMN_FAMILY_NAME: &str = "source jobs";
+
+// Serializes the assignment of new job IDs.
+static SOURCE_JOB_ID_LOCK: Mutex<()> = Mutex::new(());
+
+/// What a source job does to the data of the source.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SourceJobKind {
+    Delete,
+    Rename { new_name: String },
+}
+
+/// A job that deletes or renames all the data of a source in the background.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SourceJob {
+    pub id: u32,
+    pub source: String,
+    pub kind: SourceJobKind,
+    pub started_at: DateTime<Utc>,
+    /// `None` while the job is running.
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The error that stopped the job, if any.
+    pub error: Option<String>,
+}
+
+impl SourceJob {
+    #[must_use]
+    pub fn new(id: u32, source: &str, kind: SourceJobKind) -> Self {
+        Self {
+            id,
+            source: source.to_string(),
+            kind,
+            started_at: Utc::now(),
+            finished_at: None,
+            error: None,
+        }
+    }
+
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.finished_at.is_none()
+    }
+
+    /// Returns whether the job works on the given source name, either as the
+    /// source or as its new name.
+    #[must_use]
+    pub fn involves(&self, name: &str) -> bool {
+        self.source == name
+            || matches!(&self.kind, SourceJobKind::Rename { new_name } if new_name == name)
+    }
+}
+
+/// The store of the source jobs.
+pub struct SourceJobStore<'db> {
+    db: &'db DB,
+    cf: &'db ColumnFamily,
+}
+
+impl<'db> SourceJobStore<'db> {
+    /// Creates a running job and returns it with its ID, which is one more
+    /// than that of the last job.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job cannot be stored.
+    pub fn create(&self, source: &str, kind: SourceJobKind) -> Result<SourceJob> {
+        let _lock = SOURCE_JOB_ID_LOCK.lock().expect("not poisoned");
+        let id = match self.db.iterator_cf(self.cf, IteratorMode::End).next() {
+            Some(item) => {
+                let (key, _) = item?;
+                u32::from_be_bytes(key.as_ref().try_into().context("invalid source job ID")?)
+                    .checked_add(1)
+                    .context("no source job ID left")?
+            }
+            None => 0,
+        };
+        let job = SourceJob::new(id, source, kind);
+        self.update(&job)?;
+        Ok(job)
+    }
+
+    /// Stores the job in place of the one with the same ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the job cannot be stored.
+    pub fn update(&self, job: &SourceJob) -> Result<()> {
+        self.db
+            .put_cf(self.cf, job.id.to_be_bytes(), bincode::serialize(job)?)?;
+        Ok(())
+    }
+
+    /// Returns all the jobs in the order of their IDs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the jobs cannot be read.
+    pub fn list(&self) -> Result<Vec<SourceJob>> {
+        self.db
+            .iterator_cf(self.cf, IteratorMode::Start)
+            .map(|item| {
+                let (_, value) = item?;
+                Ok(bincode::deserialize(&value)?)
+            })
+            .collect()
+    }
+}
+
+impl Database {
+    /// Returns the store of the source jobs.
+    pub fn source_job_store(&self) -> Result<SourceJobStore> {
+        let cf = self.get_cf_handle(SOURCE_JOBS_COLUMN_FAMILY_NAME)?;
+        Ok(SourceJobStore { db: &self.db, cf })
+    }
+
+    /// Returns whether all the changes of the job are written, as they are if
+    /// giganto stopped right after the job did.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sources cannot be read.
+    pub fn is_source_job_done(&self, job: &SourceJob) -> Result<bool> {
+        let sources = self.sources_store()?;
+        let exists = |name: &str| -> Result<bool> {
+            Ok(sources.id(name)?.is_some() || sources.contains(name)?)
+        };
+        Ok(match &job.kind {
+            SourceJobKind::Delete => !exists(&job.source)?,
+            SourceJobKind::Rename { new_name } => !exists(&job.source)? && exists(new_name)?,
+        })
+    }
+
+    /// Deletes all the data of the source, including its dead letters and
+    /// quarantined records, and then the source itself along with its
+    /// metadata.
+    ///
+    /// The source should not be connected while it is deleted, since the data
+    /// ingested in the meantime may be left behind.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source does not exist, is under a legal hold,
+    /// or cannot be deleted.
+    pub fn delete_source(&self, name: &str) -> Result<()> {
+        let sources = self.sources_store()?;
+        if sources.id(name)?.is_none() && !sources.contains(name)? {
+            bail!("unknown source: {name}");
+        }
+        if let Some(hold) = self
+            .legal_hold_store()?
+            .list()?
+            .into_iter()
+            .find(|hold| hold.source == name)
+        {
+            bail!("{name} is under legal hold {}", hold.id);
+        }
+
+        if let Some(id) = sources.id(name)? {
+            let mut from = encode_source_id(id);
+            from.push(0x00);
+            let mut to = encode_source_id(id);
+            to.push(0x01);
+
+            let stores = self.retain_period_store()?;
+            for (cf_name, store) in stores.standard_cfs.iter().chain(&stores.non_standard_cfs) {
+                store.delete_range(&from, &to)?;
+                info!("Deleted {cf_name} of {name}");
+            }
+            self.dead_letter_store()?.delete_range(&from, &to)?;
+
+            // The quarantined keys are the original keys after the column
+            // family name.
+            let quarantine = self.get_cf_handle("quarantine")?;
+            for cf_name in RAW_DATA_COLUMN_FAMILY_NAMES
+                .into_iter()
+                .filter(|cf_name| !SOURCELESS_CFS.contains(cf_name))
+            {
+                let mut quarantine_from = cf_name.as_bytes().to_vec();
+                quarantine_from.push(0x00);
+                let mut quarantine_to = quarantine_from.clone();
+                quarantine_from.extend_from_slice(&from);
+                quarantine_to.extend_from_slice(&to);
+                self.db
+                    .delete_range_cf(quarantine, quarantine_from, quarantine_to)?;
+            }
+            self.db.delete_range_cf(sources.kinds, &from, &to)?;
+            let counters = self.get_cf_handle(INGEST_COUNTERS_COLUMN_FAMILY_NAME)?;
+            self.db.delete_range_cf(counters, &from, &to)?;
+        }
+
+        let _lock = SOURCE_ID_LOCK.lock().expect("not poisoned");
+        let mut batch = WriteBatch::default();
+        batch.delete_cf(sources.cf, name);
+        batch.delete_cf(sources.ids, name);
+        self.db.write(batch)?;
+        info!("Source {name} deleted");
+        Ok(())
+    }
+
+    /// Renames the source, so that all its data belong to `new_name`.
+    ///
+    /// The keys refer to the source by its ID, so only the ID, the last active
+    /// time, the dead letters, and the legal holds of the source are rewritten,
+    /// all at once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source does not exist, `new_name` is already
+    /// taken, or the source cannot be renamed.
+    pub fn rename_source(&self, name: &str, new_name: &str) -> Result<()> {
+        if new_name.is_empty() {
+            bail!("the new name must not be empty");
+        }
+        let sources = self.sources_store()?;
+        let _lock = SOURCE_ID_LOCK.lock().expect("not poisoned");
+        if sources.id(new_name)?.is_some() || sources.contains(new_name)? {
+            bail!("source {new_name} already exists");
+        }
+        let id = sources.id(name)?;
+        let last_active = self.db.get_cf(sources.cf, name)?;
+        if id.is_none() && last_active.is_none() {
+            bail!("unknown source: {name}");
+        }
+
+        let mut batch = WriteBatch::default();
+        if let Some(id) = id {
+            batch.delete_cf(sources.ids, name);
+            batch.put_cf(sources.ids, new_name, id.to_be_bytes());
+
+            let dead_letters = self.get_cf_handle("dead letters")?;
+            let mut prefix = encode_source_id(id);
+            prefix.push(0x00);
+            let iter = self.db.iterator_cf_opt(
+                dead_letters,
+                total_order_read_options(),
+                IteratorMode::From(&prefix, Direction::Forward),
+            );
+            for item in iter {
+                let (key, value) = item?;
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+                let mut dead_letter: DeadLetter = bincode::deserialize(&value)?;
+                dead_letter.source = new_name.to_string();
+                batch.put_cf(dead_letters, key, bincode::serialize(&dead_letter)?);
+            }
+        }
+        if let Some(last_active) = last_active {
+            batch.delete_cf(sources.cf, name);
+            batch.put_cf(sources.cf, new_name, last_active);
+        }
+        self.legal_hold_store()?
+            .rename_source(&mut batch, name, new_name)?;
+        self.db.write(batch)?;
+        info!("Source {name} renamed to {new_name}");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{SourceJob, SourceJobKind};
+    use crate::{
+        ingest::DeadLetter,
+        storage::{Database, DbOptions, StorageKey},
+    };
+
+    fn key(db: &Database, source: &str, timestamp: i64) -> Vec<u8> {
+        let source_key = db.conn_store().unwrap().assign_source_key(source).unwrap();
+        StorageKey::builder()
+            .start_key(source_key)
+            .end_key(timestamp)
+            .sequence(0)
+            .build()
+            .key()
+    }
+
+    #[test]
+    fn delete_source() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let sources = db.sources_store().unwrap();
+        let conn = db.conn_store().unwrap();
+        let dns = db.dns_store().unwrap();
+        let counters = db.ingest_counter_store().unwrap();
+        for source in ["src1", "src2"] {
+            sources.insert(source, Utc::now()).unwrap();
+            let id = sources.assign_id(source).unwrap();
+            sources.set_last_event(id, "conn", 1).unwrap();
+            counters.add(id, "conn", Utc::now(), 1, 4).unwrap();
+            conn.append(&key(&db, source, 1), b"conn").unwrap();
+            dns.append(&key(&db, source, 2), b"dns").unwrap();
+        }
+
+        assert!(db.delete_source("src3").is_err());
+        let hold = db
+            .legal_hold_store()
+            .unwrap()
+            .create("src1", Vec::new(), None, None, "case")
+            .unwrap();
+        assert!(db.delete_source("src1").is_err());
+        db.legal_hold_store().unwrap().release(hold.id).unwrap();
+
+        db.delete_source("src1").unwrap();
+        assert_eq!(sources.id("src1").unwrap(), None);
+        assert!(!sources.contains("src1").unwrap());
+        assert_eq!(
+            sources.last_events("src2").unwrap(),
+            vec![("conn".to_string(), 1)]
+        );
+        assert_eq!(counters.counts("src2", 0, i64::MAX).unwrap().len(), 1);
+        assert_eq!(conn.iter_forward().count(), 1);
+        assert_eq!(dns.iter_forward().count(), 1);
+        assert_eq!(
+            &*conn.iter_forward().next().unwrap().unwrap().0,
+            key(&db, "src2", 1).as_slice()
+        );
+    }
+
+    #[test]
+    fn rename_source() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let sources = db.sources_store().unwrap();
+        let conn = db.conn_store().unwrap();
+        sources.insert("src1", Utc::now()).unwrap();
+        sources.insert("src2", Utc::now()).unwrap();
+        let conn_key = key(&db, "src1", 1);
+        conn.append(&conn_key, b"conn").unwrap();
+        let dead_letter = DeadLetter {
+            source: "src1".to_string(),
+            kind: "conn".to_string(),
+            timestamp: 2,
+            error: "invalid".to_string(),
+            raw_event: Vec::new(),
+        };
+        let dead_letter_key = key(&db, "src1", 2);
+        db.dead_letter_store()
+            .unwrap()
+            .append(&dead_letter_key, &bincode::serialize(&dead_letter).unwrap())
+            .unwrap();
+        db.legal_hold_store()
+            .unwrap()
+            .create("src1", Vec::new(), None, None, "case")
+            .unwrap();
+        let id = sources.id("src1").unwrap();
+
+        assert!(db.rename_source("src1", "src2").is_err());
+        assert!(db.rename_source("src3", "src4").is_err());
+        db.rename_source("src1", "new src1").unwrap();
+
+        assert_eq!(sources.id("src1").unwrap(), None);
+        assert!(!sources.contains("src1").unwrap());
+        assert_eq!(sources.id("new src1").unwrap(), id);
+        assert!(sources.contains("new src1").unwrap());
+        assert_eq!(conn.source_key("new src1"), conn_key[..conn_key.len() - 13]);
+        let (_, value) = db
+            .dead_letter_store()
+            .unwrap()
+            .iter_forward()
+            .next()
+            .unwrap()
+            .unwrap();
+        let renamed: DeadLetter = bincode::deserialize(&value).unwrap();
+        assert_eq!(renamed.source, "new src1");
+        let holds = db.legal_hold_store().unwrap().list().unwrap();
+        assert_eq!(holds[0].source, "new src1");
+    }
+
+    #[test]
+    fn deleted_source_id_not_reused() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let sources = db.sources_store().unwrap();
+        assert_eq!(sources.assign_id("src1").unwrap(), 0);
+        assert_eq!(sources.assign_id("src2").unwrap(), 1);
+        db.delete_source("src2").unwrap();
+        assert_eq!(sources.assign_id("src3").unwrap(), 2);
+        assert_eq!(sources.ids().len(), 2);
+    }
+
+    #[test]
+    fn source_job_store() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path(), &DbOptions::default()).unwrap();
+        let store = db.source_job_store().unwrap();
+        let job = store.create("src1", SourceJobKind::Delete).unwrap();
+        assert_eq!(job.id, 0);
+        let mut job = store
+            .create(
+                "src2",
+                SourceJobKind::Rename {
+                    new_name: "src3".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(job.id, 1);
+        job.error = Some("failed".to_string());
+        job.finished_at = Some(Utc::now());
+        store.update(&job).unwrap();
+
+        let jobs = store.list().unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert!(jobs[0].is_running());
+        assert_eq!(jobs[1].error.as_deref(), Some("failed"));
+        assert!(!jobs[1].is_running());
+
+        // The source of the interrupted job still exists, so it is not done.
+        let sources = db.sources_store().unwrap();
+        sources.insert("src1", Utc::now()).unwrap();
+        assert!(!db.is_source_job_done(&jobs[0]).unwrap());
+        db.delete_source("src1").unwrap();
+        assert!(db.is_source_job_done(&jobs[0]).unwrap());
+    }
+
+    #[test]
+    fn job_involves() {
+        let job = SourceJob::new(
+            0,
+            "src1",
+            SourceJobKind::Rename {
+                new_name: "src2".to_string(),
+            },
+        );
+        assert!(job.is_running());
+        assert!(job.involves("src1"));
+        assert!(job.involves("src2"));
+        assert!(!job.involves("src3"));
+    }
+}
@@ -1,49 +1,406 @@
-use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
 
-use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
-use tokio::{sync::Notify, task};
-use tracing::info;
-use warp::{http::Response as HttpResponse, Filter};
+use async_graphql::{
+    http::{playground_source, GraphQLPlaygroundConfig, WebSocketProtocols},
+    Data,
+};
+use async_graphql_warp::{graphql_protocol, GraphQLWebSocket};
+use hyper::{
+    server::conn::Http,
+    service::{service_fn, Service},
+    Body, Request,
+};
+use serde::Serialize;
+use tokio::{net::TcpListener, sync::Notify, task};
+use tokio_rustls::TlsAcceptor;
+use tracing::{error, info, warn};
+use warp::{
+    http::{Response as HttpResponse, StatusCode},
+    Filter,
+};
 
-use crate::graphql::Schema;
+use crate::{
+    auth::Authenticator,
+    graphql::{audit::AuditRecord, PeerRequest, Schema},
+    metrics,
+    peer::{PeerIdents, Peers},
+    server::{subject_from_cert, web_server_tls_config, Certs},
+    storage::{self, Database},
+    PcapSources, StreamDirectChannels,
+};
 
-/// Runs the GraphQL server.
+/// The hostname in the client certificate of a connection.
+#[derive(Clone)]
+struct ClientHostname(String);
+
+/// What `/readyz` checks to tell whether giganto is ready to serve.
+#[derive(Clone)]
+pub struct Readiness {
+    pub data_dir: PathBuf,
+    pub ingest_bound: Arc<AtomicBool>,
+    pub publish_bound: Arc<AtomicBool>,
+    /// `None` in standalone mode.
+    pub peer_bound: Option<Arc<AtomicBool>>,
+    pub retention_running: Arc<Mutex<bool>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadinessReport {
+    ready: bool,
+    database_open: bool,
+    migrated: bool,
+    ingest_bound: bool,
+    publish_bound: bool,
+    peer_bound: Option<bool>,
+    retention_idle: bool,
+}
+
+impl Readiness {
+    fn report(&self, db: &Database) -> ReadinessReport {
+        let database_open = db.is_responsive();
+        let migrated = storage::is_migrated(&self.data_dir);
+        let ingest_bound = self.ingest_bound.load(Ordering::SeqCst);
+        let publish_bound = self.publish_bound.load(Ordering::SeqCst);
+        let peer_bound = self
+            .peer_bound
+            .as_ref()
+            .map(|bound| bound.load(Ordering::SeqCst));
+        let retention_idle = !*self.retention_running.lock().expect("not poisoned");
+        ReadinessReport {
+            ready: database_open
+                && migrated
+                && ingest_bound
+                && publish_bound
+                && peer_bound.unwrap_or(true)
+                && retention_idle,
+            database_open,
+            migrated,
+            ingest_bound,
+            publish_bound,
+            peer_bound,
+            retention_idle,
+        }
+    }
+}
+
+/// Runs the GraphQL server, which also serves the metrics at `/metrics` and
+/// the liveness and readiness probes at `/healthz` and `/readyz`.
 ///
-/// Note that `key` is not compatible with the DER-encoded key extracted by
-/// rustls-pemfile.
-#[allow(clippy::unused_async)]
+/// The subscriptions are served over websockets at `/graphql/ws`.
+///
+/// If `authenticator` is given, a GraphQL request without a valid bearer token
+/// is rejected with `401 Unauthorized`, and so is a websocket connection
+/// without one in the upgrade request or the `connection_init` payload. Every
+/// other GraphQL request is recorded in the audit trail.
+///
+/// The clients presenting certificates, such as the peers, are verified with
+/// the root certificates in `certs`. The `requestFromPeer` argument of a
+/// request is honored only if the client certificate matches the hostname of
+/// one of the peers in `peer_idents`.
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
 pub async fn serve(
     schema: Schema,
+    database: Database,
+    pcap_sources: PcapSources,
+    stream_direct_channels: StreamDirectChannels,
+    peers: Peers,
+    peer_idents: PeerIdents,
+    readiness: Readiness,
+    authenticator: Option<Arc<Authenticator>>,
     addr: SocketAddr,
-    cert: Vec<u8>,
-    key: Vec<u8>,
+    certs: Arc<Certs>,
     notify_shutdown: Arc<Notify>,
 ) {
-    let filter = async_graphql_warp::graphql(schema).and_then(
-        |(schema, request): (Schema, async_graphql::Request)| async move {
-            let resp = schema.execute(request).await;
+    let subscription_schema = schema.clone();
+    let subscription_authenticator = authenticator.clone();
+    let db = database.clone();
+    let filter = async_graphql_warp::graphql(schema)
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::ext::optional::<ClientHostname>())
+        .and_then(
+            move |(schema, mut request): (Schema, async_graphql::Request),
+                  authorization: Option<String>,
+                  client_hostname: Option<ClientHostname>| {
+                let authenticator = authenticator.clone();
+                let db = db.clone();
+                let peer_idents = peer_idents.clone();
+                async move {
+                    let caller = match authenticator
+                        .map(|authenticator| authenticator.authenticate(authorization.as_deref()))
+                        .transpose()
+                    {
+                        Ok(caller) => caller,
+                        Err(e) => {
+                            warn!("Unauthenticated GraphQL request: {e:#}");
+                            let body = serde_json::json!({
+                                "errors": [{ "message": format!("{e:#}") }]
+                            });
+                            let resp: Box<dyn warp::Reply> = Box::new(warp::reply::with_status(
+                                warp::reply::json(&body),
+                                StatusCode::UNAUTHORIZED,
+                            ));
+                            return Ok::<_, Infallible>(resp);
+                        }
+                    };
+                    let audit = AuditRecord::new(&request, caller.as_ref());
+                    if let Some(caller) = caller {
+                        request = request.data(caller);
+                    }
+                    if let Some(ClientHostname(hostname)) = client_hostname {
+                        if is_peer(&peer_idents, &hostname).await {
+                            request = request.data(PeerRequest);
+                        }
+                    }
+                    let start = Instant::now();
+                    let resp = schema.execute(request).await;
+                    // A request with a field not in the schema fails before it
+                    // is executed, with an error without a path.
+                    let rejected = resp.errors.iter().any(|error| error.path.is_empty());
+                    let fields: &[String] = if rejected { &[] } else { audit.fields() };
+                    metrics::observe_graphql(fields, start.elapsed());
+                    audit.finish(&db, &resp);
 
-            Ok::<_, Infallible>(async_graphql_warp::GraphQLResponse::from(resp))
-        },
-    );
+                    let resp: Box<dyn warp::Reply> =
+                        Box::new(async_graphql_warp::GraphQLResponse::from(resp));
+                    Ok(resp)
+                }
+            },
+        );
+
+    let route_subscription = warp::path!("graphql" / "ws")
+        .and(warp::ws())
+        .and(graphql_protocol())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(
+            move |ws: warp::ws::Ws, protocol: WebSocketProtocols, authorization: Option<String>| {
+                let schema = subscription_schema.clone();
+                let authenticator = subscription_authenticator.clone();
+                let reply = ws.on_upgrade(move |socket| {
+                    GraphQLWebSocket::new(socket, schema, protocol)
+                        .on_connection_init(move |payload| async move {
+                            connection_data(authenticator.as_deref(), authorization, &payload)
+                        })
+                        .serve()
+                });
+                warp::reply::with_header(
+                    reply,
+                    "Sec-WebSocket-Protocol",
+                    protocol.sec_websocket_protocol(),
+                )
+            },
+        );
 
     let graphql_playground = warp::path!("graphql" / "playground").map(|| {
         HttpResponse::builder()
             .header("content-type", "text/html")
-            .body(playground_source(GraphQLPlaygroundConfig::new("/graphql")))
+            .body(playground_source(
+                GraphQLPlaygroundConfig::new("/graphql").subscription_endpoint("/graphql/ws"),
+            ))
+    });
+
+    let route_healthz =
+        warp::path!("healthz").map(|| warp::reply::json(&serde_json::json!({ "status": "ok" })));
+
+    let db = database.clone();
+    let route_readyz = warp::path!("readyz").map(move || {
+        let report = readiness.report(&db);
+        let status = if report.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        warp::reply::with_status(warp::reply::json(&report), status)
+    });
+
+    let route_metrics = warp::path!("metrics").and_then(move || {
+        let database = database.clone();
+        let pcap_sources = pcap_sources.clone();
+        let stream_direct_channels = stream_direct_channels.clone();
+        let peers = peers.clone();
+        async move {
+            let metrics =
+                metrics::render(&database, &pcap_sources, &stream_direct_channels, &peers).await;
+            let resp = match metrics {
+                Ok(body) => HttpResponse::builder()
+                    .header("content-type", prometheus::TEXT_FORMAT)
+                    .body(body),
+                Err(e) => {
+                    error!("Failed to render the metrics: {e}");
+                    HttpResponse::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(e.to_string())
+                }
+            };
+            Ok::<_, Infallible>(resp)
+        }
     });
 
     let route_graphql = warp::path("graphql").and(warp::any()).and(filter);
     let route_home = warp::path::end().map(|| "");
 
-    let routes = graphql_playground.or(warp::any().and(route_graphql.or(route_home)));
-    let (_, server) = warp::serve(routes)
-        .tls()
-        .cert(cert)
-        .key(key)
-        .bind_with_graceful_shutdown(addr, async move { notify_shutdown.notified().await });
+    let routes = graphql_playground
+        .or(route_subscription)
+        .or(route_healthz)
+        .or(route_readyz)
+        .or(route_metrics)
+        .or(warp::any().and(route_graphql.or(route_home)));
+    let service = warp::service(routes);
+
+    let tls_config = match web_server_tls_config(&certs) {
+        Ok(tls_config) => tls_config,
+        Err(e) => {
+            error!("Invalid TLS configuration of the GraphQL server: {e:#}");
+            return;
+        }
+    };
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to bind the GraphQL server to {addr}: {e}");
+            return;
+        }
+    };
 
     // start Graphql Server
     info!("listening on https://{addr:?}");
-    task::spawn(server);
+    let shutdown = notify_shutdown.notified();
+    tokio::pin!(shutdown);
+    loop {
+        let stream = tokio::select! {
+            () = &mut shutdown => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept a GraphQL connection: {e}");
+                    continue;
+                }
+            },
+        };
+        let acceptor = acceptor.clone();
+        let service = service.clone();
+        task::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("TLS handshake of a GraphQL connection failed: {e}");
+                    return;
+                }
+            };
+            // The certificate has been verified in the handshake, so only its
+            // hostname is kept to tell whether the client is a peer.
+            let client_hostname = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| subject_from_cert(certs).ok())
+                .map(|(_, hostname)| ClientHostname(hostname));
+            let service = service_fn(move |mut req: Request<Body>| {
+                if let Some(client_hostname) = &client_hostname {
+                    req.extensions_mut().insert(client_hostname.clone());
+                }
+                service.clone().call(req)
+            });
+            if let Err(e) = Http::new()
+                .serve_connection(stream, service)
+                .with_upgrades()
+                .await
+            {
+                warn!("GraphQL connection error: {e}");
+            }
+        });
+    }
+}
+
+/// Returns whether `hostname` is the hostname of one of the peers.
+async fn is_peer(peer_idents: &PeerIdents, hostname: &str) -> bool {
+    peer_idents
+        .read()
+        .await
+        .iter()
+        .any(|ident| ident.hostname == hostname)
+}
+
+/// Returns the data of a websocket connection, which holds the caller if the
+/// requests are authenticated.
+///
+/// The bearer token is taken from the `Authorization` header of the upgrade
+/// request, or from the `Authorization` field of the `connection_init`
+/// payload, since browsers cannot set the headers of a websocket.
+fn connection_data(
+    authenticator: Option<&Authenticator>,
+    authorization: Option<String>,
+    payload: &serde_json::Value,
+) -> async_graphql::Result<Data> {
+    let mut data = Data::default();
+    let Some(authenticator) = authenticator else {
+        return Ok(data);
+    };
+    let authorization = authorization.or_else(|| {
+        payload
+            .get("Authorization")
+            .or_else(|| payload.get("authorization"))
+            .and_then(serde_json::Value::as_str)
+            .map(ToString::to_string)
+    });
+    match authenticator.authenticate(authorization.as_deref()) {
+        Ok(caller) => {
+            data.insert(caller);
+            Ok(data)
+        }
+        Err(e) => {
+            warn!("Unauthenticated GraphQL subscription: {e:#}");
+            Err(format!("{e:#}").into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicBool, Arc, Mutex};
+
+    use super::Readiness;
+    use crate::storage::{migrate_data_dir, Database, DbOptions};
+
+    #[test]
+    fn readiness() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(&data_dir.path().join("db"), &DbOptions::default()).unwrap();
+        migrate_data_dir(data_dir.path(), &db).unwrap();
+        let readiness = Readiness {
+            data_dir: data_dir.path().to_path_buf(),
+            ingest_bound: Arc::new(AtomicBool::new(true)),
+            publish_bound: Arc::new(AtomicBool::new(true)),
+            peer_bound: None,
+            retention_running: Arc::new(Mutex::new(false)),
+        };
+        let report = readiness.report(&db);
+        assert!(report.ready);
+        assert_eq!(report.peer_bound, None);
+
+        *readiness.retention_running.lock().unwrap() = true;
+        let readiness = Readiness {
+            peer_bound: Some(Arc::new(AtomicBool::new(false))),
+            ..readiness
+        };
+        let report = readiness.report(&db);
+        assert!(!report.ready);
+        assert!(!report.retention_idle);
+        assert_eq!(report.peer_bound, Some(false));
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            "{\"ready\":false,\"database_open\":true,\"migrated\":true,\
+            \"ingest_bound\":true,\"publish_bound\":true,\"peer_bound\":false,\
+            \"retention_idle\":false}"
+        );
+    }
 }